@@ -1,8 +1,9 @@
 use anyhow::Result;
 use lightsql::{
-    buffer::{BufferPoolManager, ClockSweepBufferPool},
+    buffer::{BufferPoolManager, BufferRing, ClockSweepBufferPool},
     disk::{DiskManager, PageId},
     table::SimpleTable,
+    transaction::Transaction,
 };
 use md5::Md5;
 use sha1::{Digest, Sha1};
@@ -19,27 +20,34 @@ use sha1::{Digest, Sha1};
 // |BE i32|md5(id)   |sha1(id) |
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.lsql")?;
-    let pool = ClockSweepBufferPool::from(1_000_000);
-    let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let pool = ClockSweepBufferPool::from(0);
+    let bufmgr = BufferPoolManager::with_memory_budget(disk, pool, 4 << 30);
 
     let mut table = SimpleTable {
         meta_page_id: PageId::new(0),
         key_elems_count: 1,
     };
-    table.create(&mut bufmgr)?;
+    let mut txn = Transaction::begin(&bufmgr)?;
+    table.create(&bufmgr, &mut txn)?;
+    bufmgr.set_catalog_page_id(Some(table.meta_page_id))?;
     dbg!(&table);
-    table.insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])?;
-    table.insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])?;
-    table.insert(&mut bufmgr, &[b"y", b"Charlie", b"Williams"])?;
-    table.insert(&mut bufmgr, &[b"w", b"Dave", b"Miller"])?;
-    table.insert(&mut bufmgr, &[b"v", b"Eve", b"Brown"])?;
+    table.insert(&bufmgr, &mut txn, &[b"z", b"Alice", b"Smith"])?;
+    table.insert(&bufmgr, &mut txn, &[b"x", b"Bob", b"Johnson"])?;
+    table.insert(&bufmgr, &mut txn, &[b"y", b"Charlie", b"Williams"])?;
+    table.insert(&bufmgr, &mut txn, &[b"w", b"Dave", b"Miller"])?;
+    table.insert(&bufmgr, &mut txn, &[b"v", b"Eve", b"Brown"])?;
+    txn.commit()?;
+    let ring = BufferRing::default();
+    let bulk = bufmgr.with_ring(&ring);
     for i in 1u32..=10_000_000u32 {
         dbg!(i);
         let pkey = i.to_be_bytes();
-        let md5 = Md5::digest(&pkey);
-        let sha1 = Sha1::digest(&pkey);
-        table.insert(&mut bufmgr, &[&pkey[..], &md5[..], &sha1[..]])?;
+        let md5 = Md5::digest(pkey);
+        let sha1 = Sha1::digest(pkey);
+        let mut txn = Transaction::begin(&bufmgr)?;
+        table.insert(&bulk, &mut txn, &[&pkey[..], &md5[..], &sha1[..]])?;
+        txn.commit()?;
     }
-    bufmgr.flush()?;
+    bufmgr.flush_all()?;
     Ok(())
 }
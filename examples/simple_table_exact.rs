@@ -1,22 +1,27 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use lightsql::{
     btree::{BTree, SearchMode},
     buffer::{BufferPoolManager, ClockSweepBufferPool},
-    disk::{DiskManager, PageId},
+    disk::DiskManager,
+    transaction::Snapshot,
     tuple,
 };
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.odb")?;
     let pool = ClockSweepBufferPool::from(10);
-    let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let bufmgr = BufferPoolManager::new(disk, pool);
+    let table_meta_page_id = bufmgr
+        .catalog_page_id()
+        .context("simple table has not been created")?;
 
-    let btree = BTree::new(PageId::new(0));
+    let btree = BTree::new(table_meta_page_id);
     let mut search_key = vec![];
     tuple::encode([b"y"].iter(), &mut search_key);
-    let mut iter = btree.search(&mut bufmgr, SearchMode::Key(search_key))?;
+    let snapshot = Snapshot::take(&bufmgr);
+    let mut iter = btree.search(&bufmgr, SearchMode::Key(search_key))?;
 
-    while let Some((key, value)) = iter.next(&mut bufmgr)? {
+    while let Some((key, value)) = iter.next(&bufmgr, &snapshot)? {
         let mut record = vec![];
         tuple::decode(&key, &mut record);
         if record[0] != b"y" {
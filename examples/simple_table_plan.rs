@@ -1,27 +1,34 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use lightsql::{
     buffer::{BufferPoolManager, ClockSweepBufferPool},
-    disk::{DiskManager, PageId},
+    disk::DiskManager,
     query::{Filter, PlanNode, SeqScan, TupleSearchMode},
+    transaction::Snapshot,
     tuple,
 };
+use std::sync::Arc;
 
 fn main() -> Result<()> {
     let disk = DiskManager::open("simple.odb")?;
     let pool = ClockSweepBufferPool::from(10);
-    let mut bufmgr = BufferPoolManager::new(disk, pool);
+    let bufmgr = Arc::new(BufferPoolManager::new(disk, pool));
+    bufmgr.start_read_ahead(2);
+    let table_meta_page_id = bufmgr
+        .catalog_page_id()
+        .context("simple table has not been created")?;
 
     let plan = Filter {
         cond: &|record| record[0].as_slice() == b"y",
         inner_plan: &SeqScan {
-            table_meta_page_id: PageId::new(0),
+            table_meta_page_id,
             search_mode: TupleSearchMode::Key(&[b"y"]),
             while_cond: &|_| true,
         },
     };
-    let mut exec = plan.start(&mut bufmgr)?;
+    let snapshot = Snapshot::take(&bufmgr);
+    let mut exec = plan.start(&bufmgr, &snapshot)?;
 
-    while let Some(record) = exec.next(&mut bufmgr)? {
+    while let Some(record) = exec.next(&bufmgr)? {
         println!("{:?}", tuple::Pretty(&record));
     }
     Ok(())
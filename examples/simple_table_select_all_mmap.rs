@@ -0,0 +1,26 @@
+use anyhow::{Context, Result};
+use lightsql::{
+    btree::{BTree, SearchMode},
+    disk::MmapFile,
+    transaction::Snapshot,
+    tuple,
+};
+
+fn main() -> Result<()> {
+    let mmap_file = MmapFile::open("simple.lsql")?;
+    let table_meta_page_id = mmap_file
+        .catalog_page_id()
+        .context("simple table has not been created")?;
+
+    let btree = BTree::new(table_meta_page_id);
+    let snapshot = Snapshot::of_mmap_file(&mmap_file);
+    let mut iter = btree.search(&mmap_file, SearchMode::Start)?;
+
+    while let Some((key, value)) = iter.next(&mmap_file, &snapshot)? {
+        let mut record = vec![];
+        tuple::decode(&key, &mut record);
+        tuple::decode(&value, &mut record);
+        println!("{:?}", tuple::Pretty(&record));
+    }
+    Ok(())
+}
@@ -1,22 +1,27 @@
 use crate::{
-    buffer::{self, Buffer, BufferPoolManager},
-    disk::PageId,
+    buffer::{self, BufferPoolManager, NextPageId, PageGuard, WithRing, WritePageGuard},
+    disk::{self, MappedPage, MmapFile, PageId},
+    transaction::{self, Snapshot, Transaction},
 };
 use bincode::Options;
+use log::Change;
 use serde::{Deserialize, Serialize};
 use std::{
-    cell::{Ref, RefMut},
     convert::identity,
-    rc::Rc,
+    mem::size_of,
+    ops::{Bound, Deref},
 };
 use thiserror::Error;
 use zerocopy::ByteSlice;
 
 mod branch;
 mod leaf;
+pub(crate) mod log;
 mod meta;
 mod node;
 
+pub type Item = (Vec<u8>, Vec<u8>);
+
 #[derive(Serialize, Deserialize)]
 pub struct Pair<'a> {
     pub key: &'a [u8],
@@ -37,8 +42,102 @@ impl<'a> Pair<'a> {
 pub enum Error {
     #[error("duplicate key")]
     DuplicateKey,
+    #[error("key not found")]
+    KeyNotFound,
+    #[error("the pair takes {0} bytes, more than a page of the tree can hold.")]
+    PairTooLarge(usize),
     #[error(transparent)]
     Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Disk(#[from] disk::Error),
+    #[error(transparent)]
+    Transaction(#[from] transaction::Error),
+}
+
+/// Where the read paths of a `BTree` get their pages from: the buffer pool,
+/// or a read-only mapping of the database file.
+pub trait PageSource {
+    type Page: NodePage;
+
+    fn fetch_page(&self, page_id: PageId) -> Result<Self::Page, Error>;
+
+    /// Hints that `count` pages will be read soon, starting at `page_id`.
+    fn prefetch(&self, _page_id: PageId, _count: usize, _next_page_id: NextPageId) {}
+}
+
+/// Where the write paths of a `BTree` get and create their pages.
+pub trait WritablePageSource: PageSource<Page = PageGuard> {
+    fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard, Error>;
+
+    fn create_page(&self) -> Result<WritePageGuard, Error>;
+}
+
+pub trait NodePage {
+    fn body(&self) -> impl Deref<Target = [u8]> + '_;
+}
+
+impl PageSource for BufferPoolManager {
+    type Page = PageGuard;
+
+    fn fetch_page(&self, page_id: PageId) -> Result<PageGuard, Error> {
+        Ok(BufferPoolManager::fetch_page(self, page_id)?)
+    }
+
+    fn prefetch(&self, page_id: PageId, count: usize, next_page_id: NextPageId) {
+        BufferPoolManager::prefetch(self, page_id, count, next_page_id)
+    }
+}
+
+impl WritablePageSource for BufferPoolManager {
+    fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard, Error> {
+        Ok(BufferPoolManager::fetch_page_write(self, page_id)?)
+    }
+
+    fn create_page(&self) -> Result<WritePageGuard, Error> {
+        Ok(BufferPoolManager::create_page(self)?)
+    }
+}
+
+impl PageSource for WithRing<'_> {
+    type Page = PageGuard;
+
+    fn fetch_page(&self, page_id: PageId) -> Result<PageGuard, Error> {
+        Ok(WithRing::fetch_page(self, page_id)?)
+    }
+
+    fn prefetch(&self, page_id: PageId, count: usize, next_page_id: NextPageId) {
+        WithRing::prefetch(self, page_id, count, next_page_id)
+    }
+}
+
+impl WritablePageSource for WithRing<'_> {
+    fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard, Error> {
+        Ok(WithRing::fetch_page_write(self, page_id)?)
+    }
+
+    fn create_page(&self) -> Result<WritePageGuard, Error> {
+        Ok(WithRing::create_page(self)?)
+    }
+}
+
+impl NodePage for PageGuard {
+    fn body(&self) -> impl Deref<Target = [u8]> + '_ {
+        self.read()
+    }
+}
+
+impl PageSource for MmapFile {
+    type Page = MappedPage;
+
+    fn fetch_page(&self, page_id: PageId) -> Result<MappedPage, Error> {
+        Ok(self.read_page(page_id)?)
+    }
+}
+
+impl NodePage for MappedPage {
+    fn body(&self) -> impl Deref<Target = [u8]> + '_ {
+        MappedPage::body(self)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -55,10 +154,11 @@ impl SearchMode {
         }
     }
 
-    fn tuple_slot_id(&self, leaf: &leaf::Leaf<impl ByteSlice>) -> Result<usize, usize> {
+    /// Keys a search in this mode starts from.
+    fn into_bound(self) -> Bound<Vec<u8>> {
         match self {
-            SearchMode::Start => Err(0),
-            SearchMode::Key(key) => leaf.search_slot_id(key),
+            SearchMode::Start => Bound::Unbounded,
+            SearchMode::Key(key) => Bound::Included(key),
         }
     }
 }
@@ -68,135 +168,187 @@ pub struct BTree {
 }
 
 impl BTree {
-    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
-        let meta_buffer = bufmgr.create_page()?;
-        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
-        let root_buffer = bufmgr.create_page()?;
-        let mut root = node::Node::new(root_buffer.page.borrow_mut() as RefMut<[_]>);
+    /// Creates a tree that is locked by `txn` and goes away if it rolls back.
+    pub fn create<S: WritablePageSource>(bufmgr: &S, txn: &mut Transaction) -> Result<Self, Error> {
+        let mut meta_guard = bufmgr.create_page()?;
+        txn.lock(meta_guard.page_id())?;
+        let mut meta = meta::Meta::new(&mut *meta_guard);
+        let mut root_guard = bufmgr.create_page()?;
+        let mut root = node::Node::new(&mut *root_guard);
         root.initialize_as_leaf();
         let mut leaf = leaf::Leaf::new(root.body);
         leaf.initialize();
-        meta.header.root_page_id = root_buffer.page_id;
-        Ok(Self::new(meta_buffer.page_id))
+        meta.header.root_page_id = root_guard.page_id();
+        let body = root_guard.to_vec();
+        txn.log(&mut root_guard, Change::Format { body });
+        let body = meta_guard.to_vec();
+        txn.log(&mut meta_guard, Change::Format { body });
+        Ok(Self::new(meta_guard.page_id()))
     }
 
     pub fn new(meta_page_id: PageId) -> Self {
         Self { meta_page_id }
     }
 
-    fn fetch_root_page(&self, bufmgr: &mut BufferPoolManager) -> Result<Rc<Buffer>, Error> {
-        let root_page_id = {
-            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
-            let meta = meta::Meta::new(meta_buffer.page.borrow() as Ref<[_]>);
-            meta.header.root_page_id
-        };
-        Ok(bufmgr.fetch_page(root_page_id)?)
-    }
-
-    fn search_internal(
+    fn search_internal<S: PageSource>(
         &self,
-        bufmgr: &mut BufferPoolManager,
-        node_buffer: Rc<Buffer>,
+        source: &S,
+        node_page_id: PageId,
+        node_page: S::Page,
         search_mode: SearchMode,
-    ) -> Result<Iter, Error> {
-        let node = node::Node::new(node_buffer.page.borrow() as Ref<[_]>);
-        match node::Body::new(node.header.node_type, &*node.body) {
+    ) -> Result<Iter<S::Page>, Error> {
+        let node_body = node_page.body();
+        let node = node::Node::new(&*node_body);
+        match node::Body::new(node.header.node_type, node.body) {
             node::Body::Leaf(leaf) => {
-                let slot_id = search_mode.tuple_slot_id(&leaf).unwrap_or_else(identity);
-                let is_right_most = leaf.pair_count() == slot_id;
-                drop(node);
-
-                let mut iter = Iter {
-                    buffer: node_buffer,
-                    slot_id,
-                };
-                if is_right_most {
-                    iter.advance(bufmgr)?;
-                }
-                Ok(iter)
+                let prev_page_id = leaf.prev_page_id();
+                drop(node_body);
+                Ok(Iter {
+                    meta_page_id: self.meta_page_id,
+                    page_id: node_page_id,
+                    page: node_page,
+                    prev_page_id,
+                    from: search_mode.into_bound(),
+                    leaves_until_read_ahead: 0,
+                })
             }
             node::Body::Branch(branch) => {
                 let child_page_id = search_mode.child_page_id(&branch);
-                drop(node);
-                drop(node_buffer);
-                let child_node_page = bufmgr.fetch_page(child_page_id)?;
-                self.search_internal(bufmgr, child_node_page, search_mode)
+                drop(node_body);
+                drop(node_page);
+                let child_node_page = source.fetch_page(child_page_id)?;
+                self.search_internal(source, child_page_id, child_node_page, search_mode)
             }
         }
     }
 
-    pub fn search(
+    pub fn search<S: PageSource>(
         &self,
-        bufmgr: &mut BufferPoolManager,
+        source: &S,
         search_mode: SearchMode,
-    ) -> Result<Iter, Error> {
-        let root_page = self.fetch_root_page(bufmgr)?;
-        self.search_internal(bufmgr, root_page, search_mode)
+    ) -> Result<Iter<S::Page>, Error> {
+        // Inserts keep the meta page latched until they are done, so holding
+        // it on the way down keeps nodes from being split under the search.
+        let meta_page = source.fetch_page(self.meta_page_id)?;
+        let meta_body = meta_page.body();
+        let meta = meta::Meta::new(&*meta_body);
+        let root_page_id = meta.header.root_page_id;
+        let root_page = source.fetch_page(root_page_id)?;
+        self.search_internal(source, root_page_id, root_page, search_mode)
     }
 
-    fn insert_internal(
+    fn insert_internal<S: WritablePageSource>(
         &self,
-        bufmgr: &mut BufferPoolManager,
-        buffer: Rc<Buffer>,
+        bufmgr: &S,
+        txn: &mut Transaction,
+        mut guard: WritePageGuard,
         key: &[u8],
         value: &[u8],
     ) -> Result<Option<(Vec<u8>, PageId)>, Error> {
-        let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+        // The node is only dereferenced mutably once it is certain to change,
+        // so that a failed insert does not leave the page dirty.
+        let node = node::Node::new(&*guard);
         match node::Body::new(node.header.node_type, node.body) {
-            node::Body::Leaf(mut leaf) => {
-                let slot_id = match leaf.search_slot_id(key) {
+            node::Body::Leaf(leaf) => {
+                // A deleted pair gets the value as its latest version,
+                // keeping the older ones the snapshots that do not see the
+                // delete may still read.
+                let (slot_id, slot, old_slot) = match leaf.search_slot_id(key) {
+                    Ok(slot_id) if leaf.stamp_at(slot_id).is_deleted() => {
+                        let slot = leaf.renewed_slot(slot_id, value, txn.xid(), txn.horizon());
+                        (slot_id, slot, Some(leaf.slot_at(slot_id).to_vec()))
+                    }
                     Ok(_) => return Err(Error::DuplicateKey),
-                    Err(slot_id) => slot_id,
+                    Err(slot_id) => (slot_id, leaf::new_slot(key, value, txn.xid()), None),
                 };
-                if leaf.insert(slot_id, key, value).is_some() {
-                    buffer.is_dirty.set(true);
+                if slot.len() > leaf.max_pair_size() {
+                    return Err(Error::PairTooLarge(slot.len()));
+                }
+                let page_id = guard.page_id();
+                let mut leaf = leaf::Leaf::new(node::Node::new(&mut *guard).body);
+                let fits = match &old_slot {
+                    Some(_) => leaf.replace(slot_id, &slot),
+                    None => leaf.insert_slot(slot_id, &slot),
+                };
+                if fits.is_some() {
+                    let change = match old_slot {
+                        Some(old) => Change::ReplaceLeaf {
+                            slot_id,
+                            old,
+                            new: slot,
+                        },
+                        None => Change::InsertLeaf {
+                            slot_id,
+                            key: key.to_vec(),
+                            value: value.to_vec(),
+                            xmin: txn.xid(),
+                        },
+                    };
+                    txn.log(&mut guard, change);
                     Ok(None)
                 } else {
+                    let before = guard.to_vec();
+                    let mut leaf = leaf::Leaf::new(node::Node::new(&mut *guard).body);
+                    if old_slot.is_some() {
+                        leaf.remove(slot_id);
+                    }
                     let prev_leaf_page_id = leaf.prev_page_id();
-                    let prev_leaf_buffer = prev_leaf_page_id
-                        .map(|next_leaf_page_id| bufmgr.fetch_page(next_leaf_page_id))
+                    let prev_leaf_guard = prev_leaf_page_id
+                        .map(|prev_leaf_page_id| bufmgr.fetch_page_write(prev_leaf_page_id))
                         .transpose()?;
 
-                    let new_leaf_buffer = bufmgr.create_page()?;
+                    let mut new_leaf_guard = bufmgr.create_page()?;
+                    let new_leaf_page_id = new_leaf_guard.page_id();
 
-                    if let Some(prev_leaf_buffer) = prev_leaf_buffer {
-                        let node =
-                            node::Node::new(prev_leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+                    if let Some(mut prev_leaf_guard) = prev_leaf_guard {
+                        let node = node::Node::new(&mut *prev_leaf_guard);
                         let mut prev_leaf = leaf::Leaf::new(node.body);
-                        prev_leaf.set_next_page_id(Some(new_leaf_buffer.page_id));
-                        prev_leaf_buffer.is_dirty.set(true);
+                        prev_leaf.set_next_page_id(Some(new_leaf_page_id));
+                        let change = Change::SetNextLeaf {
+                            old: Some(page_id),
+                            new: Some(new_leaf_page_id),
+                        };
+                        txn.log(&mut prev_leaf_guard, change);
                     }
-                    leaf.set_prev_page_id(Some(new_leaf_buffer.page_id));
+                    leaf.set_prev_page_id(Some(new_leaf_page_id));
 
-                    let mut new_leaf_node =
-                        node::Node::new(new_leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+                    let mut new_leaf_node = node::Node::new(&mut *new_leaf_guard);
                     new_leaf_node.initialize_as_leaf();
                     let mut new_leaf = leaf::Leaf::new(new_leaf_node.body);
                     new_leaf.initialize();
-                    let overflow_key = leaf.split_insert(&mut new_leaf, key, value);
-                    new_leaf.set_next_page_id(Some(buffer.page_id));
+                    let overflow_key = leaf.split_insert(&mut new_leaf, key, &slot);
+                    new_leaf.set_next_page_id(Some(page_id));
                     new_leaf.set_prev_page_id(prev_leaf_page_id);
-                    buffer.is_dirty.set(true);
-                    Ok(Some((overflow_key, new_leaf_buffer.page_id)))
+                    let body = new_leaf_guard.to_vec();
+                    txn.log(&mut new_leaf_guard, Change::Format { body });
+                    let after = guard.to_vec();
+                    txn.log(&mut guard, Change::Split { before, after });
+                    Ok(Some((overflow_key, new_leaf_page_id)))
                 }
             }
-            node::Body::Branch(mut branch) => {
+            node::Body::Branch(branch) => {
                 let child_idx = branch.search_child_idx(key);
                 let child_page_id = branch.child_at(child_idx);
-                let child_node_buffer = bufmgr.fetch_page(child_page_id)?;
+                let child_guard = bufmgr.fetch_page_write(child_page_id)?;
                 if let Some((overflow_key_from_child, overflow_child_page_id)) =
-                    self.insert_internal(bufmgr, child_node_buffer, key, value)?
+                    self.insert_internal(bufmgr, txn, child_guard, key, value)?
                 {
+                    let before = guard.to_vec();
+                    let mut branch = branch::Branch::new(node::Node::new(&mut *guard).body);
                     if branch
                         .insert(child_idx, &overflow_key_from_child, overflow_child_page_id)
                         .is_some()
                     {
-                        buffer.is_dirty.set(true);
+                        let change = Change::InsertBranch {
+                            slot_id: child_idx,
+                            key: overflow_key_from_child,
+                            child_page_id: overflow_child_page_id,
+                        };
+                        txn.log(&mut guard, change);
                         Ok(None)
                     } else {
-                        let new_branch_buffer = bufmgr.create_page()?;
-                        let mut new_branch_node =
-                            node::Node::new(new_branch_buffer.page.borrow_mut() as RefMut<[_]>);
+                        let mut new_branch_guard = bufmgr.create_page()?;
+                        let mut new_branch_node = node::Node::new(&mut *new_branch_guard);
                         new_branch_node.initialize_as_branch();
                         let mut new_branch = branch::Branch::new(new_branch_node.body);
                         let overflow_key = branch.split_insert(
@@ -204,9 +356,11 @@ impl BTree {
                             &overflow_key_from_child,
                             overflow_child_page_id,
                         );
-                        buffer.is_dirty.set(true);
-                        new_branch_buffer.is_dirty.set(true);
-                        Ok(Some((overflow_key, new_branch_buffer.page_id)))
+                        let body = new_branch_guard.to_vec();
+                        txn.log(&mut new_branch_guard, Change::Format { body });
+                        let after = guard.to_vec();
+                        txn.log(&mut guard, Change::Split { before, after });
+                        Ok(Some((overflow_key, new_branch_guard.page_id())))
                     }
                 } else {
                     Ok(None)
@@ -215,69 +369,760 @@ impl BTree {
         }
     }
 
-    pub fn insert(
+    /// Inserts the pair as part of `txn`, locking the tree for it. If the
+    /// insert fails, whatever it changed is undone and `txn` goes on. The
+    /// key may be that of a deleted pair, whose versions stay for the
+    /// snapshots that do not see the delete.
+    pub fn insert<S: WritablePageSource>(
         &self,
-        bufmgr: &mut BufferPoolManager,
+        bufmgr: &S,
+        txn: &mut Transaction,
         key: &[u8],
         value: &[u8],
     ) -> Result<(), Error> {
-        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
-        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
-        let root_page_id = meta.header.root_page_id;
-        let root_buffer = bufmgr.fetch_page(root_page_id)?;
-        if let Some((key, child_page_id)) = self.insert_internal(bufmgr, root_buffer, key, value)? {
-            let new_root_buffer = bufmgr.create_page()?;
-            let mut node = node::Node::new(new_root_buffer.page.borrow_mut() as RefMut<[_]>);
+        txn.lock(self.meta_page_id)?;
+        let savepoint = txn.savepoint();
+        let result = self.insert_locked(bufmgr, txn, key, value);
+        if result.is_err() {
+            txn.roll_back_to(savepoint)?;
+        }
+        result
+    }
+
+    /// Deletes the pair at `key` as part of `txn`, locking the tree for it.
+    /// The pair stays in the tree, stamped with `txn`, for the snapshots
+    /// that do not see the delete.
+    pub fn delete<S: WritablePageSource>(
+        &self,
+        bufmgr: &S,
+        txn: &mut Transaction,
+        key: &[u8],
+    ) -> Result<(), Error> {
+        txn.lock(self.meta_page_id)?;
+        let mut guard = self.fetch_leaf_write(bufmgr, &SearchMode::Key(key.to_vec()))?;
+        let leaf = leaf::Leaf::new(node::Node::new(&*guard).body);
+        let slot_id = match leaf.search_slot_id(key) {
+            Ok(slot_id) if !leaf.stamp_at(slot_id).is_deleted() => slot_id,
+            _ => return Err(Error::KeyNotFound),
+        };
+        let mut leaf = leaf::Leaf::new(node::Node::new(&mut *guard).body);
+        leaf.set_xmax(slot_id, txn.xid());
+        let change = Change::DeleteLeaf {
+            slot_id,
+            xmax: txn.xid(),
+        };
+        txn.log(&mut guard, change);
+        Ok(())
+    }
+
+    /// Drops the versions of pairs that no snapshot sees any more as part of
+    /// `txn`, locking the tree for it. Returns how many pairs went
+    /// altogether.
+    pub fn purge<S: WritablePageSource>(
+        &self,
+        bufmgr: &S,
+        txn: &mut Transaction,
+    ) -> Result<usize, Error> {
+        txn.lock(self.meta_page_id)?;
+        let horizon = txn.horizon();
+        let mut purged = 0;
+        let mut guard = self.fetch_leaf_write(bufmgr, &SearchMode::Start)?;
+        loop {
+            let leaf = leaf::Leaf::new(node::Node::new(&*guard).body);
+            let next_page_id = leaf.next_page_id();
+            let mut changes = vec![];
+            for slot_id in (0..leaf.pair_count()).rev() {
+                let old = leaf.slot_at(slot_id).to_vec();
+                match leaf.pruned_slot(slot_id, horizon) {
+                    None => changes.push(Change::PurgeLeaf { slot_id, slot: old }),
+                    Some(new) if new != old => {
+                        changes.push(Change::ReplaceLeaf { slot_id, old, new })
+                    }
+                    Some(_) => {}
+                }
+            }
+            for change in changes {
+                if let Change::PurgeLeaf { .. } = change {
+                    purged += 1;
+                }
+                change.redo(&mut guard);
+                txn.log(&mut guard, change);
+            }
+            match next_page_id {
+                Some(next_page_id) => guard = bufmgr.fetch_page_write(next_page_id)?,
+                None => return Ok(purged),
+            }
+        }
+    }
+
+    /// Leaf a search in `search_mode` starts from, latched for writing. Only
+    /// for a writer holding the lock on the tree, as every other writer
+    /// waits for it, so no split moves the pairs while they are looked for.
+    fn fetch_leaf_write<S: WritablePageSource>(
+        &self,
+        bufmgr: &S,
+        search_mode: &SearchMode,
+    ) -> Result<WritePageGuard, Error> {
+        let meta_page = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut page_id = meta::Meta::new(&*meta_page.body()).header.root_page_id;
+        drop(meta_page);
+        loop {
+            let guard = bufmgr.fetch_page_write(page_id)?;
+            let node = node::Node::new(&*guard);
+            match node::Body::new(node.header.node_type, node.body) {
+                node::Body::Leaf(_) => return Ok(guard),
+                node::Body::Branch(branch) => page_id = search_mode.child_page_id(&branch),
+            }
+        }
+    }
+
+    fn insert_locked<S: WritablePageSource>(
+        &self,
+        bufmgr: &S,
+        txn: &mut Transaction,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        let mut meta_guard = bufmgr.fetch_page_write(self.meta_page_id)?;
+        // A pair that fits in a leaf also fits in a branch as a key.
+        let size = leaf::slot_size(key, value);
+        if size > leaf::max_slot_size(meta_guard.len() - size_of::<node::Header>()) {
+            return Err(Error::PairTooLarge(size));
+        }
+        let root_page_id = meta::Meta::new(&*meta_guard).header.root_page_id;
+        let root_guard = bufmgr.fetch_page_write(root_page_id)?;
+        if let Some((key, child_page_id)) =
+            self.insert_internal(bufmgr, txn, root_guard, key, value)?
+        {
+            let mut new_root_guard = bufmgr.create_page()?;
+            let mut node = node::Node::new(&mut *new_root_guard);
             node.initialize_as_branch();
             let mut branch = branch::Branch::new(node.body);
             branch.initialize(&key, child_page_id, root_page_id);
-            meta.header.root_page_id = new_root_buffer.page_id;
-            meta_buffer.is_dirty.set(true);
+            let new_root_page_id = new_root_guard.page_id();
+            let body = new_root_guard.to_vec();
+            txn.log(&mut new_root_guard, Change::Format { body });
+            let mut meta = meta::Meta::new(&mut *meta_guard);
+            meta.header.root_page_id = new_root_page_id;
+            let change = Change::SetRoot {
+                old: root_page_id,
+                new: new_root_page_id,
+            };
+            txn.log(&mut meta_guard, change);
         }
         Ok(())
     }
 }
 
-pub struct Iter {
-    buffer: Rc<Buffer>,
-    slot_id: usize,
+/// Cursor over the pairs found by [`BTree::search`]. It keeps its current
+/// leaf pinned but only latches it while reading from it. Each pair is
+/// looked up again past the key last returned, so pairs inserted by other
+/// threads neither make it return a pair twice nor skip one that was there
+/// when it started.
+pub struct Iter<P = PageGuard> {
+    meta_page_id: PageId,
+    page_id: PageId,
+    page: P,
+    /// Leaf before the current one when the cursor got to it. Splitting the
+    /// current leaf moves its first pairs to a new leaf in between, so the
+    /// cursor searches from the root again when this changes.
+    prev_page_id: Option<PageId>,
+    /// Keys the pairs still to be returned are past.
+    from: Bound<Vec<u8>>,
+    /// Leaves to move through before hinting the next ones to the source.
+    leaves_until_read_ahead: usize,
+}
+
+/// What the current leaf of an [`Iter`] has for it.
+enum Step {
+    /// Key of the next pair, with the value of the version the snapshot
+    /// sees, if any.
+    Pair(Vec<u8>, Option<Vec<u8>>),
+    /// The leaf has no pairs left past the cursor; go on to this one.
+    NextLeaf(Option<PageId>),
+    /// The leaf has been split since the cursor got to it.
+    Split,
+}
+
+/// Leaf after the one whose body is given, or `None` if there is none or the
+/// page is not a leaf.
+fn next_leaf_page_id(body: &[u8]) -> Option<PageId> {
+    let node = node::Node::new(body);
+    if node.header.node_type != node::NODE_TYPE_LEAF {
+        return None;
+    }
+    leaf::Leaf::new(node.body).next_page_id()
 }
 
-impl Iter {
-    fn get(&self) -> Option<(Vec<u8>, Vec<u8>)> {
-        let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
+impl<P: NodePage> Iter<P> {
+    /// How many leaves a scan asks to be read ahead of it once it has moved
+    /// past its first one.
+    pub const READ_AHEAD_LEAVES: usize = 8;
+
+    fn step(&self, snapshot: &Snapshot) -> Step {
+        let leaf_body = self.page.body();
+        let leaf_node = node::Node::new(&*leaf_body);
         let leaf = leaf::Leaf::new(leaf_node.body);
-        if self.slot_id < leaf.pair_count() {
-            let pair = leaf.pair_at(self.slot_id);
-            Some((pair.key.to_vec(), pair.value.to_vec()))
+        if leaf.prev_page_id() != self.prev_page_id {
+            return Step::Split;
+        }
+        let slot_id = match &self.from {
+            Bound::Unbounded => 0,
+            Bound::Included(key) => leaf.search_slot_id(key).unwrap_or_else(identity),
+            Bound::Excluded(key) => leaf
+                .search_slot_id(key)
+                .map_or_else(identity, |slot_id| slot_id + 1),
+        };
+        if slot_id < leaf.pair_count() {
+            let value = leaf.value_seen_by(slot_id, snapshot);
+            Step::Pair(
+                leaf.pair_at(slot_id).key.to_vec(),
+                value.map(<[u8]>::to_vec),
+            )
         } else {
-            None
+            Step::NextLeaf(leaf.next_page_id())
+        }
+    }
+
+    /// Every half window, hints the leaves after the current one, so that a
+    /// long scan keeps up to `READ_AHEAD_LEAVES` of them in flight.
+    fn read_ahead<S: PageSource<Page = P>>(&mut self, source: &S) {
+        if self.leaves_until_read_ahead > 0 {
+            self.leaves_until_read_ahead -= 1;
+            return;
+        }
+        self.leaves_until_read_ahead = Self::READ_AHEAD_LEAVES / 2;
+        if let Some(next_page_id) = next_leaf_page_id(&self.page.body()) {
+            source.prefetch(next_page_id, Self::READ_AHEAD_LEAVES, next_leaf_page_id);
         }
     }
 
-    fn advance(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
-        self.slot_id += 1;
-        let next_page_id = {
-            let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
-            let leaf = leaf::Leaf::new(leaf_node.body);
-            if self.slot_id < leaf.pair_count() {
-                return Ok(());
+    /// Next pair `snapshot` sees.
+    pub fn next<S: PageSource<Page = P>>(
+        &mut self,
+        source: &S,
+        snapshot: &Snapshot,
+    ) -> Result<Option<Item>, Error> {
+        loop {
+            match self.step(snapshot) {
+                Step::Pair(key, value) => {
+                    self.from = Bound::Excluded(key.clone());
+                    if let Some(value) = value {
+                        return Ok(Some((key, value)));
+                    }
+                }
+                Step::NextLeaf(None) => return Ok(None),
+                Step::NextLeaf(Some(next_page_id)) => {
+                    self.page = source.fetch_page(next_page_id)?;
+                    self.prev_page_id = Some(self.page_id);
+                    self.page_id = next_page_id;
+                    self.read_ahead(source);
+                }
+                Step::Split => {
+                    let search_mode = match &self.from {
+                        Bound::Unbounded => SearchMode::Start,
+                        Bound::Included(key) | Bound::Excluded(key) => SearchMode::Key(key.clone()),
+                    };
+                    let iter = BTree::new(self.meta_page_id).search(source, search_mode)?;
+                    self.page_id = iter.page_id;
+                    self.page = iter.page;
+                    self.prev_page_id = iter.prev_page_id;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod btree_test {
+    use super::*;
+
+    mod search {
+        use super::*;
+        use crate::{buffer::ClockSweepBufferPool, disk::DiskManager};
+        use std::fs::remove_file;
+
+        #[test]
+        fn メモリマップしたファイルからバッファプールと同じ結果を読み込めること() {
+            // Arrange
+            let file_path = "btree_test::search::0.txt";
+            let bufmgr = {
+                let disk = DiskManager::open(file_path).unwrap();
+                let pool = ClockSweepBufferPool::from(16);
+                BufferPoolManager::new(disk, pool)
+            };
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in 0u32..1000 {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
             }
-            leaf.next_page_id()
+            txn.commit().unwrap();
+            bufmgr.flush_all().unwrap();
+            let mmap_file = MmapFile::open(file_path).unwrap();
+
+            // Act
+            let mut expected = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some(item) = iter.next(&bufmgr, &snapshot).unwrap() {
+                expected.push(item);
+            }
+            let mut actual = vec![];
+            let snapshot = Snapshot::of_mmap_file(&mmap_file);
+            let mut iter = btree.search(&mmap_file, SearchMode::Start).unwrap();
+            while let Some(item) = iter.next(&mmap_file, &snapshot).unwrap() {
+                actual.push(item);
+            }
+            let mut iter = btree
+                .search(&mmap_file, SearchMode::Key(500u32.to_be_bytes().to_vec()))
+                .unwrap();
+            let found = iter.next(&mmap_file, &snapshot).unwrap();
+
+            // Assert
+            assert_eq!(expected.len(), 1000);
+            assert_eq!(actual, expected);
+            assert_eq!(
+                found,
+                Some((500u32.to_be_bytes().to_vec(), vec![b'a'; 100]))
+            );
+
+            // Cleanup
+            drop(iter);
+            drop(mmap_file);
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod iter {
+        use super::*;
+        use crate::{
+            buffer::ClockSweepBufferPool,
+            disk::{DiskManager, MemoryStore},
         };
-        if let Some(next_page_id) = next_page_id {
-            self.buffer = bufmgr.fetch_page(next_page_id)?;
-            self.slot_id = 0;
+        use std::sync::Arc;
+
+        #[test]
+        fn 先読みしながらすべてのキーを順に読み込めること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let btree = {
+                let bufmgr = {
+                    let disk = DiskManager::new(store.clone()).unwrap();
+                    let pool = ClockSweepBufferPool::from(16);
+                    BufferPoolManager::new(disk, pool)
+                };
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+                for i in 0u32..2000 {
+                    btree
+                        .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                        .unwrap();
+                }
+                txn.commit().unwrap();
+                bufmgr.flush_all().unwrap();
+                btree
+            };
+            let bufmgr = {
+                let disk = DiskManager::new(store).unwrap();
+                let pool = ClockSweepBufferPool::from(32);
+                Arc::new(BufferPoolManager::new(disk, pool))
+            };
+            bufmgr.start_read_ahead(2);
+
+            // Act
+            let mut keys = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&*bufmgr, SearchMode::Start).unwrap();
+            while let Some((key, _)) = iter.next(&*bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+
+            // Assert
+            assert_eq!(keys, (0u32..2000).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 読んでいる葉が分割されてもキーを読み飛ばさず重複もしないこと() {
+            // Arrange
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                BufferPoolManager::new(disk, ClockSweepBufferPool::from(64))
+            };
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in (0u32..1000).step_by(2) {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            let mut keys = vec![];
+            for _ in 0..100 {
+                let (key, _) = iter.next(&bufmgr, &snapshot).unwrap().unwrap();
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in (1u32..1000).step_by(2) {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            while let Some((key, _)) = iter.next(&bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+
+            // Assert
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!((0u32..1000).step_by(2).all(|i| keys.contains(&i)));
         }
-        Ok(())
     }
 
-    pub fn next(
-        &mut self,
-        bufmgr: &mut BufferPoolManager,
-    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
-        let value = self.get();
-        self.advance(bufmgr)?;
-        Ok(value)
+    mod insert {
+        use super::*;
+        use crate::{
+            buffer::ClockSweepBufferPool,
+            disk::{DiskManager, MemoryStore},
+        };
+        use std::thread;
+
+        #[test]
+        fn 複数のスレッドから挿入したキーをすべて読み込めること() {
+            // Arrange
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(16);
+                BufferPoolManager::new(disk, pool)
+            };
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            txn.commit().unwrap();
+
+            // Act
+            thread::scope(|scope| {
+                for thread_id in 0u32..4 {
+                    let (bufmgr, btree) = (&bufmgr, &btree);
+                    scope.spawn(move || {
+                        for i in 0u32..500 {
+                            let key = (i * 4 + thread_id).to_be_bytes();
+                            let mut txn = Transaction::begin(bufmgr).unwrap();
+                            btree.insert(bufmgr, &mut txn, &key, &[b'a'; 100]).unwrap();
+                            txn.commit().unwrap();
+                        }
+                    });
+                }
+                let (bufmgr, btree) = (&bufmgr, &btree);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        let snapshot = Snapshot::take(bufmgr);
+                        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+                        while iter.next(bufmgr, &snapshot).unwrap().is_some() {}
+                    }
+                });
+            });
+
+            // Assert
+            let mut keys = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some((key, _)) = iter.next(&bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+            assert_eq!(keys, (0u32..2000).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 大きなページには小さなページに収まらない行を挿入できること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let value = |i: u32| vec![i as u8; 8000];
+            let btree = {
+                let options = disk::Options {
+                    page_size: 32768,
+                    ..Default::default()
+                };
+                let disk = DiskManager::with_options(store.clone(), options).unwrap();
+                let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+
+                // Act
+                for i in 0u32..200 {
+                    btree
+                        .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &value(i))
+                        .unwrap();
+                }
+                txn.commit().unwrap();
+                bufmgr.flush_all().unwrap();
+                btree
+            };
+
+            // Assert
+            let disk = DiskManager::new(store).unwrap();
+            assert_eq!(disk.page_size(), 32768);
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut items = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some(item) = iter.next(&bufmgr, &snapshot).unwrap() {
+                items.push(item);
+            }
+            let expected: Vec<_> = (0u32..200)
+                .map(|i| (i.to_be_bytes().to_vec(), value(i)))
+                .collect();
+            assert_eq!(items, expected);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 小さなページに収まらない行の場合PairTooLargeを返すこと() {
+            // Arrange
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+
+            // Act
+            let result = btree.insert(&bufmgr, &mut txn, &0u32.to_be_bytes(), &[b'a'; 8000]);
+
+            // Assert
+            assert!(matches!(result, Err(Error::PairTooLarge(_))));
+            btree
+                .insert(&bufmgr, &mut txn, &0u32.to_be_bytes(), &[b'a'; 100])
+                .unwrap();
+            txn.commit().unwrap();
+        }
+
+        #[test]
+        fn 削除して確定したキーを別のトランザクションで挿入し直せること() {
+            // Arrange
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in 0u32..500 {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..500 {
+                btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+            }
+            txn.commit().unwrap();
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..500 {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'b'; 200])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+
+            // Assert
+            let mut items = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some(item) = iter.next(&bufmgr, &snapshot).unwrap() {
+                items.push(item);
+            }
+            let expected: Vec<_> = (0u32..500)
+                .map(|i| (i.to_be_bytes().to_vec(), vec![b'b'; 200]))
+                .collect();
+            assert_eq!(items, expected);
+        }
+
+        #[test]
+        fn 削除を見ないスナップショットがあれば古い値を残して挿入し直すこと() {
+            // Arrange
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            btree
+                .insert(&bufmgr, &mut txn, &0u32.to_be_bytes(), b"old")
+                .unwrap();
+            txn.commit().unwrap();
+            let before_delete = Snapshot::take(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            btree
+                .delete(&bufmgr, &mut txn, &0u32.to_be_bytes())
+                .unwrap();
+            txn.commit().unwrap();
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            btree
+                .insert(&bufmgr, &mut txn, &0u32.to_be_bytes(), b"new")
+                .unwrap();
+            txn.commit().unwrap();
+
+            // Assert
+            let value = |snapshot: &Snapshot| {
+                let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+                iter.next(&bufmgr, snapshot)
+                    .unwrap()
+                    .map(|(_, value)| value)
+            };
+            assert_eq!(value(&before_delete), Some(b"old".to_vec()));
+            assert_eq!(value(&Snapshot::take(&bufmgr)), Some(b"new".to_vec()));
+        }
+    }
+
+    mod delete {
+        use super::*;
+        use crate::{
+            buffer::ClockSweepBufferPool,
+            disk::{DiskManager, MemoryStore},
+        };
+
+        fn bufmgr_with(keys: std::ops::Range<u32>) -> (BufferPoolManager, BTree) {
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in keys {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            (bufmgr, btree)
+        }
+
+        #[test]
+        fn 削除した行を読み飛ばすこと() {
+            // Arrange
+            let (bufmgr, btree) = bufmgr_with(0..500);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+
+            // Act
+            for i in (0u32..500).step_by(2) {
+                btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+            }
+            txn.commit().unwrap();
+
+            // Assert
+            let mut keys = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some((key, _)) = iter.next(&bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+            assert_eq!(keys, (1u32..500).step_by(2).collect::<Vec<_>>());
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 削除済みのキーの場合KeyNotFoundを返すこと() {
+            // Arrange
+            let (bufmgr, btree) = bufmgr_with(0..10);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            btree
+                .delete(&bufmgr, &mut txn, &0u32.to_be_bytes())
+                .unwrap();
+
+            // Act
+            let deleted = btree.delete(&bufmgr, &mut txn, &0u32.to_be_bytes());
+            let missing = btree.delete(&bufmgr, &mut txn, &10u32.to_be_bytes());
+
+            // Assert
+            assert!(matches!(deleted, Err(Error::KeyNotFound)));
+            assert!(matches!(missing, Err(Error::KeyNotFound)));
+        }
+    }
+
+    mod purge {
+        use super::*;
+        use crate::{
+            buffer::ClockSweepBufferPool,
+            disk::{DiskManager, MemoryStore},
+        };
+
+        #[test]
+        fn どのスナップショットも見ない行を取り除くこと() {
+            // Arrange
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in 0u32..500 {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..250 {
+                btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+            }
+            txn.commit().unwrap();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+
+            // Act
+            let purged = btree.purge(&bufmgr, &mut txn).unwrap();
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(purged, 250);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            assert_eq!(btree.purge(&bufmgr, &mut txn).unwrap(), 0);
+            txn.commit().unwrap();
+            let mut keys = vec![];
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            while let Some((key, _)) = iter.next(&bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+            assert_eq!(keys, (250u32..500).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 削除を見ないスナップショットがある間は行を残すこと() {
+            // Arrange
+            let disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(16));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let btree = BTree::create(&bufmgr, &mut txn).unwrap();
+            for i in 0u32..10 {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            let before_delete = Snapshot::take(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..10 {
+                btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+            }
+            txn.commit().unwrap();
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            let purged = btree.purge(&bufmgr, &mut txn).unwrap();
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(purged, 0);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            let mut count = 0;
+            while iter.next(&bufmgr, &before_delete).unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, 10);
+            drop(iter);
+            drop(before_delete);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            assert_eq!(btree.purge(&bufmgr, &mut txn).unwrap(), 10);
+            txn.commit().unwrap();
+        }
     }
 }
@@ -55,7 +55,7 @@ impl<B: ByteSlice> Branch<B> {
         }
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
+    pub fn pair_at(&self, slot_id: usize) -> Pair<'_> {
         Pair::from_bytes(&self.body[slot_id])
     }
 
@@ -94,6 +94,10 @@ impl<B: ByteSliceMut> Branch<B> {
         Some(())
     }
 
+    pub fn remove(&mut self, slot_id: usize) {
+        self.body.remove(slot_id);
+    }
+
     fn is_half_full(&self) -> bool {
         2 * self.body.free_space() < self.body.capacity()
     }
@@ -3,7 +3,10 @@ use crate::{
     bsearch::binary_search_by,
     disk::PageId,
     slotted::{self, Slotted},
+    transaction::{Snapshot, Stamp},
 };
+use bincode::Options;
+use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, FromZeroes, Ref};
 
@@ -14,6 +17,57 @@ pub struct Header {
     next_page_id: PageId,
 }
 
+/// What a slot holds after the stamp of the latest version of its pair.
+#[derive(Serialize, Deserialize)]
+struct Record<'a> {
+    key: &'a [u8],
+    value: &'a [u8],
+    /// Versions deleted before the latest one was inserted that snapshots
+    /// may still see, latest first.
+    #[serde(borrow)]
+    older: Vec<Version<'a>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Version<'a> {
+    stamp: Stamp,
+    value: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    fn from_bytes(bytes: &'a [u8]) -> Self {
+        bincode::options().deserialize(bytes).unwrap()
+    }
+
+    fn to_slot(&self, stamp: Stamp) -> Vec<u8> {
+        let mut slot = stamp.as_bytes().to_vec();
+        bincode::options().serialize_into(&mut slot, self).unwrap();
+        slot
+    }
+}
+
+/// Slot for a pair created by the transaction `xmin`.
+pub fn new_slot(key: &[u8], value: &[u8], xmin: u64) -> Vec<u8> {
+    let record = Record {
+        key,
+        value,
+        older: vec![],
+    };
+    record.to_slot(Stamp::new(xmin))
+}
+
+/// Bytes the pair takes in a leaf, stamp included.
+pub fn slot_size(key: &[u8], value: &[u8]) -> usize {
+    new_slot(key, value, 0).len()
+}
+
+/// `Leaf::max_pair_size` of a leaf made of `len` bytes.
+pub fn max_slot_size(len: usize) -> usize {
+    (len - size_of::<Header>() - size_of::<slotted::Header>()) / 2 - size_of::<slotted::Pointer>()
+}
+
+/// Each slot holds the stamp of the latest version of its pair followed by
+/// the pair, and the older versions of it that snapshots may still see.
 pub struct Leaf<B> {
     header: Ref<B, Header>,
     body: Slotted<B>,
@@ -44,8 +98,72 @@ impl<B: ByteSlice> Leaf<B> {
         })
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
-        Pair::from_bytes(&self.body[slot_id])
+    fn record_at(&self, slot_id: usize) -> Record<'_> {
+        Record::from_bytes(&self.body[slot_id][Stamp::SIZE..])
+    }
+
+    /// The pair as its latest version has it.
+    pub fn pair_at(&self, slot_id: usize) -> Pair<'_> {
+        let Record { key, value, .. } = self.record_at(slot_id);
+        Pair { key, value }
+    }
+
+    /// Stamp of the latest version of the pair.
+    pub fn stamp_at(&self, slot_id: usize) -> Stamp {
+        Stamp::read_from_prefix(&self.body[slot_id]).unwrap()
+    }
+
+    pub fn slot_at(&self, slot_id: usize) -> &[u8] {
+        &self.body[slot_id]
+    }
+
+    /// Value of the version of the pair `snapshot` sees, if any.
+    pub fn value_seen_by(&self, slot_id: usize, snapshot: &Snapshot) -> Option<&[u8]> {
+        let record = self.record_at(slot_id);
+        if snapshot.sees(&self.stamp_at(slot_id)) {
+            return Some(record.value);
+        }
+        record
+            .older
+            .into_iter()
+            .find(|version| snapshot.sees(&version.stamp))
+            .map(|version| version.value)
+    }
+
+    /// Slot in which the transaction `xmin` inserts `value` again for the
+    /// deleted pair, keeping the versions that are not dead at `horizon`.
+    pub fn renewed_slot(&self, slot_id: usize, value: &[u8], xmin: u64, horizon: u64) -> Vec<u8> {
+        let stamp = self.stamp_at(slot_id);
+        let record = self.record_at(slot_id);
+        let latest = Version {
+            stamp,
+            value: record.value,
+        };
+        let older = [latest]
+            .into_iter()
+            .chain(record.older)
+            .filter(|version| !version.stamp.is_dead(horizon))
+            .collect();
+        let record = Record {
+            key: record.key,
+            value,
+            older,
+        };
+        record.to_slot(Stamp::new(xmin))
+    }
+
+    /// Slot without the versions that are dead at `horizon`, or `None` if
+    /// they all are. Returns the slot as it is if none of them are.
+    pub fn pruned_slot(&self, slot_id: usize, horizon: u64) -> Option<Vec<u8>> {
+        let stamp = self.stamp_at(slot_id);
+        if stamp.is_dead(horizon) {
+            return None;
+        }
+        let mut record = self.record_at(slot_id);
+        record
+            .older
+            .retain(|version| !version.stamp.is_dead(horizon));
+        Some(record.to_slot(stamp))
     }
 
     pub fn max_pair_size(&self) -> usize {
@@ -68,15 +186,42 @@ impl<B: ByteSliceMut> Leaf<B> {
         self.header.next_page_id = next_page_id.into();
     }
 
-    pub fn insert(&mut self, slot_id: usize, key: &[u8], value: &[u8]) -> Option<()> {
-        let pair = Pair { key, value };
-        let pair_bytes = pair.to_bytes();
-        assert!(pair_bytes.len() <= self.max_pair_size());
-        self.body.insert(slot_id, pair_bytes.len())?;
-        self.body[slot_id].copy_from_slice(&pair_bytes);
+    /// Inserts the pair as created by the transaction `xmin`.
+    pub fn insert(&mut self, slot_id: usize, key: &[u8], value: &[u8], xmin: u64) -> Option<()> {
+        self.insert_slot(slot_id, &new_slot(key, value, xmin))
+    }
+
+    pub fn insert_slot(&mut self, slot_id: usize, slot: &[u8]) -> Option<()> {
+        // `BTree::insert` turns away pairs too large for a leaf.
+        debug_assert!(slot.len() <= self.max_pair_size());
+        self.body.insert(slot_id, slot.len())?;
+        self.body[slot_id].copy_from_slice(slot);
+        Some(())
+    }
+
+    /// Puts `slot` in place of the slot at `slot_id`, which must hold the
+    /// same key.
+    pub fn replace(&mut self, slot_id: usize, slot: &[u8]) -> Option<()> {
+        debug_assert!(slot.len() <= self.max_pair_size());
+        self.body.resize(slot_id, slot.len())?;
+        self.body[slot_id].copy_from_slice(slot);
         Some(())
     }
 
+    /// Marks the pair as deleted by the transaction `xmax`, or as not
+    /// deleted for 0.
+    pub fn set_xmax(&mut self, slot_id: usize, xmax: u64) {
+        let stamp = Stamp {
+            xmax,
+            ..self.stamp_at(slot_id)
+        };
+        stamp.write_to_prefix(&mut self.body[slot_id]).unwrap();
+    }
+
+    pub fn remove(&mut self, slot_id: usize) {
+        self.body.remove(slot_id);
+    }
+
     fn is_half_full(&self) -> bool {
         2 * self.body.free_space() < self.body.capacity()
     }
@@ -85,7 +230,7 @@ impl<B: ByteSliceMut> Leaf<B> {
         &mut self,
         new_leaf: &mut Leaf<impl ByteSliceMut>,
         new_key: &[u8],
-        new_value: &[u8],
+        new_slot: &[u8],
     ) -> Vec<u8> {
         new_leaf.initialize();
         loop {
@@ -93,7 +238,7 @@ impl<B: ByteSliceMut> Leaf<B> {
                 let index = self
                     .search_slot_id(new_key)
                     .expect_err("key must be unique");
-                self.insert(index, new_key, new_value)
+                self.insert_slot(index, new_slot)
                     .expect("old leaf must have space");
                 break;
             }
@@ -101,7 +246,7 @@ impl<B: ByteSliceMut> Leaf<B> {
                 self.transfer(new_leaf);
             } else {
                 new_leaf
-                    .insert(new_leaf.pair_count(), new_key, new_value)
+                    .insert_slot(new_leaf.pair_count(), new_slot)
                     .expect("new leaf must have space");
                 while !new_leaf.is_half_full() {
                     self.transfer(new_leaf);
@@ -0,0 +1,150 @@
+use super::{branch::Branch, leaf::Leaf, meta::Meta, node::Node};
+use crate::{
+    buffer::WritePageGuard,
+    disk::PageId,
+    wal::{LogManager, LogRecord, Lsn},
+};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+/// Change to the body of one page of a B-tree, as it is logged. Each one can
+/// be redone on the page as it was before, and undone on the page as it was
+/// after.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    /// Gives a newly created page its contents. Undoing it leaves the page
+    /// as it is, since nothing links to it any more by then.
+    Format {
+        body: Vec<u8>,
+    },
+    InsertLeaf {
+        slot_id: usize,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        xmin: u64,
+    },
+    /// Marks a pair that was not deleted as deleted by `xmax`.
+    DeleteLeaf {
+        slot_id: usize,
+        xmax: u64,
+    },
+    /// Rewrites the versions of a pair, as inserting it again or purging
+    /// does.
+    ReplaceLeaf {
+        slot_id: usize,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// Removes a pair none of whose versions any snapshot sees.
+    PurgeLeaf {
+        slot_id: usize,
+        slot: Vec<u8>,
+    },
+    InsertBranch {
+        slot_id: usize,
+        key: Vec<u8>,
+        child_page_id: PageId,
+    },
+    /// Moves part of the pairs of a node to its new sibling.
+    Split {
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    SetNextLeaf {
+        old: Option<PageId>,
+        new: Option<PageId>,
+    },
+    SetRoot {
+        old: PageId,
+        new: PageId,
+    },
+}
+
+impl Change {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::options().serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bincode::options().deserialize(bytes).ok()
+    }
+
+    pub fn redo(&self, body: &mut [u8]) {
+        match self {
+            Change::Format { body: after } | Change::Split { after, .. } => {
+                body.copy_from_slice(after)
+            }
+            Change::InsertLeaf {
+                slot_id,
+                key,
+                value,
+                xmin,
+            } => Leaf::new(Node::new(body).body)
+                .insert(*slot_id, key, value, *xmin)
+                .expect("logged insert must fit"),
+            Change::DeleteLeaf { slot_id, xmax } => {
+                Leaf::new(Node::new(body).body).set_xmax(*slot_id, *xmax)
+            }
+            Change::ReplaceLeaf { slot_id, new, .. } => Leaf::new(Node::new(body).body)
+                .replace(*slot_id, new)
+                .expect("logged replace must fit"),
+            Change::PurgeLeaf { slot_id, .. } => Leaf::new(Node::new(body).body).remove(*slot_id),
+            Change::InsertBranch {
+                slot_id,
+                key,
+                child_page_id,
+            } => Branch::new(Node::new(body).body)
+                .insert(*slot_id, key, *child_page_id)
+                .expect("logged insert must fit"),
+            Change::SetNextLeaf { new, .. } => {
+                Leaf::new(Node::new(body).body).set_next_page_id(*new)
+            }
+            Change::SetRoot { new, .. } => Meta::new(body).header.root_page_id = *new,
+        }
+    }
+
+    pub fn undo(&self, body: &mut [u8]) {
+        match self {
+            Change::Format { .. } => {}
+            Change::Split { before, .. } => body.copy_from_slice(before),
+            Change::InsertLeaf { slot_id, .. } => Leaf::new(Node::new(body).body).remove(*slot_id),
+            Change::DeleteLeaf { slot_id, .. } => {
+                Leaf::new(Node::new(body).body).set_xmax(*slot_id, 0)
+            }
+            Change::ReplaceLeaf { slot_id, old, .. } => Leaf::new(Node::new(body).body)
+                .replace(*slot_id, old)
+                .expect("replaced slot must fit again"),
+            Change::PurgeLeaf { slot_id, slot } => Leaf::new(Node::new(body).body)
+                .insert_slot(*slot_id, slot)
+                .expect("purged slot must fit again"),
+            Change::InsertBranch { slot_id, .. } => {
+                Branch::new(Node::new(body).body).remove(*slot_id)
+            }
+            Change::SetNextLeaf { old, .. } => {
+                Leaf::new(Node::new(body).body).set_next_page_id(*old)
+            }
+            Change::SetRoot { old, .. } => Meta::new(body).header.root_page_id = *old,
+        }
+    }
+}
+
+/// Undoes `change` on the page of `guard` for the operation `op`, logging
+/// the undone change so that it is not undone again. Returns the LSN of that
+/// record.
+pub fn undo(
+    log: &LogManager,
+    op: Lsn,
+    guard: &mut WritePageGuard,
+    change: &Change,
+    undo_next_lsn: Lsn,
+) -> Lsn {
+    change.undo(guard);
+    let lsn = log.append(&LogRecord::Compensation {
+        op,
+        page_id: guard.page_id(),
+        change: change.to_bytes(),
+        undo_next_lsn,
+    });
+    guard.set_lsn(lsn);
+    lsn
+}
@@ -1,11 +1,22 @@
+#[allow(clippy::module_inception)]
 mod buffer;
 mod error;
 mod frame;
+mod guard;
 mod manager;
 mod pool;
+mod read_ahead;
+mod ring;
+mod stats;
+mod writer;
 
 pub use buffer::*;
 pub use error::*;
 pub use frame::*;
+pub use guard::*;
 pub use manager::*;
 pub use pool::*;
+pub use read_ahead::NextPageId;
+pub use ring::{BufferRing, WithRing};
+pub use stats::*;
+pub use writer::*;
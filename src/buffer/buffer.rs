@@ -1,5 +1,12 @@
-use crate::disk::{DiskManager, PageId};
-use std::cell::{Cell, RefCell};
+use crate::{
+    disk::{Page, PageId},
+    wal::Lsn,
+};
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock, RwLock};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct BufferId(usize);
@@ -37,21 +44,108 @@ mod buffer_id_test {
     }
 }
 
-pub type Page = [u8; DiskManager::PAGE_SIZE];
-
-#[derive(Debug, PartialEq)]
+/// The page image of a frame and the state shared with the guards handed out
+/// for it. A frame keeps its `Buffer` for good; `page_id` only changes while
+/// the buffer is unpinned, under the lock of the page table shard that maps
+/// it, so a guard can rely on it for as long as it holds its pin.
+#[derive(Debug)]
 pub struct Buffer {
-    pub page_id: PageId,
-    pub page: RefCell<Page>,
-    pub is_dirty: Cell<bool>,
+    page_id: AtomicU64,
+    pin_count: AtomicUsize,
+    is_dirty: AtomicBool,
+    /// Bumped every time the page is marked dirty, so that a write-back can
+    /// tell whether the page changed while it was being written.
+    version: AtomicU64,
+    /// First log record applied to the page since it was last clean, or 0
+    /// if none has been yet.
+    rec_lsn: AtomicU64,
+    /// Latch over the page image, shared with the guards holding it.
+    page: Arc<RwLock<Page>>,
 }
 
-impl Default for Buffer {
-    fn default() -> Self {
+impl Buffer {
+    pub fn new(page_id: PageId, page: Page) -> Self {
         Self {
-            page_id: Default::default(),
-            page: RefCell::new([0u8; DiskManager::PAGE_SIZE]),
-            is_dirty: Cell::new(false),
+            page_id: AtomicU64::new(page_id.value()),
+            pin_count: AtomicUsize::new(0),
+            is_dirty: AtomicBool::new(false),
+            version: AtomicU64::new(0),
+            rec_lsn: AtomicU64::new(0),
+            page: Arc::new(RwLock::new(page)),
+        }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        PageId::new(self.page_id.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn set_page_id(&self, page_id: PageId) {
+        self.page_id.store(page_id.value(), Ordering::Release)
+    }
+
+    pub fn pin_count(&self) -> usize {
+        self.pin_count.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn pin(&self) {
+        self.pin_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn unpin(&self) {
+        let pin_count = self.pin_count.fetch_sub(1, Ordering::AcqRel);
+        debug_assert!(pin_count > 0, "buffer is not pinned");
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty.load(Ordering::Acquire)
+    }
+
+    /// Marking the page clean forgets its `rec_lsn`.
+    pub(crate) fn set_dirty(&self, is_dirty: bool) {
+        if is_dirty {
+            self.version.fetch_add(1, Ordering::AcqRel);
+        } else {
+            self.rec_lsn.store(0, Ordering::Release);
         }
+        self.is_dirty.store(is_dirty, Ordering::Release)
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Marks the page clean unless it has been marked dirty again since
+    /// `version` was read. The caller holds the latch, so that no one marks
+    /// it dirty meanwhile.
+    pub(crate) fn set_clean_since(&self, version: u64) {
+        if self.version() == version {
+            self.set_dirty(false);
+        }
+    }
+
+    pub fn rec_lsn(&self) -> Lsn {
+        Lsn::new(self.rec_lsn.load(Ordering::Acquire))
+    }
+
+    /// Records that the log record at `lsn` has been applied to the page,
+    /// which only sets `rec_lsn` if it is the first since the page was clean.
+    pub(crate) fn set_rec_lsn(&self, lsn: Lsn) {
+        let _ = self
+            .rec_lsn
+            .compare_exchange(0, lsn.value(), Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub(crate) fn read(&self) -> ArcRwLockReadGuard<RawRwLock, Page> {
+        self.page.read_arc()
+    }
+
+    pub(crate) fn write(&self) -> ArcRwLockWriteGuard<RawRwLock, Page> {
+        self.page.write_arc()
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new(PageId::default(), Page::default())
     }
 }
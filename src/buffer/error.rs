@@ -1,3 +1,4 @@
+use crate::disk::PageId;
 use std::io;
 
 #[derive(Debug, thiserror::Error)]
@@ -6,4 +7,10 @@ pub enum Error {
     IoError(#[from] io::Error),
     #[error("no free buffer is available in this buffer pool.")]
     NoFreeBuffer,
+    #[error("checksum mismatch in page {page_id:?}.")]
+    ChecksumMismatch { page_id: PageId },
+    #[error("page {0:?} is still referenced.")]
+    PagePinned(PageId),
+    #[error("a buffer pool needs at least one frame.")]
+    EmptyPool,
 }
@@ -1,24 +1,50 @@
-use super::Buffer;
-use std::rc::Rc;
-
+use super::{Buffer, PageGuard};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+/// A slot of the buffer pool. Its buffer is reused for whatever page the
+/// frame is given next, which needs the buffer to be unpinned; `is_claimed`
+/// keeps two threads from picking the same victim.
 #[derive(Default)]
 pub struct Frame {
-    pub(crate) usage_count: u64,
-    pub(crate) buffer: Rc<Buffer>,
+    pub(crate) usage_count: AtomicU64,
+    pub(crate) is_claimed: AtomicBool,
+    pub(crate) buffer: Arc<Buffer>,
 }
 
 impl Frame {
-    pub(crate) fn reset_usage_count(&mut self) {
-        self.usage_count = 0
+    pub(crate) fn reset_usage_count(&self) {
+        self.usage_count.store(0, Ordering::Relaxed)
+    }
+
+    pub(crate) fn pin(&self) -> PageGuard {
+        self.usage_count.fetch_add(1, Ordering::Relaxed);
+        PageGuard::new(self.buffer.clone())
+    }
+
+    pub(crate) fn is_pinned(&self) -> bool {
+        self.buffer.pin_count() > 0
+    }
+
+    /// Whether the frame could be claimed right now.
+    pub(crate) fn is_evictable(&self) -> bool {
+        !self.is_claimed.load(Ordering::Acquire) && !self.is_pinned()
     }
 
-    pub(crate) fn use_buffer(&mut self) -> Rc<Buffer> {
-        self.usage_count += 1;
-        self.buffer.clone()
+    /// Reserves the frame for the caller if it is unpinned and no one else
+    /// has claimed it.
+    pub(crate) fn try_claim(&self) -> bool {
+        !self.is_pinned()
+            && self
+                .is_claimed
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
     }
 
-    pub(crate) fn has_reference(&mut self) -> bool {
-        Rc::get_mut(&mut self.buffer).is_none()
+    pub(crate) fn unclaim(&self) {
+        self.is_claimed.store(false, Ordering::Release)
     }
 }
 
@@ -32,71 +58,95 @@ mod frame_test {
         #[test]
         fn usage_countが0になること() {
             // Arrange
-            let mut frame = Frame {
-                usage_count: 3,
-                buffer: Rc::new(Buffer::default()),
+            let frame = Frame {
+                usage_count: AtomicU64::new(3),
+                ..Default::default()
             };
 
             // Act
             frame.reset_usage_count();
 
             // Assert
-            assert_eq!(frame.usage_count, 0);
+            assert_eq!(frame.usage_count.load(Ordering::Relaxed), 0);
         }
     }
 
-    mod use_buffer {
+    mod pin {
         use super::*;
 
         #[test]
-        fn カウントを1だけ加算しバッファのクローンを返すこと() {
+        fn カウントを1だけ加算しバッファをピン留めすること() {
             // Arrange
-            let mut frame = Frame {
-                usage_count: 0,
-                buffer: Rc::new(Buffer::default()),
-            };
+            let frame = Frame::default();
 
             // Act
-            let buffer = frame.use_buffer();
+            let guard = frame.pin();
 
             // Assert
-            assert_eq!(frame.usage_count, 1);
-            assert_eq!(buffer, frame.buffer);
+            assert_eq!(frame.usage_count.load(Ordering::Relaxed), 1);
+            assert_eq!(frame.buffer.pin_count(), 1);
+            drop(guard);
+            assert_eq!(frame.buffer.pin_count(), 0);
         }
     }
 
-    mod has_reference {
+    mod is_pinned {
         use super::*;
 
         #[test]
-        fn 強い参照を持つ場合trueとなること() {
+        fn ピン留めされている場合trueとなること() {
             // Arrange
-            let mut frame = Frame {
-                usage_count: 0,
-                buffer: Rc::new(Buffer::default()),
-            };
-            let _clone = Rc::clone(&frame.buffer);
+            let frame = Frame::default();
+            let _guard = frame.pin();
 
             // Act
-            let actual = frame.has_reference();
+            let actual = frame.is_pinned();
 
             // Assert
-            assert_eq!(actual, true);
+            assert!(actual);
         }
 
         #[test]
-        fn 参照を持たない場合falseとなること() {
+        fn ピン留めされていない場合falseとなること() {
             // Arrange
-            let mut frame = Frame {
-                usage_count: 0,
-                buffer: Rc::new(Buffer::default()),
-            };
+            let frame = Frame::default();
+
+            // Act
+            let actual = frame.is_pinned();
+
+            // Assert
+            assert!(!actual);
+        }
+    }
+
+    mod try_claim {
+        use super::*;
+
+        #[test]
+        fn 一度だけ成功すること() {
+            // Arrange
+            let frame = Frame::default();
+
+            // Act
+            let first = frame.try_claim();
+            let second = frame.try_claim();
+
+            // Assert
+            assert!(first);
+            assert!(!second);
+        }
+
+        #[test]
+        fn ピン留めされている場合失敗すること() {
+            // Arrange
+            let frame = Frame::default();
+            let _guard = frame.pin();
 
             // Act
-            let actual = frame.has_reference();
+            let actual = frame.try_claim();
 
             // Assert
-            assert_eq!(actual, false);
+            assert!(!actual);
         }
     }
 }
@@ -0,0 +1,192 @@
+use super::Buffer;
+use crate::{
+    disk::{Page, PageHeader, PageId},
+    wal::{self, Lsn},
+};
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+/// Pin on a page in the buffer pool. The frame holding the page is not
+/// evicted until every guard on it has been dropped. It does not latch the
+/// page; `read` and `write` do, for as long as the guard they return lives.
+#[derive(Debug)]
+pub struct PageGuard {
+    buffer: Arc<Buffer>,
+    page_id: PageId,
+}
+
+impl PageGuard {
+    /// Pins `buffer`. The caller must make sure its page cannot be replaced
+    /// in the meantime, by holding the page table shard that maps it.
+    pub(crate) fn new(buffer: Arc<Buffer>) -> Self {
+        buffer.pin();
+        let page_id = buffer.page_id();
+        Self { buffer, page_id }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.buffer.is_dirty()
+    }
+
+    pub(crate) fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn read(&self) -> ReadPageGuard {
+        ReadPageGuard {
+            page: self.buffer.read(),
+            guard: self.clone(),
+        }
+    }
+
+    pub fn write(&self) -> WritePageGuard {
+        WritePageGuard {
+            page: self.buffer.write(),
+            guard: self.clone(),
+        }
+    }
+}
+
+impl Clone for PageGuard {
+    fn clone(&self) -> Self {
+        Self::new(self.buffer.clone())
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        self.buffer.unpin();
+    }
+}
+
+/// Shared latch on a pinned page. Dereferences to the page body.
+pub struct ReadPageGuard {
+    // Declared before `guard` so that the latch is released before the pin.
+    page: ArcRwLockReadGuard<RawRwLock, Page>,
+    guard: PageGuard,
+}
+
+impl ReadPageGuard {
+    pub fn page_id(&self) -> PageId {
+        self.guard.page_id
+    }
+
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+
+    pub fn lsn(&self) -> Lsn {
+        wal::page_lsn(&self.page)
+    }
+}
+
+impl Deref for ReadPageGuard {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.page[PageHeader::SIZE..]
+    }
+}
+
+/// Exclusive latch on a pinned page. Dereferences to the page body, and
+/// marks the page dirty as soon as it is dereferenced mutably.
+pub struct WritePageGuard {
+    // Declared before `guard` so that the latch is released before the pin.
+    page: ArcRwLockWriteGuard<RawRwLock, Page>,
+    guard: PageGuard,
+}
+
+impl WritePageGuard {
+    pub fn page_id(&self) -> PageId {
+        self.guard.page_id
+    }
+
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+
+    pub fn page_mut(&mut self) -> &mut Page {
+        self.guard.buffer.set_dirty(true);
+        &mut self.page
+    }
+
+    pub fn lsn(&self) -> Lsn {
+        wal::page_lsn(&self.page)
+    }
+
+    /// Records that the log record at `lsn` has been applied to the page,
+    /// which then cannot be written before the log is flushed up to it.
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        wal::set_page_lsn(self.page_mut(), lsn);
+        self.guard.buffer.set_rec_lsn(lsn);
+    }
+}
+
+impl Deref for WritePageGuard {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.page[PageHeader::SIZE..]
+    }
+}
+
+impl DerefMut for WritePageGuard {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.page_mut()[PageHeader::SIZE..]
+    }
+}
+
+#[cfg(test)]
+mod page_guard_test {
+    use super::*;
+
+    mod drop {
+        use super::*;
+
+        #[test]
+        fn ガードを破棄するとピンが外れること() {
+            // Arrange
+            let buffer = Arc::new(Buffer::default());
+            let guard = PageGuard::new(buffer.clone());
+            let read_guard = guard.read();
+
+            // Act
+            drop(guard);
+            let pin_count = buffer.pin_count();
+            drop(read_guard);
+
+            // Assert
+            assert_eq!(pin_count, 1);
+            assert_eq!(buffer.pin_count(), 0);
+        }
+    }
+
+    mod deref_mut {
+        use super::*;
+
+        #[test]
+        fn 書き込み用のガードを可変参照した場合だけページがダーティになること() {
+            // Arrange
+            let buffer = Arc::new(Buffer::new(PageId::new(1), Page::new(64)));
+            let guard = PageGuard::new(buffer.clone());
+            let mut write_guard = guard.write();
+
+            // Act
+            let first_byte = write_guard[0];
+            let is_dirty_after_read = guard.is_dirty();
+            write_guard[0] = b'a';
+
+            // Assert
+            assert_eq!(first_byte, 0);
+            assert!(!is_dirty_after_read);
+            assert!(guard.is_dirty());
+        }
+    }
+}
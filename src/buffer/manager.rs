@@ -1,160 +1,1446 @@
-use super::{Buffer, BufferId, BufferPool, Error, Frame};
-use crate::disk::{DiskManager, PageId};
-use std::{collections::HashMap, rc::Rc};
+use super::{
+    read_ahead::{self, NextPageId, ReadAhead},
+    BufferId, BufferPool, BufferPoolStats, BufferRing, Counters, Error, FrameInfo, PageGuard,
+    ReadPageGuard, WithRing, WritePageGuard,
+};
+use crate::{
+    disk::{self, CompressionStats, DiskManager, Page, PageId},
+    transaction::{LockTable, VersionTable},
+    wal::{self, LogManager, Lsn},
+};
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, mpsc::SyncSender, Arc},
+};
 
+/// Caches the pages of a `DiskManager` in the frames of a `BufferPool`. It is
+/// `Send + Sync`, so one manager can be shared between threads behind an
+/// `Arc`. Pages are handed out as guards that keep them pinned, and latched
+/// for reading or writing, until they are dropped.
 pub struct BufferPoolManager {
-    disk: DiskManager,
-    pool: Box<dyn BufferPool<Output = Frame>>,
-    page_table: HashMap<PageId, BufferId>,
+    disk: RwLock<DiskManager>,
+    /// Locked for writing only to resize the pool. Nothing waits on a page
+    /// latch while holding it, since the latch may be held by a thread that
+    /// is waiting for the pool in turn.
+    pool: RwLock<Box<dyn BufferPool>>,
+    page_table: PageTable,
+    /// Most frames the pool may grow to on its own, if it grows at all.
+    max_pool_size: Option<usize>,
+    counters: Counters,
+    read_ahead: Mutex<Option<SyncSender<ReadAhead>>>,
+    /// Flushed up to the LSN of each page before the page is written.
+    log: Option<LogManager>,
+    /// Locks the transactions writing through this manager take on trees.
+    locks: LockTable,
+    /// Transactions writing through this manager, which snapshots tell the
+    /// rows they see by.
+    versions: Arc<VersionTable>,
+}
+
+/// Maps pages to the frames holding them, split into shards so that threads
+/// working on different pages rarely wait for each other. A shard stays locked
+/// while one of its pages is read in or written back, so a page is never
+/// loaded twice or read from disk before its last copy has been written.
+struct PageTable {
+    shards: Vec<Mutex<HashMap<PageId, BufferId>>>,
+}
+
+impl PageTable {
+    const SHARD_COUNT: usize = 16;
+
+    fn new() -> Self {
+        Self {
+            shards: (0..Self::SHARD_COUNT).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn shard(&self, page_id: PageId) -> MutexGuard<'_, HashMap<PageId, BufferId>> {
+        self.shards[page_id.value() as usize % self.shards.len()].lock()
+    }
+
+    /// Maps the pages of the frames again, dropping every other entry.
+    fn rebuild(&self, pool: &dyn BufferPool) {
+        for shard in &self.shards {
+            shard.lock().clear();
+        }
+        for buffer_id in (0..pool.size()).map(BufferId::new) {
+            if let Some(page_id) = pool[buffer_id].buffer.page_id().valid() {
+                self.shard(page_id).insert(page_id, buffer_id);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn get(&self, page_id: PageId) -> Option<BufferId> {
+        self.shard(page_id).get(&page_id).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
 }
 
 impl BufferPoolManager {
-    pub fn new<T: 'static + BufferPool<Output = Frame>>(disk: DiskManager, pool: T) -> Self {
+    /// Fewest frames a pool with a memory budget has once it has grown.
+    const MIN_GROWN_POOL_SIZE: usize = 16;
+
+    pub fn new<T: 'static + BufferPool>(disk: DiskManager, pool: T) -> Self {
+        let versions = Arc::new(VersionTable::new(disk.next_xid()));
         Self {
-            disk,
-            pool: Box::new(pool),
-            page_table: HashMap::new(),
+            disk: RwLock::new(disk),
+            pool: RwLock::new(Box::new(pool)),
+            page_table: PageTable::new(),
+            max_pool_size: None,
+            counters: Counters::default(),
+            read_ahead: Mutex::default(),
+            log: None,
+            locks: LockTable::default(),
+            versions,
         }
     }
 
-    pub fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+    /// Manager that writes a page only once the log records up to its LSN
+    /// are in `log`. It does not recover the pages from the log; see
+    /// [`wal::open`] and [`wal::recover`].
+    pub fn with_log(self, log: LogManager) -> Self {
+        Self {
+            log: Some(log),
+            ..self
+        }
+    }
+
+    pub fn log(&self) -> Option<&LogManager> {
+        self.log.as_ref()
+    }
+
+    pub(crate) fn locks(&self) -> &LockTable {
+        &self.locks
+    }
+
+    pub(crate) fn versions(&self) -> &Arc<VersionTable> {
+        &self.versions
+    }
+
+    /// Records durably that transaction ids below `next_xid` may be in use.
+    pub(crate) fn reserve_xids(&self, next_xid: u64) -> Result<(), Error> {
+        self.disk.write().reserve_xids(next_xid)?;
+        Ok(())
+    }
+
+    /// Manager whose pool starts out with the frames it is given, which can
+    /// be none, and adds more whenever all of them hold a page, until their
+    /// page images take up `budget` bytes. Only then does it evict pages.
+    pub fn with_memory_budget<T: 'static + BufferPool>(
+        disk: DiskManager,
+        pool: T,
+        budget: usize,
+    ) -> Self {
+        let max_pool_size = (budget / disk.usable_page_size()).max(1);
+        Self {
+            max_pool_size: Some(max_pool_size),
+            ..Self::new(disk, pool)
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.disk.read().page_size()
+    }
+
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.disk.read().compression_stats()
+    }
+
+    pub fn catalog_page_id(&self) -> Option<PageId> {
+        self.disk.read().catalog_page_id()
+    }
+
+    pub fn set_catalog_page_id(&self, catalog_page_id: Option<PageId>) -> Result<(), Error> {
+        self.disk.write().set_catalog_page_id(catalog_page_id)?;
+        Ok(())
+    }
+
+    /// Pins the page, reading it in if it is not in the pool yet.
+    pub fn fetch_page(&self, page_id: PageId) -> Result<PageGuard, Error> {
+        self.fetch(page_id, None, false)
+    }
+
+    /// The manager, loading and creating pages through `ring` rather than
+    /// through the whole pool, for scans and bulk loads.
+    pub fn with_ring<'a>(&'a self, ring: &'a BufferRing) -> WithRing<'a> {
+        WithRing::new(self, ring)
+    }
+
+    /// Starts `workers` threads that read the pages passed to `prefetch` into
+    /// the pool in the background. They stop once the manager is dropped.
+    pub fn start_read_ahead(self: &Arc<Self>, workers: usize) {
+        *self.read_ahead.lock() = Some(read_ahead::spawn(Arc::downgrade(self), workers));
+    }
+
+    /// Hints that `count` pages will be read soon: `page_id`, then the page
+    /// `next_page_id` finds in the body of each page read. Does nothing if
+    /// read-ahead has not been started or is too far behind already.
+    pub fn prefetch(&self, page_id: PageId, count: usize, next_page_id: NextPageId) {
+        self.prefetch_into(None, page_id, count, next_page_id)
+    }
+
+    pub(crate) fn prefetch_into(
+        &self,
+        ring: Option<&BufferRing>,
+        page_id: PageId,
+        count: usize,
+        next_page_id: NextPageId,
+    ) {
+        if let Some(sender) = &*self.read_ahead.lock() {
+            let _ = sender.try_send(ReadAhead {
+                page_id,
+                count,
+                next_page_id,
+                ring: ring.cloned(),
+            });
+        }
+    }
+
+    pub(crate) fn fetch(
+        &self,
+        page_id: PageId,
+        ring: Option<&BufferRing>,
+        is_read_ahead: bool,
+    ) -> Result<PageGuard, Error> {
         // dbg!(page_id);
-        if let Some(&buffer_id) = self.page_table.get(&page_id) {
-            let frame = &mut self.pool[buffer_id];
-            let buffer = frame.use_buffer();
-            return Ok(buffer);
-        }
-        let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
-        let frame = &mut self.pool[buffer_id];
-        let evict_page_id = frame.buffer.page_id;
         {
-            let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
-            if buffer.is_dirty.get() {
-                self.disk
-                    .write_page_data(evict_page_id, buffer.page.get_mut())?;
-            }
-            buffer.page_id = page_id;
-            buffer.is_dirty.set(false);
-            self.disk.read_page_data(page_id, buffer.page.get_mut())?;
+            let pool = &**self.pool.read();
+            let shard = self.page_table.shard(page_id);
+            if let Some(&buffer_id) = shard.get(&page_id) {
+                return Ok(self.hit(pool, buffer_id, ring, is_read_ahead));
+            }
+        }
+        let ring_victim = ring.and_then(|ring| ring.lock().next_victim());
+        if ring_victim.is_none() {
+            self.grow_if_full();
+        }
+        let pool = &**self.pool.read();
+        let buffer_id = self.detach_ring_or_pool_victim(pool, ring_victim)?;
+        let shard = self.page_table.shard(page_id);
+        if let Some(&resident_id) = shard.get(&page_id) {
+            give_back(pool, buffer_id);
+            return Ok(self.hit(pool, resident_id, ring, is_read_ahead));
+        }
+        let counter = if is_read_ahead {
+            &self.counters.prefetched
+        } else {
+            &self.counters.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let result = {
+            let mut page = pool[buffer_id].buffer.write();
+            self.clear_page(&mut page);
+            self.read_page(page_id, &mut page)
+        };
+        if let Err(err) = result {
+            give_back(pool, buffer_id);
+            return Err(err);
+        }
+        let guard = attach(pool, shard, buffer_id, page_id);
+        if let Some(ring) = ring {
+            ring.lock().advance(buffer_id, page_id);
+        }
+        Ok(guard)
+    }
+
+    pub fn fetch_page_read(&self, page_id: PageId) -> Result<ReadPageGuard, Error> {
+        Ok(self.fetch_page(page_id)?.read())
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard, Error> {
+        Ok(self.fetch_page(page_id)?.write())
+    }
+
+    pub fn create_page(&self) -> Result<WritePageGuard, Error> {
+        self.create(None)
+    }
+
+    pub(crate) fn create(&self, ring: Option<&BufferRing>) -> Result<WritePageGuard, Error> {
+        let ring_victim = ring.and_then(|ring| ring.lock().next_victim());
+        if ring_victim.is_none() {
+            self.grow_if_full();
+        }
+        let guard = {
+            let pool = &**self.pool.read();
+            let buffer_id = self.detach_ring_or_pool_victim(pool, ring_victim)?;
+            let page_id = match self.disk.write().allocate_page() {
+                Ok(page_id) => page_id,
+                Err(err) => {
+                    give_back(pool, buffer_id);
+                    return Err(err.into());
+                }
+            };
+            let buffer = &pool[buffer_id].buffer;
+            self.clear_page(&mut buffer.write());
+            buffer.set_dirty(true);
+            let shard = self.page_table.shard(page_id);
+            let guard = attach(pool, shard, buffer_id, page_id);
+            if let Some(ring) = ring {
+                ring.lock().advance(buffer_id, page_id);
+            }
+            guard
+        };
+        // Latched only once the pool is no longer held, like any other fetch.
+        Ok(guard.write())
+    }
+
+    pub(crate) fn allocate_up_to(&self, page_id: PageId) -> Result<(), Error> {
+        self.disk.write().allocate_up_to(page_id)?;
+        Ok(())
+    }
+
+    pub fn deallocate_page(&self, page_id: PageId) -> Result<(), Error> {
+        let pool = &**self.pool.read();
+        let mut shard = self.page_table.shard(page_id);
+        if let Some(&buffer_id) = shard.get(&page_id) {
+            let frame = &pool[buffer_id];
+            if frame.is_pinned() {
+                return Err(Error::PagePinned(page_id));
+            }
+            frame.buffer.set_page_id(PageId::INVALID_PAGE_ID);
+            frame.buffer.set_dirty(false);
             frame.reset_usage_count();
+            pool.release(buffer_id);
+            shard.remove(&page_id);
+        }
+        self.disk.write().deallocate_page(page_id)?;
+        Ok(())
+    }
+
+    /// Writes the page back if it is in the pool and dirty, and returns
+    /// whether it was.
+    pub fn flush_page(&self, page_id: PageId) -> Result<bool, Error> {
+        let guard = {
+            let pool = self.pool.read();
+            match self.page_table.shard(page_id).get(&page_id) {
+                Some(&buffer_id) => PageGuard::new(pool[buffer_id].buffer.clone()),
+                None => return Ok(false),
+            }
+        };
+        Ok(self.write_back(&[guard])? == 1)
+    }
+
+    /// Writes back every dirty page in the pool, in `PageId` order so that
+    /// the writes land sequentially, and returns how many were written.
+    pub fn flush_all(&self) -> Result<usize, Error> {
+        let mut guards = vec![];
+        {
+            let pool = self.pool.read();
+            for shard in &self.page_table.shards {
+                for &buffer_id in shard.lock().values() {
+                    let buffer = &pool[buffer_id].buffer;
+                    if buffer.is_dirty() {
+                        guards.push(PageGuard::new(buffer.clone()));
+                    }
+                }
+            }
+        }
+        guards.sort_by_key(PageGuard::page_id);
+        self.write_back(&guards)
+    }
+
+    /// Pages in the pool that are dirty, each with the first log record that
+    /// may be missing from it on disk. That is `Lsn::ZERO` for a page changed
+    /// but not logged yet, since its change may be logged before the page is
+    /// marked with it. The pages can change while this runs.
+    pub fn dirty_pages(&self) -> Vec<(PageId, Lsn)> {
+        let pool = self.pool.read();
+        let mut dirty_pages = vec![];
+        for shard in &self.page_table.shards {
+            for (&page_id, &buffer_id) in shard.lock().iter() {
+                let buffer = &pool[buffer_id].buffer;
+                if buffer.is_dirty() {
+                    dirty_pages.push((page_id, buffer.rec_lsn()));
+                }
+            }
+        }
+        dirty_pages
+    }
+
+    /// Writes back the dirty pages among the next `count` frames the pool
+    /// would evict, so that fetching a new page usually finds a clean victim
+    /// and does not have to wait for a write. Returns how many were written.
+    pub fn clean_upcoming_victims(&self, count: usize) -> Result<usize, Error> {
+        let mut guards = vec![];
+        {
+            let pool = self.pool.read();
+            for buffer_id in pool.upcoming_victims(count) {
+                let buffer = &pool[buffer_id].buffer;
+                let page_id = buffer.page_id();
+                if page_id == PageId::INVALID_PAGE_ID || !buffer.is_dirty() {
+                    continue;
+                }
+                // The frame may have been given another page since it was listed.
+                let shard = self.page_table.shard(page_id);
+                if shard.get(&page_id) == Some(&buffer_id) {
+                    guards.push(PageGuard::new(buffer.clone()));
+                }
+            }
+        }
+        guards.sort_by_key(PageGuard::page_id);
+        self.write_back(&guards)
+    }
+
+    /// Grows the pool to `pool_size` frames, or shrinks it by evicting the
+    /// pages held by the frames past that size, writing back the dirty ones
+    /// first. Fails with `EmptyPool` for a size of zero and with `PagePinned`
+    /// if one of those pages is pinned, and leaves the pool at its old size
+    /// on any error. Waits for every fetch,
+    /// creation and flush in progress, and holds off new ones until it is
+    /// done.
+    pub fn resize(&self, pool_size: usize) -> Result<(), Error> {
+        if pool_size == 0 {
+            return Err(Error::EmptyPool);
+        }
+        let mut pool = self.pool.write();
+        let dropped: Vec<_> = (pool_size..pool.size()).map(BufferId::new).collect();
+        if let Some(&buffer_id) = dropped.iter().find(|&&id| pool[id].is_pinned()) {
+            return Err(Error::PagePinned(pool[buffer_id].buffer.page_id()));
+        }
+        for &buffer_id in &dropped {
+            let buffer = &pool[buffer_id].buffer;
+            if buffer.is_dirty() {
+                self.write_page(buffer.page_id(), &mut buffer.write())?;
+                buffer.set_dirty(false);
+            }
+        }
+        for &buffer_id in &dropped {
+            let buffer = &pool[buffer_id].buffer;
+            let page_id = buffer.page_id();
+            if page_id != PageId::INVALID_PAGE_ID {
+                pool.release(buffer_id);
+                self.counters.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        pool.resize(pool_size);
+        self.page_table.rebuild(&**pool);
+        Ok(())
+    }
+
+    /// Adds frames to a pool with a memory budget once every frame holds a
+    /// page, doubling it up to the budget.
+    fn grow_if_full(&self) {
+        let Some(max_pool_size) = self.max_pool_size else {
+            return;
+        };
+        let can_grow = |pool_size| pool_size < max_pool_size && self.page_table.len() >= pool_size;
+        if !can_grow(self.pool.read().size()) {
+            return;
+        }
+        let mut pool = self.pool.write();
+        let pool_size = pool.size();
+        if can_grow(pool_size) {
+            let grown_size = (pool_size * 2).max(Self::MIN_GROWN_POOL_SIZE);
+            pool.resize(grown_size.min(max_pool_size));
         }
-        let buffer = frame.use_buffer();
-        self.page_table.remove(&evict_page_id);
-        self.page_table.insert(page_id, buffer_id);
-        Ok(buffer)
-    }
-
-    pub fn create_page(&mut self) -> Result<Rc<Buffer>, Error> {
-        let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
-        let frame = &mut self.pool[buffer_id];
-        let evict_page_id = frame.buffer.page_id;
-        let page_id = {
-            let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
-            if buffer.is_dirty.get() {
-                self.disk
-                    .write_page_data(evict_page_id, buffer.page.get_mut())?;
-            }
-            let page_id = self.disk.allocate_page();
-            *buffer = Buffer::default();
-            buffer.page_id = page_id;
-            buffer.is_dirty.set(true);
-            page_id
+    }
+
+    /// Writes the pages that are still dirty in one batch and syncs them.
+    /// Only then are they marked clean, unless they changed meanwhile.
+    fn write_back(&self, guards: &[PageGuard]) -> Result<usize, Error> {
+        let pages: Vec<_> = guards
+            .iter()
+            .filter_map(|guard| {
+                let page = guard.read();
+                if !guard.is_dirty() {
+                    return None;
+                }
+                let version = guard.buffer().version();
+                let mut page = page.page().clone();
+                disk::stamp_checksum(guard.page_id(), &mut page);
+                Some((guard, version, page))
+            })
+            .collect();
+        if pages.is_empty() {
+            return Ok(0);
+        }
+        let lsn = pages.iter().map(|(_, _, page)| wal::page_lsn(page)).max();
+        self.flush_log(lsn.unwrap())?;
+        let batch: Vec<_> = pages
+            .iter()
+            .map(|(guard, _, page)| (guard.page_id(), &page[..]))
+            .collect();
+        {
+            let mut disk = self.disk.write();
+            disk.write_pages_data(&batch)?;
+            disk.sync()?;
+        }
+        for (guard, version, _) in &pages {
+            let _page = guard.read();
+            guard.buffer().set_clean_since(*version);
+        }
+        Ok(pages.len())
+    }
+
+    /// Makes every page written so far durable, including those written
+    /// without a sync when they were evicted.
+    pub fn sync(&self) -> Result<(), Error> {
+        self.disk.write().sync()?;
+        Ok(())
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool.read().size()
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        let pool = self.pool.read();
+        let pinned_frames = (0..pool.size())
+            .filter(|&id| pool[BufferId::new(id)].is_pinned())
+            .count();
+        self.counters.stats(pinned_frames)
+    }
+
+    /// Looks at every frame of the pool in turn. Frames can change while
+    /// this runs, so the result is not a consistent snapshot of the pool.
+    pub fn frames(&self) -> Vec<FrameInfo> {
+        let pool = self.pool.read();
+        (0..pool.size())
+            .map(BufferId::new)
+            .map(|buffer_id| FrameInfo::new(buffer_id, &pool[buffer_id]))
+            .collect()
+    }
+
+    fn hit(
+        &self,
+        pool: &dyn BufferPool,
+        buffer_id: BufferId,
+        ring: Option<&BufferRing>,
+        is_read_ahead: bool,
+    ) -> PageGuard {
+        let frame = &pool[buffer_id];
+        // Read-ahead finding its page in the pool already is no access to it.
+        if is_read_ahead {
+            return PageGuard::new(frame.buffer.clone());
+        }
+        self.counters.hits.fetch_add(1, Ordering::Relaxed);
+        // A scan going over a page counts as one use of it at most, so that
+        // it does not keep the page from its ring or from the pool.
+        if ring.is_some() {
+            let _ = frame
+                .usage_count
+                .compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed);
+            return PageGuard::new(frame.buffer.clone());
+        }
+        let guard = frame.pin();
+        pool.touch(buffer_id);
+        guard
+    }
+
+    /// Takes back the frame a ring is due to reuse, as long as it still holds
+    /// the page the ring loaded into it and no one else has used that page
+    /// since. Otherwise the ring is given a victim of the pool.
+    fn detach_ring_or_pool_victim(
+        &self,
+        pool: &dyn BufferPool,
+        ring_victim: Option<(BufferId, PageId)>,
+    ) -> Result<BufferId, Error> {
+        let Some((buffer_id, page_id)) = ring_victim else {
+            return self.detach_victim(pool);
         };
-        let page = frame.use_buffer();
-        self.page_table.remove(&evict_page_id);
-        self.page_table.insert(page_id, buffer_id);
-        Ok(page)
+        // The pool may have shrunk since the ring loaded the page.
+        if buffer_id.value() >= pool.size() || !pool[buffer_id].try_claim() {
+            return self.detach_victim(pool);
+        }
+        let frame = &pool[buffer_id];
+        if frame.buffer.page_id() == page_id && frame.usage_count.load(Ordering::Relaxed) <= 1 {
+            match self.detach(pool, buffer_id) {
+                Ok(true) => return Ok(buffer_id),
+                Ok(false) => {}
+                Err(err) => {
+                    frame.unclaim();
+                    return Err(err);
+                }
+            }
+        }
+        frame.unclaim();
+        self.detach_victim(pool)
     }
 
-    pub fn flush(&mut self) -> Result<(), Error> {
-        for (&page_id, &buffer_id) in self.page_table.iter() {
-            let frame = &self.pool[buffer_id];
-            let mut page = frame.buffer.page.borrow_mut();
-            self.disk.write_page_data(page_id, page.as_mut())?;
-            frame.buffer.is_dirty.set(false);
+    /// Claims a frame from the pool and detaches it from the page it held,
+    /// writing that page back first if it is dirty.
+    fn detach_victim(&self, pool: &dyn BufferPool) -> Result<BufferId, Error> {
+        loop {
+            let Some(buffer_id) = pool.evict() else {
+                self.counters
+                    .no_free_buffer_errors
+                    .fetch_add(1, Ordering::Relaxed);
+                return Err(Error::NoFreeBuffer);
+            };
+            match self.detach(pool, buffer_id) {
+                Ok(true) => return Ok(buffer_id),
+                Ok(false) => pool[buffer_id].unclaim(),
+                Err(err) => {
+                    pool[buffer_id].unclaim();
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Returns `false` if the page in the frame was pinned or replaced after
+    /// the pool picked it, in which case another victim has to be found.
+    fn detach(&self, pool: &dyn BufferPool, buffer_id: BufferId) -> Result<bool, Error> {
+        let frame = &pool[buffer_id];
+        let page_id = frame.buffer.page_id();
+        let mut shard = self.page_table.shard(page_id);
+        if frame.buffer.page_id() != page_id || frame.is_pinned() {
+            return Ok(false);
+        }
+        if page_id == PageId::INVALID_PAGE_ID {
+            return Ok(true);
+        }
+        if frame.buffer.is_dirty() {
+            self.write_page(page_id, &mut frame.buffer.write())?;
+            frame.buffer.set_dirty(false);
+            self.counters
+                .dirty_write_backs
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        shard.remove(&page_id);
+        frame.buffer.set_page_id(PageId::INVALID_PAGE_ID);
+        self.counters.evictions.fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Gives a detached frame a zeroed page image of the usable page size,
+    /// reusing the current one when the size matches.
+    fn clear_page(&self, page: &mut Page) {
+        let page_size = self.disk.read().usable_page_size();
+        if page.len() == page_size {
+            page.fill(0);
+        } else {
+            *page = Page::new(page_size);
+        }
+    }
+
+    fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), Error> {
+        self.disk.read().read_page_data(page_id, page)?;
+        // A page allocated but never written reads back as zeros, which
+        // recovery may find when the log formats it.
+        if !disk::verify_checksum(page_id, page) && page.iter().any(|&byte| byte != 0) {
+            return Err(Error::ChecksumMismatch { page_id });
         }
-        self.disk.sync()?;
+        Ok(())
+    }
+
+    /// Makes sure the log records up to `lsn` are in the log before a page
+    /// they apply to is written.
+    fn flush_log(&self, lsn: Lsn) -> Result<(), Error> {
+        if let Some(log) = &self.log {
+            log.flush(lsn)?;
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: PageId, page: &mut Page) -> Result<(), Error> {
+        self.flush_log(wal::page_lsn(page))?;
+        disk::stamp_checksum(page_id, page);
+        self.disk.write().write_page_data(page_id, page)?;
         Ok(())
     }
 }
 
+/// Publishes `page_id` in its claimed frame and pins it for the caller.
+fn attach(
+    pool: &dyn BufferPool,
+    mut shard: MutexGuard<'_, HashMap<PageId, BufferId>>,
+    buffer_id: BufferId,
+    page_id: PageId,
+) -> PageGuard {
+    let frame = &pool[buffer_id];
+    frame.buffer.set_page_id(page_id);
+    frame.reset_usage_count();
+    let guard = frame.pin();
+    pool.admit(buffer_id, page_id);
+    shard.insert(page_id, buffer_id);
+    frame.unclaim();
+    guard
+}
+
+/// Leaves a detached frame free.
+fn give_back(pool: &dyn BufferPool, buffer_id: BufferId) {
+    pool.release(buffer_id);
+    pool[buffer_id].unclaim();
+}
+
 #[cfg(test)]
 mod buffer_pool_manager_test {
     use super::*;
+    use crate::disk::MemoryStore;
 
     mod fetch_page {
         use super::*;
-        use crate::buffer::{BufferId, ClockSweepBufferPool, Frame};
-        use std::{
-            cell::{Cell, RefCell},
-            fs::remove_file,
-        };
+        use crate::buffer::{Buffer, BufferId, ClockSweepBufferPool, Frame};
+        use std::sync::{atomic::AtomicU64, Arc};
 
         #[test]
         fn バッファプールに存在しないページを読み込もうとした場合ディスクから読み込みバッファプールに書き込んだ後ページの内容を返すこと(
         ) {
             // Arrange
-            let file_path = "buffer_pool_manager_test::fetch_page::0.txt";
-            let page_id = PageId::new(0);
-            let data = ['a' as u8; DiskManager::PAGE_SIZE];
-            let mut buffer_pool_manager = {
-                let mut disk = DiskManager::open(file_path).unwrap();
+            let page_id = PageId::new(1);
+            let mut data = Page::from(&[b'a'; DiskManager::DEFAULT_PAGE_SIZE][..]);
+            disk::stamp_checksum(page_id, &mut data);
+            let buffer_pool_manager = {
+                let mut disk = DiskManager::new(MemoryStore::default()).unwrap();
                 disk.write_page_data(page_id, &data).unwrap();
                 let pool = ClockSweepBufferPool::from(3);
                 BufferPoolManager::new(disk, pool)
             };
 
             // Act
-            let buffer = buffer_pool_manager.fetch_page(page_id).unwrap();
+            let guard = buffer_pool_manager.fetch_page(page_id).unwrap();
 
             // Assert
-            assert_eq!(buffer.page_id, page_id);
-            assert_eq!(buffer.page, RefCell::new(data));
+            assert_eq!(guard.page_id(), page_id);
+            assert_eq!(*guard.read().page(), data);
             assert_eq!(
-                *buffer_pool_manager.page_table.get(&page_id).unwrap(),
-                BufferId::new(0)
+                buffer_pool_manager.page_table.get(page_id),
+                Some(BufferId::new(0))
             );
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn ディスク上のページが壊れている場合ChecksumMismatchを返すこと() {
+            // Arrange
+            let page_id = PageId::new(1);
+            let mut data = Page::from(&[b'a'; DiskManager::DEFAULT_PAGE_SIZE][..]);
+            disk::stamp_checksum(page_id, &mut data);
+            data[DiskManager::DEFAULT_PAGE_SIZE - 1] = b'b';
+            let buffer_pool_manager = {
+                let mut disk = DiskManager::new(MemoryStore::default()).unwrap();
+                disk.write_page_data(page_id, &data).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
 
-            // Cleanup
-            remove_file(file_path).unwrap();
+            // Act
+            let result = buffer_pool_manager.fetch_page(page_id);
+
+            // Assert
+            assert!(
+                matches!(result, Err(Error::ChecksumMismatch { page_id: id }) if id == page_id)
+            );
+            assert_eq!(buffer_pool_manager.page_table.get(page_id), None);
         }
 
         #[test]
         fn ページがバッファプールに存在する場合バッファプールの内容を読み込むこと() {
             // Arrange
-            let file_path = "buffer_pool_manager_test::fetch_page::1.txt";
             let page_id = PageId::new(0);
-            let data = ['a' as u8; DiskManager::PAGE_SIZE];
+            let data = Page::from(&[b'a'; DiskManager::DEFAULT_PAGE_SIZE][..]);
             let buffer_id = BufferId::new(0);
-            let mut buffer_pool_manager = {
-                let disk = DiskManager::open(file_path).unwrap();
-                let frame = {
-                    let buffer = Buffer {
-                        page_id,
-                        page: RefCell::new(data),
-                        is_dirty: Cell::new(false),
-                    };
-                    Frame {
-                        usage_count: 1,
-                        buffer: Rc::new(buffer),
-                    }
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let frame = Frame {
+                    usage_count: AtomicU64::new(1),
+                    buffer: Arc::new(Buffer::new(page_id, data.clone())),
+                    ..Default::default()
                 };
                 let mut pool = ClockSweepBufferPool::from(1);
                 pool.buffers = vec![frame];
                 BufferPoolManager::new(disk, pool)
             };
-            buffer_pool_manager.page_table.insert(page_id, buffer_id);
+            buffer_pool_manager
+                .page_table
+                .shard(page_id)
+                .insert(page_id, buffer_id);
+
+            // Act
+            let guard = buffer_pool_manager.fetch_page(page_id).unwrap();
+
+            // Assert
+            assert_eq!(guard.page_id(), page_id);
+            assert_eq!(*guard.read().page(), data);
+            assert!(!guard.is_dirty());
+        }
+
+        #[test]
+        fn 複数のスレッドから読み書きしてもページの内容が失われないこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(8);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_ids: Vec<_> = (0..64u8)
+                .map(|value| {
+                    let mut guard = buffer_pool_manager.create_page().unwrap();
+                    guard.fill(value);
+                    guard.page_id()
+                })
+                .collect();
+
+            // Act
+            std::thread::scope(|scope| {
+                for thread_id in 0..4 {
+                    let buffer_pool_manager = &buffer_pool_manager;
+                    let page_ids = &page_ids;
+                    scope.spawn(move || {
+                        for i in 0..500 {
+                            let value = (i * 7 + thread_id) % page_ids.len();
+                            let page_id = page_ids[value];
+                            if i % 3 == 0 {
+                                let mut guard =
+                                    buffer_pool_manager.fetch_page_write(page_id).unwrap();
+                                guard.fill(value as u8);
+                            }
+                            let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                            assert!(guard.iter().all(|&byte| byte == value as u8));
+                        }
+                    });
+                }
+            });
+
+            // Assert
+            for (value, &page_id) in page_ids.iter().enumerate() {
+                let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                assert!(guard.iter().all(|&byte| byte == value as u8));
+            }
+        }
+    }
+
+    #[test]
+    fn スレッド間で共有できること() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BufferPoolManager>();
+    }
+
+    mod create_page {
+        use super::*;
+        use crate::{buffer::ClockSweepBufferPool, disk::Options};
+
+        #[test]
+        fn データベースのページサイズで初期化されたページを返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let options = Options {
+                    page_size: 32768,
+                    ..Default::default()
+                };
+                let disk = DiskManager::with_options(MemoryStore::default(), options).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
 
             // Act
-            let buffer = buffer_pool_manager.fetch_page(page_id).unwrap();
+            let guard = buffer_pool_manager.create_page().unwrap();
 
             // Assert
-            assert_eq!(buffer.page_id, page_id);
-            assert_eq!(buffer.page, RefCell::new(data));
-            assert_eq!(buffer.is_dirty.get(), false);
+            assert_eq!(guard.page().len(), 32768);
+            assert!(guard.page().iter().all(|&byte| byte == 0));
+            let buffer_id = buffer_pool_manager.page_table.get(guard.page_id()).unwrap();
+            assert!(buffer_pool_manager.pool.read()[buffer_id].buffer.is_dirty());
+        }
+    }
+
+    mod deallocate_page {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
 
-            // Cleanup
-            remove_file(file_path).unwrap();
+        #[test]
+        fn 解放したページがcreate_pageで再利用されること() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_id = buffer_pool_manager.create_page().unwrap().page_id();
+
+            // Act
+            buffer_pool_manager.deallocate_page(page_id).unwrap();
+            let guard = buffer_pool_manager.create_page().unwrap();
+
+            // Assert
+            assert_eq!(guard.page_id(), page_id);
+            assert_eq!(buffer_pool_manager.page_table.len(), 1);
+        }
+
+        #[test]
+        fn 参照されているページを解放しようとした場合エラーを返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let guard = buffer_pool_manager.create_page().unwrap();
+
+            // Act
+            let result = buffer_pool_manager.deallocate_page(guard.page_id());
+
+            // Assert
+            assert!(
+                matches!(result, Err(Error::PagePinned(page_id)) if page_id == guard.page_id())
+            );
+        }
+    }
+
+    mod stats {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+
+        #[test]
+        fn ヒットとミスと追い出しの回数を数えること() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(1);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_id = buffer_pool_manager.create_page().unwrap().page_id();
+            buffer_pool_manager.fetch_page(page_id).unwrap();
+            buffer_pool_manager.create_page().unwrap();
+            let _guard = buffer_pool_manager.fetch_page(page_id).unwrap();
+            let result = buffer_pool_manager.create_page();
+
+            // Act
+            let stats = buffer_pool_manager.stats();
+
+            // Assert
+            assert!(matches!(result, Err(Error::NoFreeBuffer)));
+            assert_eq!(
+                stats,
+                BufferPoolStats {
+                    hits: 1,
+                    misses: 1,
+                    prefetched: 0,
+                    evictions: 2,
+                    dirty_write_backs: 2,
+                    no_free_buffer_errors: 1,
+                    pinned_frames: 1,
+                }
+            );
+        }
+    }
+
+    mod frames {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+
+        #[test]
+        fn 各フレームの状態を返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(2);
+                BufferPoolManager::new(disk, pool)
+            };
+            let guard = buffer_pool_manager.create_page().unwrap();
+
+            // Act
+            let mut frames = buffer_pool_manager.frames();
+
+            // Assert
+            frames.sort_by_key(|frame| frame.page_id.is_none());
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].page_id, Some(guard.page_id()));
+            assert_eq!(frames[0].usage_count, 1);
+            assert_eq!(frames[0].pin_count, 1);
+            assert!(frames[0].is_dirty);
+            assert_eq!(frames[1].page_id, None);
+            assert_eq!(frames[1].pin_count, 0);
+            assert_ne!(frames[0].buffer_id, frames[1].buffer_id);
+        }
+    }
+
+    mod prefetch {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+        use std::{
+            thread,
+            time::{Duration, Instant},
+        };
+
+        fn next_page_id(body: &[u8]) -> Option<PageId> {
+            PageId::new(u64::from_le_bytes(body[..8].try_into().unwrap())).valid()
+        }
+
+        #[test]
+        fn 辿ったページを読み込む前にバッファプールに読み込むこと() {
+            // Arrange
+            let store = MemoryStore::default();
+            let page_ids = {
+                let buffer_pool_manager = {
+                    let disk = DiskManager::new(store.clone()).unwrap();
+                    let pool = ClockSweepBufferPool::from(4);
+                    BufferPoolManager::new(disk, pool)
+                };
+                let mut guards: Vec<_> = (0..4)
+                    .map(|_| buffer_pool_manager.create_page().unwrap())
+                    .collect();
+                let page_ids: Vec<_> = guards.iter().map(|guard| guard.page_id()).collect();
+                for (guard, next) in guards.iter_mut().zip(page_ids.iter().skip(1)) {
+                    guard[..8].copy_from_slice(&next.value().to_le_bytes());
+                }
+                guards[3][..8].copy_from_slice(&PageId::INVALID_PAGE_ID.value().to_le_bytes());
+                drop(guards);
+                buffer_pool_manager.flush_all().unwrap();
+                page_ids
+            };
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(store).unwrap();
+                let pool = ClockSweepBufferPool::from(8);
+                Arc::new(BufferPoolManager::new(disk, pool))
+            };
+            buffer_pool_manager.start_read_ahead(1);
+
+            // Act
+            buffer_pool_manager.prefetch(page_ids[0], 8, next_page_id);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while buffer_pool_manager.stats().prefetched < 4 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            for &page_id in &page_ids {
+                buffer_pool_manager.fetch_page(page_id).unwrap();
+            }
+
+            // Assert
+            let stats = buffer_pool_manager.stats();
+            assert_eq!(stats.prefetched, 4);
+            assert_eq!(stats.hits, 4);
+            assert_eq!(stats.misses, 0);
+        }
+
+        #[test]
+        fn 先読みを開始していない場合何もしないこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                BufferPoolManager::new(disk, pool)
+            };
+
+            // Act
+            buffer_pool_manager.prefetch(PageId::new(1), 8, next_page_id);
+
+            // Assert
+            assert_eq!(buffer_pool_manager.stats().prefetched, 0);
+            assert_eq!(buffer_pool_manager.page_table.len(), 0);
+        }
+    }
+
+    mod with_ring {
+        use super::*;
+        use crate::buffer::{BufferRing, ClockSweepBufferPool};
+
+        fn manager_with_pages(pool_size: usize, page_count: usize) -> BufferPoolManager {
+            let store = MemoryStore::default();
+            {
+                let disk = DiskManager::new(store.clone()).unwrap();
+                let pool = ClockSweepBufferPool::from(1);
+                let buffer_pool_manager = BufferPoolManager::new(disk, pool);
+                for _ in 0..page_count {
+                    buffer_pool_manager.create_page().unwrap();
+                }
+                buffer_pool_manager.flush_all().unwrap();
+            }
+            let disk = DiskManager::new(store).unwrap();
+            let pool = ClockSweepBufferPool::from(pool_size);
+            BufferPoolManager::new(disk, pool)
+        }
+
+        #[test]
+        fn リングを通して読み込んだページはリングのフレームだけを使い回すこと() {
+            // Arrange
+            let buffer_pool_manager = manager_with_pages(8, 24);
+            let hot_page_ids: Vec<_> = (0..4).map(PageId::new).collect();
+            for _ in 0..2 {
+                for &page_id in &hot_page_ids {
+                    buffer_pool_manager.fetch_page(page_id).unwrap();
+                }
+            }
+            let ring = BufferRing::new(2);
+
+            // Act
+            let scan = buffer_pool_manager.with_ring(&ring);
+            for page_id in (4..24).map(PageId::new) {
+                scan.fetch_page(page_id).unwrap();
+            }
+
+            // Assert
+            for &page_id in &hot_page_ids {
+                assert!(buffer_pool_manager.page_table.get(page_id).is_some());
+            }
+            assert_eq!(buffer_pool_manager.page_table.len(), 6);
+            assert_eq!(buffer_pool_manager.stats().evictions, 18);
+        }
+
+        #[test]
+        fn リングで読み込んだ後に他で使われたページはリングで使い回さないこと() {
+            // Arrange
+            let buffer_pool_manager = manager_with_pages(4, 8);
+            let ring = BufferRing::new(2);
+            let scan = buffer_pool_manager.with_ring(&ring);
+            scan.fetch_page(PageId::new(0)).unwrap();
+            scan.fetch_page(PageId::new(1)).unwrap();
+            buffer_pool_manager.fetch_page(PageId::new(0)).unwrap();
+
+            // Act
+            for page_id in (2..8).map(PageId::new) {
+                scan.fetch_page(page_id).unwrap();
+            }
+
+            // Assert
+            assert!(buffer_pool_manager.page_table.get(PageId::new(0)).is_some());
+            assert!(buffer_pool_manager.page_table.get(PageId::new(1)).is_none());
+        }
+    }
+
+    mod resize {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+
+        #[test]
+        fn 拡張したフレームにページを読み込めること() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(2);
+                BufferPoolManager::new(disk, pool)
+            };
+            let mut guards: Vec<_> = (0..2)
+                .map(|_| buffer_pool_manager.create_page().unwrap())
+                .collect();
+
+            // Act
+            buffer_pool_manager.resize(4).unwrap();
+            guards.extend((0..2).map(|_| buffer_pool_manager.create_page().unwrap()));
+
+            // Assert
+            assert_eq!(buffer_pool_manager.pool_size(), 4);
+            assert_eq!(buffer_pool_manager.page_table.len(), 4);
+            assert!(matches!(
+                buffer_pool_manager.create_page(),
+                Err(Error::NoFreeBuffer)
+            ));
+        }
+
+        #[test]
+        fn 縮小したフレームのページを書き戻してから追い出すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_ids: Vec<_> = (0..4u8)
+                .map(|value| {
+                    let mut guard = buffer_pool_manager.create_page().unwrap();
+                    guard.fill(value);
+                    guard.page_id()
+                })
+                .collect();
+
+            // Act
+            buffer_pool_manager.resize(2).unwrap();
+
+            // Assert
+            assert_eq!(buffer_pool_manager.pool_size(), 2);
+            assert_eq!(buffer_pool_manager.page_table.len(), 2);
+            for (value, &page_id) in page_ids.iter().enumerate() {
+                let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                assert!(guard.iter().all(|&byte| byte == value as u8));
+            }
+        }
+
+        #[test]
+        fn 読み書きしている間に大きさを変えてもページの内容が失われないこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(8);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_ids: Vec<_> = (0..64u8)
+                .map(|value| {
+                    let mut guard = buffer_pool_manager.create_page().unwrap();
+                    guard.fill(value);
+                    guard.page_id()
+                })
+                .collect();
+
+            // Act
+            std::thread::scope(|scope| {
+                for thread_id in 0..4 {
+                    let buffer_pool_manager = &buffer_pool_manager;
+                    let page_ids = &page_ids;
+                    scope.spawn(move || {
+                        for i in 0..500 {
+                            let value = (i * 7 + thread_id) % page_ids.len();
+                            let page_id = page_ids[value];
+                            if i % 3 == 0 {
+                                let mut guard =
+                                    buffer_pool_manager.fetch_page_write(page_id).unwrap();
+                                guard.fill(value as u8);
+                            }
+                            let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                            assert!(guard.iter().all(|&byte| byte == value as u8));
+                        }
+                    });
+                }
+                for i in 0..50 {
+                    // Shrinking fails whenever a dropped frame is in use.
+                    let _ = buffer_pool_manager.resize(8 + i % 3 * 8);
+                }
+            });
+
+            // Assert
+            for (value, &page_id) in page_ids.iter().enumerate() {
+                let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                assert!(guard.iter().all(|&byte| byte == value as u8));
+            }
+        }
+
+        #[test]
+        fn 縮小するフレームのページが参照されている場合エラーを返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(2);
+                BufferPoolManager::new(disk, pool)
+            };
+            let guards: Vec<_> = (0..2)
+                .map(|_| buffer_pool_manager.create_page().unwrap())
+                .collect();
+
+            // Act
+            let result = buffer_pool_manager.resize(1);
+
+            // Assert
+            assert!(matches!(result, Err(Error::PagePinned(_))));
+            assert_eq!(buffer_pool_manager.pool_size(), 2);
+            drop(guards);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn フレーム数を0にしようとした場合EmptyPoolを返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(2);
+                BufferPoolManager::new(disk, pool)
+            };
+
+            // Act
+            let result = buffer_pool_manager.resize(0);
+
+            // Assert
+            assert!(matches!(result, Err(Error::EmptyPool)));
+            assert_eq!(buffer_pool_manager.pool_size(), 2);
+        }
+    }
+
+    mod with_memory_budget {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+
+        #[test]
+        fn 予算に達するまでは追い出さずにフレームを増やすこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let budget = 20 * disk.usable_page_size();
+                let pool = ClockSweepBufferPool::from(0);
+                BufferPoolManager::with_memory_budget(disk, pool, budget)
+            };
+
+            // Act
+            let first_pool_size = {
+                buffer_pool_manager.create_page().unwrap();
+                buffer_pool_manager.pool_size()
+            };
+            for _ in 1..20 {
+                buffer_pool_manager.create_page().unwrap();
+            }
+            let evictions_at_budget = buffer_pool_manager.stats().evictions;
+            buffer_pool_manager.create_page().unwrap();
+
+            // Assert
+            assert_eq!(first_pool_size, 16);
+            assert_eq!(evictions_at_budget, 0);
+            assert_eq!(buffer_pool_manager.pool_size(), 20);
+            assert_eq!(buffer_pool_manager.stats().evictions, 1);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 予算を使い切っている場合NoFreeBufferを返すこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let budget = 2 * disk.usable_page_size();
+                let pool = ClockSweepBufferPool::from(0);
+                BufferPoolManager::with_memory_budget(disk, pool, budget)
+            };
+            let _guards = [
+                buffer_pool_manager.create_page().unwrap(),
+                buffer_pool_manager.create_page().unwrap(),
+            ];
+
+            // Act
+            let result = buffer_pool_manager.create_page();
+
+            // Assert
+            assert!(matches!(result, Err(Error::NoFreeBuffer)));
+            assert_eq!(buffer_pool_manager.pool_size(), 2);
+        }
+    }
+
+    mod flush_page {
+        use super::*;
+        use crate::buffer::ClockSweepBufferPool;
+
+        #[test]
+        fn ダーティなページだけを書き込むこと() {
+            // Arrange
+            let store = MemoryStore::default();
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(store.clone()).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_id = {
+                let mut guard = buffer_pool_manager.create_page().unwrap();
+                guard.fill(b'a');
+                guard.page_id()
+            };
+
+            // Act
+            let first = buffer_pool_manager.flush_page(page_id).unwrap();
+            let second = buffer_pool_manager.flush_page(page_id).unwrap();
+
+            // Assert
+            assert!(first);
+            assert!(!second);
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(store).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let guard = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+            assert!(guard.iter().all(|&byte| byte == b'a'));
+        }
+
+        #[test]
+        fn バッファプールに存在しないページの場合何もしないこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+
+            // Act
+            let actual = buffer_pool_manager.flush_page(PageId::new(1)).unwrap();
+
+            // Assert
+            assert!(!actual);
+        }
+    }
+
+    mod flush_all {
+        use super::*;
+        use crate::{buffer::ClockSweepBufferPool, disk::FaultInjectingStore};
+
+        #[test]
+        fn ダーティなページの数を返しクリーンなページを書き込まないこと() {
+            // Arrange
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_ids: Vec<_> = (0..3)
+                .map(|_| buffer_pool_manager.create_page().unwrap().page_id())
+                .collect();
+
+            // Act
+            let first = buffer_pool_manager.flush_all().unwrap();
+            let second = buffer_pool_manager.flush_all().unwrap();
+            buffer_pool_manager
+                .fetch_page_write(page_ids[1])
+                .unwrap()
+                .fill(b'a');
+            let third = buffer_pool_manager.flush_all().unwrap();
+
+            // Assert
+            assert_eq!(first, 3);
+            assert_eq!(second, 0);
+            assert_eq!(third, 1);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 書き込み途中でクラッシュしたページは再度開いた後ChecksumMismatchとなること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let (faulty_store, injector) = FaultInjectingStore::new(store.clone());
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(faulty_store).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_id = {
+                let mut guard = buffer_pool_manager.create_page().unwrap();
+                guard.fill(b'a');
+                guard.page_id()
+            };
+            injector.tear_next_write(DiskManager::DEFAULT_PAGE_SIZE / 2);
+
+            // Act
+            let result = buffer_pool_manager.flush_all();
+
+            // Assert
+            assert!(result.is_err());
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(store).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            assert!(matches!(
+                buffer_pool_manager.fetch_page(page_id),
+                Err(Error::ChecksumMismatch { page_id: id }) if id == page_id
+            ));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 同期に失敗した場合ページはダーティなまま最初のLSNを保つこと() {
+            // Arrange
+            let (faulty_store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            let buffer_pool_manager = {
+                let disk = DiskManager::new(faulty_store).unwrap();
+                let pool = ClockSweepBufferPool::from(3);
+                BufferPoolManager::new(disk, pool)
+            };
+            let page_id = {
+                let mut guard = buffer_pool_manager.create_page().unwrap();
+                guard.set_lsn(Lsn::new(7));
+                guard.page_id()
+            };
+            injector.fail_syncs(true);
+
+            // Act
+            let result = buffer_pool_manager.flush_all();
+
+            // Assert
+            assert!(result.is_err());
+            assert_eq!(
+                buffer_pool_manager.dirty_pages(),
+                vec![(page_id, Lsn::new(7))]
+            );
+            injector.clear();
+            assert_eq!(buffer_pool_manager.flush_all().unwrap(), 1);
+            assert_eq!(buffer_pool_manager.dirty_pages(), vec![]);
         }
     }
 }
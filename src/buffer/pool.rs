@@ -1,9 +1,46 @@
-use super::BufferId;
-use std::ops::{Index, IndexMut};
+use super::{BufferId, Frame};
+use crate::disk::PageId;
+use std::ops::Index;
 
+mod arc;
 mod clock_sweep;
+mod lru_k;
+#[cfg(test)]
+mod suite;
+pub use arc::*;
 pub use clock_sweep::*;
+pub use lru_k::*;
 
-pub trait BufferPool: Index<BufferId> + IndexMut<BufferId> {
-    fn evict(&mut self) -> Option<BufferId>;
+/// Replacement policy over a fixed set of frames, shared between the threads
+/// using a `BufferPoolManager`. The manager reports every access through the
+/// hooks below; a pool that keeps its bookkeeping in the frames themselves can
+/// leave them as they are.
+pub trait BufferPool: Index<BufferId, Output = Frame> + Send + Sync {
+    /// Number of frames, indexed from `BufferId::new(0)`.
+    fn size(&self) -> usize;
+
+    /// Grows the pool with free frames, or drops the frames from `pool_size`
+    /// on. The caller makes sure no one is using the pool meanwhile, and that
+    /// every frame dropped has been released.
+    fn resize(&mut self, pool_size: usize);
+
+    /// Picks an unreferenced frame to load a page into and claims it for the
+    /// caller, or returns `None` if every frame is in use. The caller unclaims
+    /// the frame once it has called `admit`, or given up on it.
+    fn evict(&self) -> Option<BufferId>;
+
+    /// Lists up to `count` unreferenced frames in the order `evict` would
+    /// likely pick them, without claiming them or touching the policy state.
+    fn upcoming_victims(&self, count: usize) -> Vec<BufferId>;
+
+    /// Called when the page in `buffer_id` is fetched again.
+    fn touch(&self, _buffer_id: BufferId) {}
+
+    /// Called when `page_id` has been loaded into `buffer_id`, which was
+    /// returned by `evict`. Any page the frame held before is now evicted.
+    fn admit(&self, _buffer_id: BufferId, _page_id: PageId) {}
+
+    /// Called when the page in `buffer_id` has been dropped and the frame is
+    /// free again.
+    fn release(&self, _buffer_id: BufferId) {}
 }
@@ -0,0 +1,351 @@
+use super::BufferPool;
+use crate::{
+    buffer::{BufferId, Frame},
+    disk::PageId,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Index,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum List {
+    /// Pages seen once since they were loaded.
+    Recent,
+    /// Pages seen at least twice.
+    Frequent,
+}
+
+/// Page ids in the order they were evicted, oldest first.
+#[derive(Default)]
+struct Ghosts {
+    order: BTreeMap<u64, PageId>,
+    seqs: HashMap<PageId, u64>,
+}
+
+impl Ghosts {
+    fn len(&self) -> usize {
+        self.seqs.len()
+    }
+
+    fn push(&mut self, seq: u64, page_id: PageId) {
+        self.order.insert(seq, page_id);
+        self.seqs.insert(page_id, seq);
+    }
+
+    fn remove(&mut self, page_id: PageId) -> bool {
+        match self.seqs.remove(&page_id) {
+            Some(seq) => {
+                self.order.remove(&seq);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some((_, page_id)) = self.order.pop_first() {
+            self.seqs.remove(&page_id);
+        }
+    }
+}
+
+/// Adaptive Replacement Cache. Resident pages are split between a list of
+/// pages seen once and a list of pages seen again, and the ids of pages
+/// recently evicted from each are remembered. A miss on one of those ghosts
+/// shifts the target size of the first list towards where it would have
+/// helped, so the pool adapts between recency and frequency while a scan,
+/// seen only once, stays confined to the first list.
+///
+/// Unlike the original algorithm, `evict` does not know which page is about
+/// to be loaded, so it picks the list to evict from by the target size alone.
+/// The victim only becomes a ghost once `admit` confirms it was replaced.
+pub struct ArcBufferPool {
+    pub buffers: Vec<Frame>,
+    lists: Mutex<Lists>,
+}
+
+struct Lists {
+    /// Target number of frames holding pages seen once.
+    target_recent_len: usize,
+    seq: u64,
+    resident: Vec<Option<(List, u64, PageId)>>,
+    /// Frames holding no page. A frame stays here until `admit`, so that one
+    /// given up by the caller of `evict` is not lost.
+    free: BTreeSet<usize>,
+    recent: BTreeMap<u64, BufferId>,
+    frequent: BTreeMap<u64, BufferId>,
+    recent_ghosts: Ghosts,
+    frequent_ghosts: Ghosts,
+}
+
+impl Lists {
+    fn size(&self) -> usize {
+        self.resident.len()
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn list(&self, list: List) -> &BTreeMap<u64, BufferId> {
+        match list {
+            List::Recent => &self.recent,
+            List::Frequent => &self.frequent,
+        }
+    }
+
+    fn list_mut(&mut self, list: List) -> &mut BTreeMap<u64, BufferId> {
+        match list {
+            List::Recent => &mut self.recent,
+            List::Frequent => &mut self.frequent,
+        }
+    }
+
+    fn insert(&mut self, buffer_id: BufferId, list: List, page_id: PageId) {
+        self.free.remove(&buffer_id.value());
+        let seq = self.next_seq();
+        self.list_mut(list).insert(seq, buffer_id);
+        self.resident[buffer_id.value()] = Some((list, seq, page_id));
+    }
+
+    /// Resident frames, least recently used first, starting with the list
+    /// that is over its target.
+    fn eviction_order(&self) -> impl Iterator<Item = BufferId> + '_ {
+        let (first, second) = if self.recent.len() > self.target_recent_len {
+            (List::Recent, List::Frequent)
+        } else {
+            (List::Frequent, List::Recent)
+        };
+        self.list(first)
+            .values()
+            .chain(self.list(second).values())
+            .copied()
+    }
+
+    fn remove(&mut self, buffer_id: BufferId) -> Option<(List, PageId)> {
+        let (list, seq, page_id) = self.resident[buffer_id.value()].take()?;
+        self.list_mut(list).remove(&seq);
+        self.free.insert(buffer_id.value());
+        Some((list, page_id))
+    }
+}
+
+impl BufferPool for ArcBufferPool {
+    fn size(&self) -> usize {
+        self.buffers.len()
+    }
+
+    fn resize(&mut self, pool_size: usize) {
+        self.buffers.resize_with(pool_size, Frame::default);
+        let lists = self.lists.get_mut();
+        let len = lists.resident.len();
+        lists.resident.resize(pool_size, None);
+        lists.free.retain(|&index| index < pool_size);
+        lists.free.extend(len..pool_size);
+        lists.target_recent_len = lists.target_recent_len.min(pool_size);
+        // Forget the oldest ghosts until the lists fit the new size again.
+        while lists.recent.len() + lists.recent_ghosts.len() > pool_size
+            && lists.recent_ghosts.len() > 0
+        {
+            lists.recent_ghosts.pop_oldest();
+        }
+        while lists.recent.len()
+            + lists.frequent.len()
+            + lists.recent_ghosts.len()
+            + lists.frequent_ghosts.len()
+            > 2 * pool_size
+            && lists.frequent_ghosts.len() > 0
+        {
+            lists.frequent_ghosts.pop_oldest();
+        }
+    }
+
+    fn evict(&self) -> Option<BufferId> {
+        let lists = self.lists.lock();
+        let free_id = lists
+            .free
+            .iter()
+            .map(|&index| BufferId::new(index))
+            .find(|&buffer_id| self[buffer_id].try_claim());
+        if free_id.is_some() {
+            return free_id;
+        }
+        let victim_id = lists
+            .eviction_order()
+            .find(|&buffer_id| self[buffer_id].try_claim());
+        victim_id
+    }
+
+    fn upcoming_victims(&self, count: usize) -> Vec<BufferId> {
+        let lists = self.lists.lock();
+        let victim_ids = lists
+            .eviction_order()
+            .filter(|&buffer_id| self[buffer_id].is_evictable())
+            .take(count)
+            .collect();
+        victim_ids
+    }
+
+    fn touch(&self, buffer_id: BufferId) {
+        let mut lists = self.lists.lock();
+        if let Some((_, page_id)) = lists.remove(buffer_id) {
+            lists.insert(buffer_id, List::Frequent, page_id);
+        }
+    }
+
+    fn admit(&self, buffer_id: BufferId, page_id: PageId) {
+        let mut lists = self.lists.lock();
+        if let Some((list, evicted_page_id)) = lists.remove(buffer_id) {
+            let seq = lists.next_seq();
+            match list {
+                List::Recent => lists.recent_ghosts.push(seq, evicted_page_id),
+                List::Frequent => lists.frequent_ghosts.push(seq, evicted_page_id),
+            }
+        }
+        let size = lists.size();
+        let recent_ghosts = lists.recent_ghosts.len();
+        let frequent_ghosts = lists.frequent_ghosts.len();
+        let list = if lists.recent_ghosts.remove(page_id) {
+            let delta = (frequent_ghosts / recent_ghosts).max(1);
+            lists.target_recent_len = (lists.target_recent_len + delta).min(size);
+            List::Frequent
+        } else if lists.frequent_ghosts.remove(page_id) {
+            let delta = (recent_ghosts / frequent_ghosts).max(1);
+            lists.target_recent_len = lists.target_recent_len.saturating_sub(delta);
+            List::Frequent
+        } else {
+            if lists.recent.len() + recent_ghosts >= size {
+                lists.recent_ghosts.pop_oldest();
+            } else if lists.recent.len() + lists.frequent.len() + recent_ghosts + frequent_ghosts
+                >= 2 * size
+            {
+                lists.frequent_ghosts.pop_oldest();
+            }
+            List::Recent
+        };
+        lists.insert(buffer_id, list, page_id);
+    }
+
+    fn release(&self, buffer_id: BufferId) {
+        self.lists.lock().remove(buffer_id);
+    }
+}
+
+impl From<usize> for ArcBufferPool {
+    fn from(pool_size: usize) -> Self {
+        let mut buffers = vec![];
+        buffers.resize_with(pool_size, Frame::default);
+        Self {
+            buffers,
+            lists: Mutex::new(Lists {
+                target_recent_len: 0,
+                seq: 0,
+                resident: vec![None; pool_size],
+                free: (0..pool_size).collect(),
+                recent: BTreeMap::new(),
+                frequent: BTreeMap::new(),
+                recent_ghosts: Ghosts::default(),
+                frequent_ghosts: Ghosts::default(),
+            }),
+        }
+    }
+}
+
+impl Index<BufferId> for ArcBufferPool {
+    type Output = Frame;
+
+    fn index(&self, buffer_id: BufferId) -> &Self::Output {
+        &self.buffers[buffer_id.value()]
+    }
+}
+
+#[cfg(test)]
+mod arc_buffer_pool_test {
+    use super::*;
+    use crate::buffer::pool::suite::{buffer_pool_test_suite, Harness};
+
+    buffer_pool_test_suite!(ArcBufferPool::from);
+
+    mod evict {
+        use super::*;
+
+        #[test]
+        fn スキャンしても繰り返し参照されたページは追い出されないこと() {
+            // Arrange
+            let mut harness = Harness::new(ArcBufferPool::from(4));
+            for _ in 0..2 {
+                harness.fetch(PageId::new(0)).unwrap();
+                harness.fetch(PageId::new(1)).unwrap();
+            }
+
+            // Act
+            for value in 2..100 {
+                harness.fetch(PageId::new(value)).unwrap();
+            }
+
+            // Assert
+            assert!(harness.is_resident(PageId::new(0)));
+            assert!(harness.is_resident(PageId::new(1)));
+        }
+
+        #[test]
+        fn 追い出された直後に再び読み込まれたページは頻度のリストに入ること() {
+            // Arrange
+            let mut harness = Harness::new(ArcBufferPool::from(2));
+            harness.fetch(PageId::new(0)).unwrap();
+            harness.fetch(PageId::new(0)).unwrap();
+            harness.fetch(PageId::new(1)).unwrap();
+            harness.fetch(PageId::new(2)).unwrap();
+            assert!(!harness.is_resident(PageId::new(1)));
+
+            // Act
+            harness.fetch(PageId::new(1)).unwrap();
+
+            // Assert
+            let buffer_id = harness.page_table[&PageId::new(1)];
+            let lists = harness.pool.lists.lock();
+            assert!(matches!(
+                lists.resident[buffer_id.value()],
+                Some((List::Frequent, _, _))
+            ));
+            assert_eq!(lists.target_recent_len, 1);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 読み込みを諦めた空きFrameを再び返すこと() {
+            // Arrange
+            let pool = ArcBufferPool::from(1);
+            let buffer_id = pool.evict().unwrap();
+            pool[buffer_id].unclaim();
+
+            // Act
+            let victim_id = pool.evict();
+
+            // Assert
+            assert_eq!(victim_id, Some(buffer_id));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 解放したFrameを最初に返すこと() {
+            // Arrange
+            let mut harness = Harness::new(ArcBufferPool::from(2));
+            for _ in 0..2 {
+                harness.fetch(PageId::new(0)).unwrap();
+                harness.fetch(PageId::new(1)).unwrap();
+            }
+            let buffer_id = harness.page_table[&PageId::new(1)];
+            harness.pool.release(buffer_id);
+
+            // Act
+            let victim_id = harness.pool.evict();
+
+            // Assert
+            assert_eq!(victim_id, Some(buffer_id));
+        }
+    }
+}
@@ -1,45 +1,70 @@
 use super::BufferPool;
 use crate::buffer::{BufferId, Frame};
-use std::ops::{Index, IndexMut};
-
+use std::{
+    ops::Index,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Clock sweep over the usage counts kept in the frames. The clock hand is an
+/// atomic counter, so threads evicting at the same time sweep different
+/// frames instead of waiting on each other.
 pub struct ClockSweepBufferPool {
     pub buffers: Vec<Frame>,
-    pub next_victim_id: BufferId,
+    next_victim_id: AtomicUsize,
 }
 
 impl ClockSweepBufferPool {
+    fn advance_next_victim_id(&self) -> BufferId {
+        BufferId::new(self.next_victim_id.fetch_add(1, Ordering::Relaxed) % self.size())
+    }
+}
+
+impl BufferPool for ClockSweepBufferPool {
     fn size(&self) -> usize {
         self.buffers.len()
     }
 
-    fn update_next_victim_id(&mut self) -> () {
-        self.next_victim_id = BufferId::new((self.next_victim_id.value() + 1) % self.size())
+    fn resize(&mut self, pool_size: usize) {
+        self.buffers.resize_with(pool_size, Frame::default);
     }
-}
 
-impl BufferPool for ClockSweepBufferPool {
-    fn evict(&mut self) -> Option<BufferId> {
+    fn evict(&self) -> Option<BufferId> {
         let pool_size = self.size();
+        if pool_size == 0 {
+            return None;
+        }
         let mut consecutive_pinned = 0;
 
-        let victim_id = loop {
-            let next_victim_id = self.next_victim_id;
-            let frame = &mut self[next_victim_id];
-            if frame.usage_count == 0 {
-                break self.next_victim_id;
-            }
-            if frame.has_reference() {
+        loop {
+            let victim_id = self.advance_next_victim_id();
+            let frame = &self[victim_id];
+            if !frame.is_evictable() {
                 consecutive_pinned += 1;
                 if consecutive_pinned >= pool_size {
                     return None;
                 }
-            } else {
-                frame.usage_count -= 1;
-                consecutive_pinned = 0;
+                continue;
             }
-            self.update_next_victim_id();
-        };
-        Some(victim_id)
+            consecutive_pinned = 0;
+            let is_unused = frame
+                .usage_count
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                    count.checked_sub(1)
+                })
+                .is_err();
+            if is_unused && frame.try_claim() {
+                return Some(victim_id);
+            }
+        }
+    }
+
+    fn upcoming_victims(&self, count: usize) -> Vec<BufferId> {
+        let next_victim_id = self.next_victim_id.load(Ordering::Relaxed);
+        (0..self.size())
+            .map(|offset| BufferId::new((next_victim_id + offset) % self.size()))
+            .filter(|&buffer_id| self[buffer_id].is_evictable())
+            .take(count)
+            .collect()
     }
 }
 
@@ -49,7 +74,7 @@ impl From<usize> for ClockSweepBufferPool {
         buffers.resize_with(pool_size, Frame::default);
         Self {
             buffers,
-            next_victim_id: BufferId::default(),
+            next_victim_id: AtomicUsize::new(0),
         }
     }
 }
@@ -62,15 +87,12 @@ impl Index<BufferId> for ClockSweepBufferPool {
     }
 }
 
-impl IndexMut<BufferId> for ClockSweepBufferPool {
-    fn index_mut(&mut self, buffer_id: BufferId) -> &mut Self::Output {
-        &mut self.buffers[buffer_id.value()]
-    }
-}
-
 #[cfg(test)]
 mod clock_sweep_buffer_pool_test {
     use super::*;
+    use crate::buffer::pool::suite::buffer_pool_test_suite;
+
+    buffer_pool_test_suite!(ClockSweepBufferPool::from);
 
     mod from {
         use super::*;
@@ -95,7 +117,7 @@ mod clock_sweep_buffer_pool_test {
         #[test]
         fn next_victim_idが参照されていない場合そのIDを返すこと() {
             // Arrange
-            let mut pool = ClockSweepBufferPool::from(2);
+            let pool = ClockSweepBufferPool::from(2);
 
             // Act
             let victim_id = pool.evict().unwrap();
@@ -108,9 +130,9 @@ mod clock_sweep_buffer_pool_test {
         #[test]
         fn next_victim_idが参照されている場合その次のIDを返すこと() {
             // Arrange
-            let mut pool = ClockSweepBufferPool::from(2);
-            let frame = &mut pool[BufferId::new(0)];
-            let _buffer = frame.use_buffer();
+            let pool = ClockSweepBufferPool::from(2);
+            let frame = &pool[BufferId::new(0)];
+            let _guard = frame.pin();
 
             // Act
             let victim_id = pool.evict().unwrap();
@@ -123,11 +145,11 @@ mod clock_sweep_buffer_pool_test {
         #[test]
         fn すべてのFrameが参照されている場合Noneを返すこと() {
             // Arrange
-            let mut pool = ClockSweepBufferPool::from(2);
-            let frame = &mut pool[BufferId::new(0)];
-            let _buffer = frame.use_buffer();
-            let frame = &mut pool[BufferId::new(1)];
-            let _buffer = frame.use_buffer();
+            let pool = ClockSweepBufferPool::from(2);
+            let frame = &pool[BufferId::new(0)];
+            let _guard = frame.pin();
+            let frame = &pool[BufferId::new(1)];
+            let _guard = frame.pin();
 
             // Act
             let victim_id = pool.evict();
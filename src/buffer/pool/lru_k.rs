@@ -0,0 +1,241 @@
+use super::BufferPool;
+use crate::{
+    buffer::{BufferId, Frame},
+    disk::PageId,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    ops::Index,
+};
+
+/// LRU-K replacement: evicts the frame whose K-th most recent access is the
+/// oldest. Frames accessed fewer than K times go first, least recently used
+/// first, so pages touched once by a scan cannot push out the hot set.
+pub struct LruKBufferPool {
+    pub buffers: Vec<Frame>,
+    history: Mutex<History>,
+}
+
+/// Sort key of a frame for eviction; the smallest one goes first.
+type EvictionKey = (bool, u64);
+
+struct History {
+    k: usize,
+    clock: u64,
+    /// Access times of each frame's page, most recent first. Empty for free
+    /// frames.
+    accesses: Vec<VecDeque<u64>>,
+    /// Every frame by its eviction key, the one to go first at the front.
+    order: BTreeSet<(EvictionKey, usize)>,
+}
+
+impl LruKBufferPool {
+    pub const DEFAULT_K: usize = 2;
+
+    pub fn new(pool_size: usize, k: usize) -> Self {
+        assert!(k > 0, "k must be positive");
+        let mut buffers = vec![];
+        buffers.resize_with(pool_size, Frame::default);
+        let mut history = History {
+            k,
+            clock: 0,
+            accesses: vec![],
+            order: BTreeSet::new(),
+        };
+        history.resize(pool_size);
+        Self {
+            buffers,
+            history: Mutex::new(history),
+        }
+    }
+}
+
+impl History {
+    fn eviction_key(&self, index: usize) -> EvictionKey {
+        let accesses = &self.accesses[index];
+        match accesses.get(self.k - 1) {
+            Some(&kth_access) => (true, kth_access),
+            None => (false, accesses.front().copied().unwrap_or(0)),
+        }
+    }
+
+    /// Changes the access history of a frame, keeping `order` in step.
+    fn update(&mut self, buffer_id: BufferId, f: impl FnOnce(&mut VecDeque<u64>, u64)) {
+        let index = buffer_id.value();
+        self.order.remove(&(self.eviction_key(index), index));
+        f(&mut self.accesses[index], self.clock);
+        self.order.insert((self.eviction_key(index), index));
+    }
+
+    fn touch(&mut self, buffer_id: BufferId) {
+        self.clock += 1;
+        let k = self.k;
+        self.update(buffer_id, |accesses, clock| {
+            accesses.push_front(clock);
+            accesses.truncate(k);
+        });
+    }
+
+    fn resize(&mut self, pool_size: usize) {
+        for index in pool_size..self.accesses.len() {
+            self.order.remove(&(self.eviction_key(index), index));
+        }
+        let len = self.accesses.len();
+        self.accesses.resize(pool_size, VecDeque::new());
+        for index in len..pool_size {
+            self.order.insert((self.eviction_key(index), index));
+        }
+    }
+}
+
+impl BufferPool for LruKBufferPool {
+    fn size(&self) -> usize {
+        self.buffers.len()
+    }
+
+    fn resize(&mut self, pool_size: usize) {
+        self.buffers.resize_with(pool_size, Frame::default);
+        self.history.get_mut().resize(pool_size);
+    }
+
+    fn evict(&self) -> Option<BufferId> {
+        let history = self.history.lock();
+        let victim_id = history
+            .order
+            .iter()
+            .map(|&(_, index)| BufferId::new(index))
+            .find(|&buffer_id| self[buffer_id].try_claim());
+        victim_id
+    }
+
+    fn upcoming_victims(&self, count: usize) -> Vec<BufferId> {
+        let history = self.history.lock();
+        let victim_ids = history
+            .order
+            .iter()
+            .map(|&(_, index)| BufferId::new(index))
+            .filter(|&buffer_id| self[buffer_id].is_evictable())
+            .take(count)
+            .collect();
+        victim_ids
+    }
+
+    fn touch(&self, buffer_id: BufferId) {
+        self.history.lock().touch(buffer_id);
+    }
+
+    fn admit(&self, buffer_id: BufferId, _page_id: PageId) {
+        let mut history = self.history.lock();
+        history.update(buffer_id, |accesses, _| accesses.clear());
+        history.touch(buffer_id);
+    }
+
+    fn release(&self, buffer_id: BufferId) {
+        self.history
+            .lock()
+            .update(buffer_id, |accesses, _| accesses.clear());
+    }
+}
+
+impl From<usize> for LruKBufferPool {
+    fn from(pool_size: usize) -> Self {
+        Self::new(pool_size, Self::DEFAULT_K)
+    }
+}
+
+impl Index<BufferId> for LruKBufferPool {
+    type Output = Frame;
+
+    fn index(&self, buffer_id: BufferId) -> &Self::Output {
+        &self.buffers[buffer_id.value()]
+    }
+}
+
+#[cfg(test)]
+mod lru_k_buffer_pool_test {
+    use super::*;
+    use crate::buffer::pool::suite::{buffer_pool_test_suite, Harness};
+
+    buffer_pool_test_suite!(LruKBufferPool::from);
+
+    mod evict {
+        use super::*;
+
+        #[test]
+        fn スキャンしても繰り返し参照されたページは追い出されないこと() {
+            // Arrange
+            let mut harness = Harness::new(LruKBufferPool::from(4));
+            for _ in 0..2 {
+                harness.fetch(PageId::new(0)).unwrap();
+                harness.fetch(PageId::new(1)).unwrap();
+            }
+
+            // Act
+            for value in 2..100 {
+                harness.fetch(PageId::new(value)).unwrap();
+            }
+
+            // Assert
+            assert!(harness.is_resident(PageId::new(0)));
+            assert!(harness.is_resident(PageId::new(1)));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn K回前の参照が最も古いFrameを返すこと() {
+            // Arrange
+            let mut harness = Harness::new(LruKBufferPool::from(2));
+            harness.fetch(PageId::new(0)).unwrap();
+            harness.fetch(PageId::new(1)).unwrap();
+            harness.fetch(PageId::new(0)).unwrap();
+            harness.fetch(PageId::new(1)).unwrap();
+            harness.fetch(PageId::new(0)).unwrap();
+
+            // Act
+            harness.fetch(PageId::new(2)).unwrap();
+
+            // Assert
+            assert!(harness.is_resident(PageId::new(0)));
+            assert!(!harness.is_resident(PageId::new(1)));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 次に追い出す候補をK回前の参照が古い順に返すこと() {
+            // Arrange
+            let mut harness = Harness::new(LruKBufferPool::from(3));
+            for value in [0, 1, 2, 2, 0, 1, 0] {
+                harness.fetch(PageId::new(value)).unwrap();
+            }
+
+            // Act
+            let victim_ids = harness.pool.upcoming_victims(3);
+
+            // Assert
+            let expected: Vec<_> = [1, 2, 0]
+                .map(|value| harness.page_table[&PageId::new(value)])
+                .into();
+            assert_eq!(victim_ids, expected);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 解放したFrameを最初に返すこと() {
+            // Arrange
+            let mut harness = Harness::new(LruKBufferPool::from(2));
+            for _ in 0..2 {
+                harness.fetch(PageId::new(0)).unwrap();
+                harness.fetch(PageId::new(1)).unwrap();
+            }
+            let buffer_id = harness.page_table[&PageId::new(1)];
+            harness.pool.release(buffer_id);
+
+            // Act
+            let victim_id = harness.pool.evict();
+
+            // Assert
+            assert_eq!(victim_id, Some(buffer_id));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Behaviour every `BufferPool` has to provide. Each implementation runs it
+//! through `buffer_pool_test_suite!`.
+use super::BufferPool;
+use crate::{
+    buffer::{BufferId, PageGuard},
+    disk::PageId,
+};
+use std::{collections::HashMap, thread};
+
+/// Drives a pool the way `BufferPoolManager` does, without any disk behind it.
+pub struct Harness<P> {
+    pub pool: P,
+    pub page_table: HashMap<PageId, BufferId>,
+}
+
+impl<P: BufferPool> Harness<P> {
+    pub fn new(pool: P) -> Self {
+        Self {
+            pool,
+            page_table: HashMap::new(),
+        }
+    }
+
+    pub fn fetch(&mut self, page_id: PageId) -> Option<PageGuard> {
+        if let Some(&buffer_id) = self.page_table.get(&page_id) {
+            let guard = self.pool[buffer_id].pin();
+            self.pool.touch(buffer_id);
+            return Some(guard);
+        }
+        let buffer_id = self.pool.evict()?;
+        let frame = &self.pool[buffer_id];
+        self.page_table.remove(&frame.buffer.page_id());
+        frame.buffer.set_page_id(page_id);
+        frame.reset_usage_count();
+        let guard = frame.pin();
+        self.pool.admit(buffer_id, page_id);
+        self.page_table.insert(page_id, buffer_id);
+        frame.unclaim();
+        Some(guard)
+    }
+
+    pub fn is_resident(&self, page_id: PageId) -> bool {
+        self.page_table.contains_key(&page_id)
+    }
+}
+
+pub fn returns_every_free_frame<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(4));
+
+    // Act
+    for value in 0..4 {
+        harness.fetch(PageId::new(value)).unwrap();
+    }
+
+    // Assert
+    let mut buffer_ids: Vec<_> = harness.page_table.values().map(|id| id.value()).collect();
+    buffer_ids.sort();
+    assert_eq!(buffer_ids, vec![0, 1, 2, 3]);
+}
+
+pub fn never_evicts_referenced_frames<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(3));
+    let _pinned = [
+        harness.fetch(PageId::new(0)).unwrap(),
+        harness.fetch(PageId::new(1)).unwrap(),
+    ];
+
+    // Act
+    for value in 2..20 {
+        harness.fetch(PageId::new(value)).unwrap();
+    }
+
+    // Assert
+    assert!(harness.is_resident(PageId::new(0)));
+    assert!(harness.is_resident(PageId::new(1)));
+    assert!(harness.is_resident(PageId::new(19)));
+}
+
+pub fn returns_none_when_all_frames_are_referenced<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(2));
+    let _pinned = [
+        harness.fetch(PageId::new(0)).unwrap(),
+        harness.fetch(PageId::new(1)).unwrap(),
+    ];
+
+    // Act
+    let guard = harness.fetch(PageId::new(2));
+
+    // Assert
+    assert!(guard.is_none());
+}
+
+pub fn returns_none_when_empty<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let pool = new_pool(0);
+
+    // Act
+    let buffer_id = pool.evict();
+
+    // Assert
+    assert!(buffer_id.is_none());
+}
+
+pub fn keeps_fetched_pages_in_the_same_frame<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(2));
+    harness.fetch(PageId::new(0)).unwrap();
+    let buffer_id = harness.page_table[&PageId::new(0)];
+
+    // Act
+    let guard = harness.fetch(PageId::new(0)).unwrap();
+
+    // Assert
+    assert_eq!(guard.page_id(), PageId::new(0));
+    assert_eq!(harness.page_table[&PageId::new(0)], buffer_id);
+}
+
+pub fn claims_each_frame_once_across_threads<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let pool = new_pool(64);
+
+    // Act
+    let mut buffer_ids: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| std::iter::from_fn(|| pool.evict()).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .map(|buffer_id| buffer_id.value())
+            .collect()
+    });
+
+    // Assert
+    buffer_ids.sort();
+    assert_eq!(buffer_ids, (0..64).collect::<Vec<_>>());
+}
+
+pub fn lists_upcoming_victims_without_claiming_them<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(4));
+    let _pinned = harness.fetch(PageId::new(0)).unwrap();
+    for value in 1..4 {
+        harness.fetch(PageId::new(value)).unwrap();
+    }
+    let pinned_id = harness.page_table[&PageId::new(0)];
+
+    // Act
+    let buffer_ids = harness.pool.upcoming_victims(4);
+
+    // Assert
+    assert_eq!(buffer_ids.len(), 3);
+    assert!(!buffer_ids.contains(&pinned_id));
+    assert!(buffer_ids
+        .iter()
+        .all(|&buffer_id| harness.pool[buffer_id].is_evictable()));
+}
+
+pub fn uses_frames_added_by_resize<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(2));
+    let mut pinned: Vec<_> = (0..2)
+        .map(|value| harness.fetch(PageId::new(value)).unwrap())
+        .collect();
+
+    // Act
+    harness.pool.resize(4);
+    pinned.extend((2..4).map(|value| harness.fetch(PageId::new(value)).unwrap()));
+
+    // Assert
+    assert_eq!(harness.pool.size(), 4);
+    assert!(harness.fetch(PageId::new(4)).is_none());
+}
+
+pub fn never_returns_frames_dropped_by_resize<P: BufferPool>(new_pool: impl Fn(usize) -> P) {
+    // Arrange
+    let mut harness = Harness::new(new_pool(4));
+    for value in 0..4 {
+        harness.fetch(PageId::new(value)).unwrap();
+    }
+    for buffer_id in (2..4).map(BufferId::new) {
+        harness.pool.release(buffer_id);
+        harness.page_table.retain(|_, &mut id| id != buffer_id);
+    }
+
+    // Act
+    harness.pool.resize(2);
+    for value in 4..20 {
+        harness.fetch(PageId::new(value)).unwrap();
+    }
+
+    // Assert
+    assert_eq!(harness.pool.size(), 2);
+    assert!(harness.page_table.values().all(|id| id.value() < 2));
+}
+
+macro_rules! buffer_pool_test_suite {
+    ($new_pool:expr) => {
+        mod buffer_pool_test_suite {
+            use super::*;
+            use crate::buffer::pool::suite;
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 空いているFrameをすべて使うこと() {
+                suite::returns_every_free_frame($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 参照されているFrameを追い出さないこと() {
+                suite::never_evicts_referenced_frames($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn すべてのFrameが参照されている場合Noneを返すこと() {
+                suite::returns_none_when_all_frames_are_referenced($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn Frameがない場合Noneを返すこと() {
+                suite::returns_none_when_empty($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 再び読み込んだページは同じFrameを使うこと() {
+                suite::keeps_fetched_pages_in_the_same_frame($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 複数のスレッドから追い出しても同じFrameを二度返さないこと() {
+                suite::claims_each_frame_once_across_threads($new_pool);
+            }
+
+            #[test]
+            fn 次に追い出す候補を確保せずに返すこと() {
+                suite::lists_upcoming_victims_without_claiming_them($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 拡張したFrameを使うこと() {
+                suite::uses_frames_added_by_resize($new_pool);
+            }
+
+            #[allow(non_snake_case)]
+            #[test]
+            fn 縮小して取り除いたFrameを返さないこと() {
+                suite::never_returns_frames_dropped_by_resize($new_pool);
+            }
+        }
+    };
+}
+pub(crate) use buffer_pool_test_suite;
@@ -0,0 +1,67 @@
+use super::{BufferPoolManager, BufferRing};
+use crate::disk::PageId;
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Weak,
+    },
+    thread,
+};
+
+/// Finds the page to read after the one whose body it is given, if any.
+pub type NextPageId = fn(&[u8]) -> Option<PageId>;
+
+/// Chain of pages to read into the pool, from `page_id` on.
+pub(crate) struct ReadAhead {
+    pub page_id: PageId,
+    pub count: usize,
+    pub next_page_id: NextPageId,
+    /// Ring of the scan the pages are read for, if it uses one.
+    pub ring: Option<BufferRing>,
+}
+
+impl ReadAhead {
+    /// Hints waiting for a worker beyond this many are dropped.
+    const QUEUE_DEPTH: usize = 64;
+
+    fn run(&self, bufmgr: &BufferPoolManager) {
+        let mut page_id = Some(self.page_id);
+        for _ in 0..self.count {
+            let Some(current_page_id) = page_id else {
+                break;
+            };
+            // Errors are left for the fetch that actually needs the page.
+            let Ok(guard) = bufmgr.fetch(current_page_id, self.ring.as_ref(), true) else {
+                break;
+            };
+            page_id = (self.next_page_id)(&guard.read());
+        }
+    }
+}
+
+/// Starts the workers of a manager, returning where to send them hints. They
+/// only hold on to the manager while they are reading, and exit once it has
+/// been dropped along with the sender.
+pub(crate) fn spawn(bufmgr: Weak<BufferPoolManager>, workers: usize) -> SyncSender<ReadAhead> {
+    let (sender, receiver) = mpsc::sync_channel(ReadAhead::QUEUE_DEPTH);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..workers {
+        let bufmgr = bufmgr.clone();
+        let receiver = receiver.clone();
+        thread::spawn(move || run(&bufmgr, &receiver));
+    }
+    sender
+}
+
+fn run(bufmgr: &Weak<BufferPoolManager>, receiver: &Mutex<Receiver<ReadAhead>>) {
+    loop {
+        let Ok(read_ahead) = receiver.lock().recv() else {
+            return;
+        };
+        let Some(bufmgr) = bufmgr.upgrade() else {
+            return;
+        };
+        read_ahead.run(&bufmgr);
+    }
+}
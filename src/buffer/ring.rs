@@ -0,0 +1,133 @@
+use super::{BufferId, BufferPoolManager, Error, NextPageId, PageGuard, WritePageGuard};
+use crate::disk::PageId;
+use parking_lot::{Mutex, MutexGuard};
+use std::sync::Arc;
+
+/// Small set of frames that a sequential scan or a bulk load cycles through,
+/// so that it evicts the pages it loaded itself rather than the working set
+/// of everyone else. A frame whose page someone else has used since the ring
+/// loaded it is left to the pool and replaced in the ring. Clones share the
+/// same frames.
+#[derive(Clone)]
+pub struct BufferRing {
+    ring: Arc<Mutex<Ring>>,
+}
+
+pub(crate) struct Ring {
+    size: usize,
+    /// Frames in the order the ring reuses them, with the page it loaded
+    /// into each.
+    frames: Vec<(BufferId, PageId)>,
+    next: usize,
+}
+
+impl BufferRing {
+    /// Enough frames that pages read ahead of a scan are not reused before
+    /// the scan gets to them.
+    pub const DEFAULT_SIZE: usize = 32;
+
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "ring size must be positive");
+        Self {
+            ring: Arc::new(Mutex::new(Ring {
+                size,
+                frames: Vec::with_capacity(size),
+                next: 0,
+            })),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Ring> {
+        self.ring.lock()
+    }
+}
+
+impl Default for BufferRing {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SIZE)
+    }
+}
+
+impl Ring {
+    /// Frame to reuse for the next page and the page it should still hold,
+    /// once the ring has all its frames.
+    pub fn next_victim(&self) -> Option<(BufferId, PageId)> {
+        (self.frames.len() == self.size).then(|| self.frames[self.next])
+    }
+
+    /// Records that the next page, `page_id`, went into `buffer_id`.
+    pub fn advance(&mut self, buffer_id: BufferId, page_id: PageId) {
+        if self.frames.len() < self.size {
+            self.frames.push((buffer_id, page_id));
+        } else {
+            self.frames[self.next] = (buffer_id, page_id);
+            self.next = (self.next + 1) % self.size;
+        }
+    }
+}
+
+/// A `BufferPoolManager` that loads and creates pages through a ring.
+#[derive(Clone, Copy)]
+pub struct WithRing<'a> {
+    bufmgr: &'a BufferPoolManager,
+    ring: &'a BufferRing,
+}
+
+impl<'a> WithRing<'a> {
+    pub(crate) fn new(bufmgr: &'a BufferPoolManager, ring: &'a BufferRing) -> Self {
+        Self { bufmgr, ring }
+    }
+
+    pub fn fetch_page(&self, page_id: PageId) -> Result<PageGuard, Error> {
+        self.bufmgr.fetch(page_id, Some(self.ring), false)
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard, Error> {
+        Ok(self.fetch_page(page_id)?.write())
+    }
+
+    pub fn create_page(&self) -> Result<WritePageGuard, Error> {
+        self.bufmgr.create(Some(self.ring))
+    }
+
+    /// Like `BufferPoolManager::prefetch`, reading the pages into the ring.
+    pub fn prefetch(&self, page_id: PageId, count: usize, next_page_id: NextPageId) {
+        self.bufmgr
+            .prefetch_into(Some(self.ring), page_id, count, next_page_id)
+    }
+}
+
+#[cfg(test)]
+mod ring_test {
+    use super::*;
+
+    mod advance {
+        use super::*;
+
+        #[test]
+        fn 埋まるまでは再利用するフレームを返さず埋まった後は古い順に返すこと() {
+            // Arrange
+            let mut ring = Ring {
+                size: 2,
+                frames: vec![],
+                next: 0,
+            };
+
+            // Act
+            let before_full = ring.next_victim();
+            ring.advance(BufferId::new(3), PageId::new(0));
+            ring.advance(BufferId::new(5), PageId::new(1));
+            let first = ring.next_victim();
+            ring.advance(BufferId::new(7), PageId::new(2));
+            let second = ring.next_victim();
+            ring.advance(BufferId::new(5), PageId::new(3));
+            let third = ring.next_victim();
+
+            // Assert
+            assert_eq!(before_full, None);
+            assert_eq!(first, Some((BufferId::new(3), PageId::new(0))));
+            assert_eq!(second, Some((BufferId::new(5), PageId::new(1))));
+            assert_eq!(third, Some((BufferId::new(7), PageId::new(2))));
+        }
+    }
+}
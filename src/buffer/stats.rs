@@ -0,0 +1,114 @@
+use super::{BufferId, Frame};
+use crate::disk::PageId;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Running totals of a `BufferPoolManager` since it was created, with the
+/// number of frames pinned when they were taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BufferPoolStats {
+    /// Fetches of a page that was already in the pool.
+    pub hits: u64,
+    /// Fetches that had to read the page from disk.
+    pub misses: u64,
+    /// Pages read from disk ahead of their first fetch.
+    pub prefetched: u64,
+    /// Pages dropped from the pool to make room for another one.
+    pub evictions: u64,
+    /// Evicted pages that were dirty and had to be written first.
+    pub dirty_write_backs: u64,
+    /// Fetches and creations that failed with `NoFreeBuffer`.
+    pub no_free_buffer_errors: u64,
+    pub pinned_frames: usize,
+}
+
+impl BufferPoolStats {
+    /// Share of fetches served from the pool, or 0.0 before any fetch.
+    pub fn hit_rate(&self) -> f64 {
+        let fetches = self.hits + self.misses;
+        if fetches == 0 {
+            0.0
+        } else {
+            self.hits as f64 / fetches as f64
+        }
+    }
+}
+
+/// State of one frame at the time it was looked at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameInfo {
+    pub buffer_id: BufferId,
+    /// `None` if the frame holds no page.
+    pub page_id: Option<PageId>,
+    pub usage_count: u64,
+    pub pin_count: usize,
+    pub is_dirty: bool,
+}
+
+impl FrameInfo {
+    pub(crate) fn new(buffer_id: BufferId, frame: &Frame) -> Self {
+        let page_id = frame.buffer.page_id();
+        Self {
+            buffer_id,
+            page_id: (page_id != PageId::INVALID_PAGE_ID).then_some(page_id),
+            usage_count: frame.usage_count.load(Ordering::Relaxed),
+            pin_count: frame.buffer.pin_count(),
+            is_dirty: frame.buffer.is_dirty(),
+        }
+    }
+}
+
+/// The counters behind `BufferPoolStats`, bumped by the threads using the
+/// manager.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    pub prefetched: AtomicU64,
+    pub evictions: AtomicU64,
+    pub dirty_write_backs: AtomicU64,
+    pub no_free_buffer_errors: AtomicU64,
+}
+
+impl Counters {
+    pub fn stats(&self, pinned_frames: usize) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            prefetched: self.prefetched.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            dirty_write_backs: self.dirty_write_backs.load(Ordering::Relaxed),
+            no_free_buffer_errors: self.no_free_buffer_errors.load(Ordering::Relaxed),
+            pinned_frames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod buffer_pool_stats_test {
+    use super::*;
+
+    mod hit_rate {
+        use super::*;
+
+        #[test]
+        fn 読み込んだページのうちバッファプールにあった割合を返すこと() {
+            // Arrange
+            let stats = BufferPoolStats {
+                hits: 3,
+                misses: 1,
+                ..Default::default()
+            };
+
+            // Act
+            let actual = stats.hit_rate();
+
+            // Assert
+            assert_eq!(actual, 0.75);
+        }
+
+        #[test]
+        fn 一度も読み込んでいない場合0を返すこと() {
+            assert_eq!(BufferPoolStats::default().hit_rate(), 0.0);
+        }
+    }
+}
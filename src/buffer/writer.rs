@@ -0,0 +1,106 @@
+use super::BufferPoolManager;
+use crate::worker::PeriodicWorker;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+#[derive(Clone, Debug)]
+pub struct BackgroundWriterOptions {
+    /// How long the writer sleeps between two rounds.
+    pub interval: Duration,
+    /// How many of the next victims each round looks at.
+    pub max_pages_per_round: usize,
+}
+
+impl Default for BackgroundWriterOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(200),
+            max_pages_per_round: 100,
+        }
+    }
+}
+
+/// Thread that periodically writes back the dirty pages the pool is about to
+/// evict, taking those writes off the path of `fetch_page` and `create_page`.
+/// It runs until it is stopped or dropped.
+pub struct BackgroundWriter {
+    worker: PeriodicWorker,
+    pages_written: Arc<AtomicU64>,
+}
+
+impl BackgroundWriter {
+    pub fn start(bufmgr: Arc<BufferPoolManager>, options: BackgroundWriterOptions) -> Self {
+        let pages_written = Arc::new(AtomicU64::new(0));
+        let worker = PeriodicWorker::start(options.interval, {
+            let pages_written = pages_written.clone();
+            move || {
+                // A failed write leaves its pages dirty, so the eviction that
+                // picks one of them writes it again and reports the error.
+                if let Ok(written) = bufmgr.clean_upcoming_victims(options.max_pages_per_round) {
+                    pages_written.fetch_add(written as u64, Ordering::Relaxed);
+                }
+            }
+        });
+        Self {
+            worker,
+            pages_written,
+        }
+    }
+
+    /// Number of pages written since the writer was started.
+    pub fn pages_written(&self) -> u64 {
+        self.pages_written.load(Ordering::Relaxed)
+    }
+
+    /// Stops the writer and waits for the round in progress to finish.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+#[cfg(test)]
+mod background_writer_test {
+    use super::*;
+    use crate::{
+        buffer::ClockSweepBufferPool,
+        disk::{DiskManager, MemoryStore},
+    };
+    use std::{thread, time::Instant};
+
+    mod start {
+        use super::*;
+
+        #[test]
+        fn 追い出される前にダーティなページを書き込むこと() {
+            // Arrange
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                Arc::new(BufferPoolManager::new(disk, pool))
+            };
+            for _ in 0..4 {
+                bufmgr.create_page().unwrap().fill(b'a');
+            }
+            let options = BackgroundWriterOptions {
+                interval: Duration::from_millis(1),
+                max_pages_per_round: 4,
+            };
+
+            // Act
+            let writer = BackgroundWriter::start(bufmgr.clone(), options);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while writer.pages_written() < 4 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            writer.stop();
+
+            // Assert
+            assert_eq!(bufmgr.flush_all().unwrap(), 0);
+        }
+    }
+}
@@ -1,5 +1,21 @@
+mod compression;
+mod encryption;
+mod error;
+mod free_space;
 mod manager;
+mod mmap;
 mod page;
+mod page_map;
+mod store;
+mod superblock;
 
+pub use compression::*;
+pub use encryption::*;
+pub use error::*;
+pub use free_space::*;
 pub use manager::*;
+pub use mmap::*;
 pub use page::*;
+pub use page_map::*;
+pub use store::*;
+pub use superblock::*;
@@ -0,0 +1,232 @@
+use super::{Error, PageHeader};
+use std::io;
+use zerocopy::FromBytes;
+
+/// Algorithm used to compress page bodies on disk, chosen when the database is
+/// created. Compressed pages are packed into the file at sector granularity,
+/// wherever the [`PageMap`] of the database puts them.
+///
+/// [`PageMap`]: super::PageMap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// LZ4 block compression, available with the `lz4` feature.
+    Lz4,
+}
+
+impl Compression {
+    pub fn check(self) -> Result<(), Error> {
+        match self {
+            Compression::None => Ok(()),
+            Compression::Lz4 if cfg!(feature = "lz4") => Ok(()),
+            _ => Err(Error::UnsupportedCompression(self.into())),
+        }
+    }
+
+    /// Writes the compressed form of `page` into `slot` and returns how many
+    /// bytes of it are used, or `None` when the page should be stored as is.
+    pub fn compress_page(self, page: &[u8], slot: &mut [u8]) -> Option<usize> {
+        let body = &page[PageHeader::SIZE..];
+        let compressed = self.compress(body)?;
+        if compressed.len() >= body.len() {
+            return None;
+        }
+        slot[PageHeader::SIZE..][..compressed.len()].copy_from_slice(&compressed);
+        let header = PageHeader::mut_from_prefix(slot).unwrap();
+        let page_header = PageHeader::ref_from_prefix(page).unwrap();
+        header.checksum = page_header.checksum;
+        header.compressed_len = compressed.len() as u32;
+        header.lsn = page_header.lsn;
+        Some(PageHeader::SIZE + compressed.len())
+    }
+
+    /// Restores a page written by [`Compression::compress_page`] from `slot`
+    /// into `page`.
+    pub fn decompress_page(self, slot: &[u8], page: &mut [u8]) -> io::Result<()> {
+        let header = PageHeader::ref_from_prefix(slot).unwrap();
+        let compressed = slot[PageHeader::SIZE..]
+            .get(..header.compressed_len as usize)
+            .ok_or_else(corrupted)?;
+        let body = &mut page[PageHeader::SIZE..];
+        let len = self.decompress(compressed, body).ok_or_else(corrupted)?;
+        if len != body.len() {
+            return Err(corrupted());
+        }
+        let (checksum, lsn) = (header.checksum, header.lsn);
+        let header = PageHeader::mut_from_prefix(page).unwrap();
+        header.checksum = checksum;
+        header.compressed_len = 0;
+        header.lsn = lsn;
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn compress(self, body: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some(lz4_flex::block::compress(body)),
+            _ => None,
+        }
+    }
+
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn decompress(self, compressed: &[u8], body: &mut [u8]) -> Option<usize> {
+        match self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::decompress_into(compressed, body).ok(),
+            _ => None,
+        }
+    }
+}
+
+fn corrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "compressed page is corrupted")
+}
+
+impl From<Compression> for u32 {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+}
+
+impl TryFrom<u32> for Compression {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            _ => Err(Error::UnsupportedCompression(value)),
+        }
+    }
+}
+
+/// Running totals of the pages a `DiskManager` has written while compression
+/// is enabled. `stored_bytes` counts compressed pages at their compressed
+/// length, before rounding up to whole sectors.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompressionStats {
+    pub pages_written: u64,
+    pub pages_compressed: u64,
+    pub logical_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    pub fn record(&mut self, logical_len: usize, stored_len: Option<usize>) {
+        self.pages_written += 1;
+        self.logical_bytes += logical_len as u64;
+        match stored_len {
+            Some(stored_len) => {
+                self.pages_compressed += 1;
+                self.stored_bytes += stored_len as u64;
+            }
+            None => self.stored_bytes += logical_len as u64,
+        }
+    }
+
+    /// Logical bytes per byte stored, or 1.0 before anything was written.
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            1.0
+        } else {
+            self.logical_bytes as f64 / self.stored_bytes as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod compression_test {
+    use super::*;
+
+    mod check {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 圧縮しない場合Okを返すこと() {
+            assert!(Compression::None.check().is_ok())
+        }
+
+        #[cfg(not(feature = "lz4"))]
+        #[allow(non_snake_case)]
+        #[test]
+        fn 機能が無効な場合UnsupportedCompressionを返すこと() {
+            assert!(matches!(
+                Compression::Lz4.check(),
+                Err(Error::UnsupportedCompression(1))
+            ))
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    mod compress_page {
+        use super::*;
+        use crate::disk::{stamp_checksum, verify_checksum, DiskManager, PageId};
+
+        #[test]
+        fn 圧縮したページを元に戻せること() {
+            // Arrange
+            let page_id = PageId::new(1);
+            let mut page = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            page[PageHeader::SIZE..].fill(b'a');
+            page[100..200].fill(b'b');
+            stamp_checksum(page_id, &mut page);
+            let mut slot = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+
+            // Act
+            let len = Compression::Lz4.compress_page(&page, &mut slot).unwrap();
+            let mut restored = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            Compression::Lz4
+                .decompress_page(&slot[..len], &mut restored)
+                .unwrap();
+
+            // Assert
+            assert!(len < DiskManager::DEFAULT_PAGE_SIZE / 10);
+            assert_eq!(restored, page);
+            assert!(verify_checksum(page_id, &restored));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 圧縮しても小さくならない場合Noneを返すこと() {
+            // Arrange
+            let mut page = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            let mut state = 1u32;
+            for byte in page.iter_mut() {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                *byte = (state >> 24) as u8;
+            }
+            let mut slot = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+
+            // Act
+            let result = Compression::Lz4.compress_page(&page, &mut slot);
+
+            // Assert
+            assert_eq!(result, None);
+        }
+    }
+
+    mod ratio {
+        use super::*;
+
+        #[test]
+        fn 論理サイズと格納サイズの比を返すこと() {
+            // Arrange
+            let mut stats = CompressionStats::default();
+
+            // Act
+            stats.record(4096, Some(1024));
+            stats.record(4096, None);
+
+            // Assert
+            assert_eq!(stats.pages_written, 2);
+            assert_eq!(stats.pages_compressed, 1);
+            assert_eq!(stats.ratio(), 8192.0 / 5120.0);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::PageId;
+use aes_gcm::{
+    aead::{AeadInPlace, KeyInit, OsRng},
+    AeadCore, Aes256Gcm, Nonce, Tag,
+};
+use std::{
+    fmt::{self, Debug},
+    io,
+};
+use zerocopy::AsBytes;
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const KEY_CHECK_AAD: &[u8] = b"lightsql key check";
+
+/// 256-bit key of an encrypted database.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Nonce and tag sealing an empty message under the database key, kept in the
+/// superblock so that opening with the wrong key fails up front.
+pub type KeyCheck = [u8; NONCE_SIZE + TAG_SIZE];
+
+/// AES-256-GCM over whole pages. The nonce and tag of a page are kept in a
+/// trailer of [`Cipher::TRAILER_SIZE`] bytes at its end, and the page id is
+/// authenticated along with it so that pages cannot be swapped around.
+pub struct Cipher {
+    aead: Aes256Gcm,
+}
+
+impl Cipher {
+    pub const TRAILER_SIZE: usize = NONCE_SIZE + TAG_SIZE;
+
+    pub fn new(key: &EncryptionKey) -> Self {
+        Self {
+            aead: Aes256Gcm::new(&key.0.into()),
+        }
+    }
+
+    pub fn encrypt_page(&self, page_id: PageId, page: &mut [u8]) -> io::Result<()> {
+        let (data, trailer) = page.split_at_mut(page.len() - Self::TRAILER_SIZE);
+        self.seal(page_id.as_bytes(), data, trailer)
+    }
+
+    pub fn decrypt_page(&self, page_id: PageId, page: &mut [u8]) -> io::Result<()> {
+        let (data, trailer) = page.split_at_mut(page.len() - Self::TRAILER_SIZE);
+        self.open(page_id.as_bytes(), data, trailer).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("page {:?} failed authentication", page_id),
+            )
+        })
+    }
+
+    pub fn key_check(&self) -> io::Result<KeyCheck> {
+        let mut key_check = [0u8; Self::TRAILER_SIZE];
+        self.seal(KEY_CHECK_AAD, &mut [], &mut key_check)?;
+        Ok(key_check)
+    }
+
+    pub fn verify_key_check(&self, key_check: &KeyCheck) -> bool {
+        self.open(KEY_CHECK_AAD, &mut [], key_check).is_ok()
+    }
+
+    fn seal(&self, aad: &[u8], data: &mut [u8], trailer: &mut [u8]) -> io::Result<()> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let tag = self
+            .aead
+            .encrypt_in_place_detached(&nonce, aad, data)
+            .map_err(|_| io::Error::other("failed to encrypt page"))?;
+        trailer[..NONCE_SIZE].copy_from_slice(&nonce);
+        trailer[NONCE_SIZE..].copy_from_slice(&tag);
+        Ok(())
+    }
+
+    fn open(&self, aad: &[u8], data: &mut [u8], trailer: &[u8]) -> Result<(), aes_gcm::Error> {
+        let nonce = Nonce::from_slice(&trailer[..NONCE_SIZE]);
+        let tag = Tag::from_slice(&trailer[NONCE_SIZE..]);
+        self.aead.decrypt_in_place_detached(nonce, aad, data, tag)
+    }
+}
+
+#[cfg(test)]
+mod cipher_test {
+    use super::*;
+
+    mod encrypt_page {
+        use super::*;
+
+        #[test]
+        fn 暗号化したページを復号できること() {
+            // Arrange
+            let cipher = Cipher::new(&EncryptionKey::new([1; 32]));
+            let page_id = PageId::new(1);
+            let mut page = [b'a'; 4096];
+
+            // Act
+            cipher.encrypt_page(page_id, &mut page).unwrap();
+
+            // Assert
+            assert!(!page.windows(16).any(|window| window == [b'a'; 16]));
+            cipher.decrypt_page(page_id, &mut page).unwrap();
+            assert_eq!(
+                page[..4096 - Cipher::TRAILER_SIZE],
+                [b'a'; 4096 - Cipher::TRAILER_SIZE]
+            );
+        }
+
+        #[test]
+        fn 別のページとして復号しようとした場合エラーを返すこと() {
+            // Arrange
+            let cipher = Cipher::new(&EncryptionKey::new([1; 32]));
+            let mut page = [b'a'; 4096];
+            cipher.encrypt_page(PageId::new(1), &mut page).unwrap();
+
+            // Act
+            let result = cipher.decrypt_page(PageId::new(2), &mut page);
+
+            // Assert
+            assert!(matches!(result, Err(err) if err.kind() == io::ErrorKind::InvalidData));
+        }
+    }
+
+    mod verify_key_check {
+        use super::*;
+
+        #[test]
+        fn 異なる鍵の場合falseを返すこと() {
+            // Arrange
+            let key_check = Cipher::new(&EncryptionKey::new([1; 32]))
+                .key_check()
+                .unwrap();
+
+            // Act
+            let result = Cipher::new(&EncryptionKey::new([2; 32])).verify_key_check(&key_check);
+
+            // Assert
+            assert!(!result);
+            assert!(Cipher::new(&EncryptionKey::new([1; 32])).verify_key_check(&key_check));
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use super::PageId;
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("not a lightsql database file.")]
+    InvalidMagic,
+    #[error("unsupported format version {found} (expected {expected}).")]
+    IncompatibleVersion { found: u32, expected: u32 },
+    #[error("unsupported page size {0}.")]
+    UnsupportedPageSize(usize),
+    #[error("unsupported compression {0}.")]
+    UnsupportedCompression(u32),
+    #[error("compression cannot be combined with encryption.")]
+    CompressionWithEncryption,
+    #[error("the database is encrypted and needs a key.")]
+    EncryptionKeyRequired,
+    #[error("the database is not encrypted.")]
+    NotEncrypted,
+    #[error("wrong encryption key.")]
+    WrongEncryptionKey,
+    #[error("checksum mismatch in page {page_id:?}.")]
+    ChecksumMismatch { page_id: PageId },
+    #[error("the page map is corrupted.")]
+    CorruptedPageMap,
+}
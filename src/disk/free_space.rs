@@ -0,0 +1,190 @@
+use super::Extent;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Unused extents of a compressed database's store, indexed by offset to merge
+/// neighbours as they are freed, and by length to hand out the smallest one
+/// that fits. The store grows at its end when none does.
+#[derive(Debug, Default)]
+pub struct FreeSpace {
+    by_offset: BTreeMap<u64, u64>,
+    by_len: BTreeSet<(u64, u64)>,
+    end: u64,
+    /// Extents given up since the last sync. The page map on disk may still
+    /// point at them, so they must not be reused until it has been replaced.
+    pending: Vec<Extent>,
+}
+
+impl FreeSpace {
+    /// Free space of a store of `end` bytes, in which the `used` extents are
+    /// taken.
+    pub fn new(used: impl IntoIterator<Item = Extent>, end: u64) -> Self {
+        let mut used: Vec<_> = used
+            .into_iter()
+            .filter(|extent| !extent.is_empty())
+            .collect();
+        used.sort_by_key(|extent| extent.offset);
+        let mut free_space = Self {
+            end: used.iter().map(Extent::end).fold(end, u64::max),
+            ..Default::default()
+        };
+        let mut offset = 0;
+        for extent in used {
+            if offset < extent.offset {
+                free_space.insert(offset, extent.offset - offset);
+            }
+            offset = offset.max(extent.end());
+        }
+        if offset < free_space.end {
+            free_space.insert(offset, free_space.end - offset);
+        }
+        free_space
+    }
+
+    /// Takes an extent of at least `len` bytes, rounded up to whole sectors.
+    pub fn allocate(&mut self, len: u64) -> Extent {
+        let len = len.next_multiple_of(Extent::SECTOR_SIZE as u64);
+        if let Some(&(free_len, offset)) = self.by_len.range((len, 0)..).next() {
+            self.remove(offset, free_len);
+            if free_len > len {
+                self.insert(offset + len, free_len - len);
+            }
+            return Extent::new(offset, len);
+        }
+        let offset = match self.by_offset.last_key_value() {
+            Some((&offset, &free_len)) if offset + free_len == self.end => {
+                self.remove(offset, free_len);
+                offset
+            }
+            _ => self.end,
+        };
+        self.end = offset + len;
+        Extent::new(offset, len)
+    }
+
+    pub fn free(&mut self, extent: Extent) {
+        if extent.is_empty() {
+            return;
+        }
+        let (mut offset, mut len) = (extent.offset, extent.len);
+        if let Some((&prev_offset, &prev_len)) = self.by_offset.range(..offset).next_back() {
+            if prev_offset + prev_len == offset {
+                self.remove(prev_offset, prev_len);
+                offset = prev_offset;
+                len += prev_len;
+            }
+        }
+        if let Some(&next_len) = self.by_offset.get(&extent.end()) {
+            self.remove(extent.end(), next_len);
+            len += next_len;
+        }
+        self.insert(offset, len);
+    }
+
+    /// Frees `extent` once the next sync has replaced the page map on disk.
+    pub fn free_after_sync(&mut self, extent: Extent) {
+        if !extent.is_empty() {
+            self.pending.push(extent);
+        }
+    }
+
+    /// Frees the extents given up before a sync that has just completed.
+    pub fn release_pending(&mut self) {
+        for extent in std::mem::take(&mut self.pending) {
+            self.free(extent);
+        }
+    }
+
+    fn insert(&mut self, offset: u64, len: u64) {
+        self.by_offset.insert(offset, len);
+        self.by_len.insert((len, offset));
+    }
+
+    fn remove(&mut self, offset: u64, len: u64) {
+        self.by_offset.remove(&offset);
+        self.by_len.remove(&(len, offset));
+    }
+}
+
+#[cfg(test)]
+mod free_space_test {
+    use super::*;
+
+    mod new {
+        use super::*;
+
+        #[test]
+        fn 使用中の領域の隙間を空き領域とすること() {
+            // Act
+            let free_space = FreeSpace::new(
+                [
+                    Extent::new(4096, 512),
+                    Extent::new(0, 4096),
+                    Extent::new(6144, 1024),
+                ],
+                16384,
+            );
+
+            // Assert
+            assert_eq!(
+                free_space.by_offset,
+                BTreeMap::from([(4608, 1536), (7168, 9216)])
+            );
+        }
+    }
+
+    mod allocate {
+        use super::*;
+
+        #[test]
+        fn 収まる空き領域のうち最も小さいものを割り当てること() {
+            // Arrange
+            let mut free_space =
+                FreeSpace::new([Extent::new(0, 4096), Extent::new(6144, 512)], 8192);
+
+            // Act
+            let extent = free_space.allocate(1000);
+
+            // Assert
+            assert_eq!(extent, Extent::new(6656, 1024));
+            assert_eq!(
+                free_space.by_offset,
+                BTreeMap::from([(4096, 2048), (7680, 512)])
+            );
+        }
+
+        #[test]
+        fn 収まる空き領域がない場合末尾の空き領域から伸ばすこと() {
+            // Arrange
+            let mut free_space =
+                FreeSpace::new([Extent::new(0, 4096), Extent::new(4608, 3072)], 8192);
+
+            // Act
+            let extent = free_space.allocate(2048);
+
+            // Assert
+            assert_eq!(extent, Extent::new(7680, 2048));
+            assert_eq!(free_space.end, 9728);
+            assert_eq!(free_space.by_offset, BTreeMap::from([(4096, 512)]));
+        }
+    }
+
+    mod free {
+        use super::*;
+
+        #[test]
+        fn 隣り合う空き領域とまとめること() {
+            // Arrange
+            let mut free_space = FreeSpace::new([Extent::new(0, 4096)], 4096);
+            let extents: Vec<_> = (0..3).map(|_| free_space.allocate(512)).collect();
+
+            // Act
+            free_space.free(extents[0]);
+            free_space.free(extents[2]);
+            free_space.free(extents[1]);
+
+            // Assert
+            assert_eq!(free_space.by_offset, BTreeMap::from([(4096, 1536)]));
+            assert_eq!(free_space.by_len, BTreeSet::from([(1536, 4096)]));
+        }
+    }
+}
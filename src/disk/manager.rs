@@ -1,97 +1,908 @@
-use super::page::PageId;
-use std::{
-    fs::{File, OpenOptions},
-    io::{self, Read, Seek, SeekFrom, Write},
-    path::Path,
+use super::{
+    page::{stamp_checksum, verify_checksum, Page, PageHeader, PageId},
+    Cipher, Compression, CompressionStats, EncryptionKey, Error, Extent, FileStore, FreeSpace,
+    PageMap, PageStore, Superblock,
 };
+use std::{io, iter, mem::size_of, path::Path};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+#[derive(Debug, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct FreePage {
+    pub next_page_id: PageId,
+    /// Marks the page as being on the free list, so that it is not freed
+    /// again.
+    pub magic: [u8; 8],
+}
+
+impl FreePage {
+    pub const MAGIC: [u8; 8] = *b"FREEPAGE";
+}
+
+/// Settings for a database opened by [`DiskManager::with_options`]. They only
+/// take effect when the database is created; an existing database keeps the
+/// settings recorded in its superblock. The exception is `encryption_key`,
+/// which has to be given every time an encrypted database is opened.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub page_size: usize,
+    pub compression: Compression,
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            page_size: DiskManager::DEFAULT_PAGE_SIZE,
+            compression: Compression::None,
+            encryption_key: None,
+        }
+    }
+}
 
 pub struct DiskManager {
-    heap_file: File,
-    next_page_id: PageId,
+    store: Box<dyn PageStore>,
+    superblock: Superblock,
+    /// Whether allocating or freeing pages changed the superblock since it
+    /// was last written.
+    is_superblock_dirty: bool,
+    compression: Compression,
+    compression_stats: CompressionStats,
+    /// Where each page is stored when the database is compressed.
+    page_map: Option<PageMap>,
+    free_space: FreeSpace,
+    cipher: Option<Cipher>,
 }
 
 impl DiskManager {
-    pub const PAGE_SIZE: usize = 4096;
+    pub const DEFAULT_PAGE_SIZE: usize = 4096;
+    pub const PAGE_SIZES: [usize; 4] = [4096, 8192, 16384, 32768];
+    pub const SUPERBLOCK_PAGE_ID: PageId = PageId::new(0);
+
+    pub fn new<T: 'static + PageStore>(store: T) -> Result<Self, Error> {
+        Self::with_options(store, Options::default())
+    }
+
+    pub fn with_options<T: 'static + PageStore>(store: T, options: Options) -> Result<Self, Error> {
+        Self::check_page_size(options.page_size)?;
+        if options.compression != Compression::None && options.encryption_key.is_some() {
+            return Err(Error::CompressionWithEncryption);
+        }
+        options.compression.check()?;
+        let mut disk = Self {
+            store: Box::new(store),
+            superblock: Superblock::new(
+                Self::SUPERBLOCK_PAGE_ID.next(),
+                options.page_size,
+                options.compression,
+            ),
+            is_superblock_dirty: false,
+            compression: Compression::None,
+            compression_stats: CompressionStats::default(),
+            page_map: None,
+            free_space: FreeSpace::default(),
+            cipher: None,
+        };
+        if disk.store.page_count() == 0 {
+            disk.store.set_page_size(options.page_size);
+            disk.store.allocate()?;
+            if let Some(key) = &options.encryption_key {
+                disk.superblock.is_encrypted = 1;
+                disk.superblock.key_check = Cipher::new(key).key_check()?;
+            }
+            disk.write_superblock()?;
+        } else {
+            disk.read_superblock()?;
+        }
+        disk.compression = disk.superblock.compression()?;
+        disk.cipher = disk.superblock.cipher(options.encryption_key.as_ref())?;
+        if disk.compression != Compression::None {
+            disk.read_page_map()?;
+        }
+        Ok(disk)
+    }
+
+    pub fn open(heap_file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(FileStore::open(heap_file_path)?)
+    }
+
+    /// Opens the heap file with `O_DIRECT`, leaving the buffer pool as the
+    /// only cache of its pages.
+    #[cfg(target_os = "linux")]
+    pub fn open_direct(heap_file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(FileStore::open_direct(heap_file_path)?)
+    }
+
+    pub fn open_encrypted(
+        heap_file_path: impl AsRef<Path>,
+        encryption_key: EncryptionKey,
+    ) -> Result<Self, Error> {
+        let options = Options {
+            encryption_key: Some(encryption_key),
+            ..Default::default()
+        };
+        Self::with_options(FileStore::open(heap_file_path)?, options)
+    }
+
+    pub fn check_page_size(page_size: usize) -> Result<(), Error> {
+        if Self::PAGE_SIZES.contains(&page_size) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedPageSize(page_size))
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.superblock.page_size as usize
+    }
+
+    /// Bytes of each page left to its user once the encryption trailer, if
+    /// any, is set aside. Pages passed in and out of this manager have this
+    /// size, except for the superblock.
+    pub fn usable_page_size(&self) -> usize {
+        match self.cipher {
+            Some(_) => self.page_size() - Cipher::TRAILER_SIZE,
+            None => self.page_size(),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_stats
+    }
 
-    pub fn new(heap_file: File) -> io::Result<Self> {
-        let heap_file_size = heap_file.metadata()?.len();
-        let next_page_id = PageId::new(heap_file_size / Self::PAGE_SIZE as u64);
-        Ok(Self {
-            heap_file,
-            next_page_id,
-        })
+    pub fn catalog_page_id(&self) -> Option<PageId> {
+        self.superblock.catalog_page_id.valid()
     }
 
-    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
-        let heap_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(heap_file_path)?;
-        Self::new(heap_file)
+    pub fn set_catalog_page_id(&mut self, catalog_page_id: Option<PageId>) -> io::Result<()> {
+        self.superblock.catalog_page_id = catalog_page_id.into();
+        self.write_superblock()
     }
 
-    pub fn allocate_page(&mut self) -> PageId {
-        let page_id = self.next_page_id;
-        self.next_page_id = page_id.next();
-        page_id
+    /// First transaction id that may not have been handed out before. Ids
+    /// start at 1.
+    pub fn next_xid(&self) -> u64 {
+        self.superblock.next_xid
+    }
+
+    /// Records that transaction ids below `next_xid` may be in use, and
+    /// makes that durable, so that none of them is handed out again after a
+    /// crash.
+    pub fn reserve_xids(&mut self, next_xid: u64) -> io::Result<()> {
+        self.superblock.next_xid = next_xid;
+        self.is_superblock_dirty = true;
+        self.sync()
+    }
+
+    /// Hands out the page at the head of the free list, or a new one at the
+    /// end of the store. The superblock is only written by `sync`, so after
+    /// a crash its free list may start at a page that had already been
+    /// taken off it. Such a list is dropped, leaking the pages left on it,
+    /// rather than handing a page out twice.
+    pub fn allocate_page(&mut self) -> io::Result<PageId> {
+        if let Some(page_id) = self.superblock.free_page_id.valid() {
+            let free_page = self.read_free_page(page_id)?;
+            self.superblock.free_page_id = free_page
+                .as_ref()
+                .map_or(PageId::INVALID_PAGE_ID, |free_page| free_page.next_page_id);
+            self.is_superblock_dirty = true;
+            if free_page.is_some() {
+                // Clears the mark, so that the page can be freed again before
+                // anything else is written to it.
+                let mut page = Page::new(self.usable_page_size());
+                stamp_checksum(page_id, &mut page);
+                self.write_page_data(page_id, &page)?;
+                return Ok(page_id);
+            }
+        }
+        let page_id = self.extend()?;
+        self.superblock.next_page_id = page_id.next();
+        self.is_superblock_dirty = true;
+        Ok(page_id)
+    }
+
+    /// Allocates pages until `page_id` is one of them, for recovery to redo
+    /// changes to pages whose allocation was lost in a crash.
+    pub fn allocate_up_to(&mut self, page_id: PageId) -> io::Result<()> {
+        if self.page_count() > page_id.value() {
+            return Ok(());
+        }
+        while self.page_count() <= page_id.value() {
+            self.superblock.next_page_id = self.extend()?.next();
+        }
+        self.is_superblock_dirty = true;
+        Ok(())
+    }
+
+    pub fn deallocate_page(&mut self, page_id: PageId) -> io::Result<()> {
+        assert_ne!(
+            page_id,
+            Self::SUPERBLOCK_PAGE_ID,
+            "superblock must not be freed"
+        );
+        if self.read_free_page(page_id)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {:?} is already free", page_id),
+            ));
+        }
+        let free_page = FreePage {
+            next_page_id: self.superblock.free_page_id,
+            magic: FreePage::MAGIC,
+        };
+        let mut page = Page::new(self.usable_page_size());
+        page[PageHeader::SIZE..][..size_of::<FreePage>()].copy_from_slice(free_page.as_bytes());
+        stamp_checksum(page_id, &mut page);
+        self.write_page_data(page_id, &page)?;
+        self.superblock.free_page_id = page_id;
+        self.is_superblock_dirty = true;
+        Ok(())
+    }
+
+    /// The page as it is on the free list, or `None` if it is not on it.
+    fn read_free_page(&self, page_id: PageId) -> io::Result<Option<FreePage>> {
+        let mut page = Page::new(self.usable_page_size());
+        self.read_page_data(page_id, &mut page)?;
+        let free_page = FreePage::read_from_prefix(&page[PageHeader::SIZE..]).unwrap();
+        let is_free = verify_checksum(page_id, &page) && free_page.magic == FreePage::MAGIC;
+        Ok(is_free.then_some(free_page))
     }
 
     pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
-        let offset = Self::calc_offset(page_id);
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.write_all(data)
+        if self.is_mapped(page_id) {
+            return self.write_mapped_page(page_id, data);
+        }
+        match self.encrypt_page(page_id, data)? {
+            Some(page) => self.store.write_page(page_id, &page),
+            None => self.store.write_page(page_id, data),
+        }
+    }
+
+    pub fn read_page_data(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        if self.is_mapped(page_id) {
+            return self.read_mapped_page(page_id, data);
+        }
+        if !self.is_encrypted_page(page_id) {
+            return self.store.read_page(page_id, data);
+        }
+        let mut page = Page::new(self.page_size());
+        self.store.read_page(page_id, &mut page)?;
+        self.decrypt_page(page_id, &mut page, data)
     }
 
-    pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
-        let offset = Self::calc_offset(page_id);
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.read_exact(data)
+    pub fn read_pages_data(&self, pages: &mut [(PageId, &mut [u8])]) -> io::Result<()> {
+        if self.page_map.is_some() {
+            for (page_id, data) in pages.iter_mut() {
+                self.read_page_data(*page_id, data)?;
+            }
+            return Ok(());
+        }
+        if !pages
+            .iter()
+            .any(|(page_id, _)| self.is_encrypted_page(*page_id))
+        {
+            return self.store.read_pages(pages);
+        }
+        let mut encrypted: Vec<_> = pages
+            .iter()
+            .map(|(page_id, _)| (*page_id, Page::new(self.page_size())))
+            .collect();
+        let mut batch: Vec<_> = encrypted
+            .iter_mut()
+            .map(|(page_id, page)| (*page_id, &mut page[..]))
+            .collect();
+        self.store.read_pages(&mut batch)?;
+        for ((page_id, data), (_, page)) in pages.iter_mut().zip(&mut encrypted) {
+            if self.is_encrypted_page(*page_id) {
+                self.decrypt_page(*page_id, page, data)?;
+            } else {
+                data.copy_from_slice(&page[..data.len()]);
+            }
+        }
+        Ok(())
     }
 
+    pub fn write_pages_data(&mut self, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        if self.page_map.is_some() {
+            for &(page_id, data) in pages {
+                self.write_page_data(page_id, data)?;
+            }
+            return Ok(());
+        }
+        let encrypted = pages
+            .iter()
+            .map(|&(page_id, data)| self.encrypt_page(page_id, data))
+            .collect::<io::Result<Vec<_>>>()?;
+        let batch: Vec<_> = pages
+            .iter()
+            .zip(&encrypted)
+            .map(|(&(page_id, data), encrypted)| match encrypted {
+                Some(page) => (page_id, &page[..]),
+                None => (page_id, data),
+            })
+            .collect();
+        self.store.write_pages(&batch)
+    }
+
+    /// Makes everything written so far durable, along with the superblock
+    /// as pages have been allocated and freed since the last sync, and the
+    /// page map of a compressed database.
     pub fn sync(&mut self) -> io::Result<()> {
-        self.heap_file.flush()?;
-        self.heap_file.sync_all()
+        self.write_page_map()?;
+        if self.is_superblock_dirty {
+            self.write_superblock()?;
+        }
+        self.store.sync()?;
+        self.free_space.release_pending();
+        Ok(())
     }
 
-    fn calc_offset(page_id: PageId) -> u64 {
-        page_id.value() * Self::PAGE_SIZE as u64
+    fn page_count(&self) -> u64 {
+        match &self.page_map {
+            Some(page_map) => page_map.len(),
+            None => self.store.page_count(),
+        }
+    }
+
+    /// Adds a page at the end of the database. A page of a compressed
+    /// database takes up no space in the store until it is written.
+    fn extend(&mut self) -> io::Result<PageId> {
+        match &mut self.page_map {
+            Some(page_map) => {
+                let page_id = PageId::new(page_map.len());
+                page_map.resize(page_id.next().value());
+                Ok(page_id)
+            }
+            None => self.store.allocate(),
+        }
+    }
+
+    /// Whether the page is stored where the page map says. The superblock
+    /// never is, so that it can be read before the page map.
+    fn is_mapped(&self, page_id: PageId) -> bool {
+        page_id != Self::SUPERBLOCK_PAGE_ID && self.page_map.is_some()
+    }
+
+    /// Whether the page is encrypted on its way to the store. The superblock
+    /// never is, so that it can be read before the cipher is set up.
+    fn is_encrypted_page(&self, page_id: PageId) -> bool {
+        page_id != Self::SUPERBLOCK_PAGE_ID && self.cipher.is_some()
+    }
+
+    fn encrypt_page(&self, page_id: PageId, data: &[u8]) -> io::Result<Option<Page>> {
+        let Some(cipher) = self
+            .cipher
+            .as_ref()
+            .filter(|_| self.is_encrypted_page(page_id))
+        else {
+            return Ok(None);
+        };
+        debug_assert!(data.len() <= self.usable_page_size());
+        let mut page = Page::new(self.page_size());
+        page[..data.len()].copy_from_slice(data);
+        cipher.encrypt_page(page_id, &mut page)?;
+        Ok(Some(page))
+    }
+
+    fn decrypt_page(&self, page_id: PageId, page: &mut Page, data: &mut [u8]) -> io::Result<()> {
+        // A page allocated but never written has nothing to decrypt.
+        if page.iter().all(|&byte| byte == 0) {
+            data.fill(0);
+            return Ok(());
+        }
+        if let Some(cipher) = &self.cipher {
+            cipher.decrypt_page(page_id, page)?;
+        }
+        data.copy_from_slice(&page[..data.len()]);
+        Ok(())
+    }
+
+    /// Compresses the page into a free extent and points the page map at it.
+    /// The extent it replaces is only reused after the next sync, as the
+    /// page map on disk may still point at it.
+    fn write_mapped_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        let mut page = Page::new(self.page_size());
+        let compressed_len = self.compression.compress_page(data, &mut page);
+        self.compression_stats.record(data.len(), compressed_len);
+        let stored_len = compressed_len.unwrap_or_else(|| {
+            page[..data.len()].copy_from_slice(data);
+            data.len()
+        });
+        let stored = &page[..stored_len.next_multiple_of(Extent::SECTOR_SIZE)];
+        let extent = write_extent(&mut *self.store, &mut self.free_space, stored)?;
+        if let Some(page_map) = &mut self.page_map {
+            let replaced = page_map.set(page_id, extent);
+            self.free_space.free_after_sync(replaced);
+        }
+        Ok(())
+    }
+
+    fn read_mapped_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        let extent = self
+            .page_map
+            .as_ref()
+            .and_then(|page_map| page_map.get(page_id))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        // A page allocated but never written has no extent yet.
+        if extent.is_empty() {
+            data.fill(0);
+            return Ok(());
+        }
+        let mut page = Page::new(extent.len as usize);
+        self.store.read_at(extent.offset, &mut page)?;
+        if PageHeader::ref_from_prefix(&page).unwrap().compressed_len != 0 {
+            return self.compression.decompress_page(&page, data);
+        }
+        let stored = page.get(..data.len()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "stored page is truncated")
+        })?;
+        data.copy_from_slice(stored);
+        Ok(())
+    }
+
+    /// Writes the chunks of the page map that changed and a new directory to
+    /// free extents, and makes them durable before the superblock is pointed
+    /// at them.
+    fn write_page_map(&mut self) -> io::Result<()> {
+        let Some(page_map) = self
+            .page_map
+            .as_mut()
+            .filter(|page_map| page_map.is_dirty())
+        else {
+            return Ok(());
+        };
+        for index in page_map.dirty_chunks() {
+            let chunk = page_map.encode_chunk(index);
+            let extent = write_extent(&mut *self.store, &mut self.free_space, &chunk)?;
+            self.free_space
+                .free_after_sync(page_map.set_chunk(index, extent));
+        }
+        let (directory, checksum) = page_map.encode_directory();
+        let extent = write_extent(&mut *self.store, &mut self.free_space, &directory)?;
+        if let Err(err) = self.store.sync() {
+            self.free_space.free(extent);
+            return Err(err);
+        }
+        page_map.mark_directory_written();
+        self.free_space.free_after_sync(self.superblock.page_map);
+        self.superblock.page_map = extent;
+        self.superblock.page_map_chunk_count = page_map.chunk_count();
+        self.superblock.page_map_checksum = checksum;
+        self.is_superblock_dirty = true;
+        Ok(())
+    }
+
+    /// Loads the page map of a compressed database, and takes the rest of
+    /// the store as free space. Extents written since the last sync are not
+    /// on the map, so a crash leaves them free.
+    fn read_page_map(&mut self) -> Result<(), Error> {
+        let page_size = self.page_size();
+        let mut page_map = PageMap::new(page_size);
+        let directory = self.superblock.page_map;
+        if !directory.is_empty() {
+            let mut page = Page::new(directory.len as usize);
+            self.store.read_at(directory.offset, &mut page)?;
+            let chunks = PageMap::decode_directory(
+                &page,
+                self.superblock.page_map_chunk_count,
+                self.superblock.page_map_checksum,
+            )?;
+            let mut chunk = Page::new(page_size);
+            for (index, extent) in chunks.into_iter().enumerate() {
+                self.store.read_at(extent.offset, &mut chunk)?;
+                page_map.load_chunk(index, extent, &chunk)?;
+            }
+        }
+        page_map.resize(self.superblock.next_page_id.value());
+        let superblock = Extent::new(0, page_size as u64);
+        let used = iter::once(superblock)
+            .chain(iter::once(directory))
+            .chain(page_map.used_extents());
+        self.free_space = FreeSpace::new(used, self.store.page_count() * page_size as u64);
+        self.page_map = Some(page_map);
+        Ok(())
+    }
+
+    fn read_superblock(&mut self) -> Result<(), Error> {
+        // The superblock fits in the smallest page, so read that much first to
+        // learn the page size, then the whole page to verify its checksum.
+        let mut page = Page::new(Self::PAGE_SIZES[0]);
+        self.read_superblock_page(&mut page)?;
+        let superblock = Superblock::read_from_prefix(&page[PageHeader::SIZE..]).unwrap();
+        superblock.validate()?;
+        let page_size = superblock.page_size as usize;
+        self.store.set_page_size(page_size);
+        let mut page = Page::new(page_size);
+        self.read_superblock_page(&mut page)?;
+        if !verify_checksum(Self::SUPERBLOCK_PAGE_ID, &page) {
+            return Err(Error::ChecksumMismatch {
+                page_id: Self::SUPERBLOCK_PAGE_ID,
+            });
+        }
+        self.superblock = superblock;
+        Ok(())
+    }
+
+    fn read_superblock_page(&self, page: &mut Page) -> Result<(), Error> {
+        match self.read_page_data(Self::SUPERBLOCK_PAGE_ID, page) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(Error::InvalidMagic),
+            result => Ok(result?),
+        }
+    }
+
+    fn write_superblock(&mut self) -> io::Result<()> {
+        let mut page = Page::new(self.page_size());
+        page[PageHeader::SIZE..][..size_of::<Superblock>()]
+            .copy_from_slice(self.superblock.as_bytes());
+        stamp_checksum(Self::SUPERBLOCK_PAGE_ID, &mut page);
+        self.write_page_data(Self::SUPERBLOCK_PAGE_ID, &page)?;
+        self.is_superblock_dirty = false;
+        Ok(())
     }
 }
 
+impl Drop for DiskManager {
+    fn drop(&mut self) {
+        if self.page_map.is_some() {
+            // The superblock must not point at a page map that is not on
+            // disk yet.
+            let _ = self.sync();
+        } else if self.is_superblock_dirty {
+            // Without a sync this is lost in a crash anyway.
+            let _ = self.write_superblock();
+        }
+    }
+}
+
+/// Writes `bytes` to a free extent of `store`.
+fn write_extent(
+    store: &mut dyn PageStore,
+    free_space: &mut FreeSpace,
+    bytes: &[u8],
+) -> io::Result<Extent> {
+    let extent = free_space.allocate(bytes.len() as u64);
+    if let Err(err) = store.write_at(extent.offset, bytes) {
+        free_space.free(extent);
+        return Err(err);
+    }
+    Ok(extent)
+}
+
 #[cfg(test)]
 mod disk_manager_test {
     use super::*;
+    use crate::disk::MemoryStore;
+
+    fn write_superblock_page(store: &mut MemoryStore, superblock: &Superblock) {
+        let mut page = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+        page[PageHeader::SIZE..][..size_of::<Superblock>()].copy_from_slice(superblock.as_bytes());
+        stamp_checksum(DiskManager::SUPERBLOCK_PAGE_ID, &mut page);
+        store
+            .write_page(DiskManager::SUPERBLOCK_PAGE_ID, &page)
+            .unwrap();
+    }
 
     mod new {
         use super::*;
-        use std::fs::remove_file;
+
+        #[test]
+        fn 空のストアの場合スーパーブロックが書き込まれること() {
+            // Arrange
+            let store = MemoryStore::default();
+
+            // Act
+            let disk = DiskManager::new(store.clone()).unwrap();
+
+            // Assert
+            assert_eq!(store.page_count(), 1);
+            assert_eq!(
+                disk.superblock,
+                Superblock::new(
+                    PageId::new(1),
+                    DiskManager::DEFAULT_PAGE_SIZE,
+                    Compression::None
+                )
+            );
+        }
+
+        #[test]
+        fn スーパーブロックからページの割り当て状況を読み込むこと() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            let mut superblock = Superblock::new(
+                PageId::new(3),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None,
+            );
+            superblock.free_page_id = PageId::new(2);
+            write_superblock_page(&mut store, &superblock);
+
+            // Act
+            let disk = DiskManager::new(store).unwrap();
+
+            // Assert
+            assert_eq!(disk.superblock, superblock);
+        }
 
         #[allow(non_snake_case)]
         #[test]
-        fn DiskManagerが正しく生成されること() {
+        fn データベースファイルでない場合InvalidMagicを返すこと() {
             // Arrange
-            let file_path = "disk_manager_test::new::0.txt";
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(file_path)
+            let mut store = MemoryStore::default();
+            store
+                .write_page(PageId::new(0), &[b'a'; DiskManager::DEFAULT_PAGE_SIZE])
                 .unwrap();
-            file.write_all(b"Hello, world!").unwrap();
-            file.seek(SeekFrom::Start(0)).unwrap();
 
             // Act
-            let mut disk = DiskManager::new(file).unwrap();
-            let mut content = String::new();
-            disk.heap_file.read_to_string(&mut content).unwrap();
+            let result = DiskManager::new(store);
+
+            // Assert
+            assert!(matches!(result, Err(Error::InvalidMagic)));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn ページサイズに満たないファイルの場合InvalidMagicを返すこと() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            store.write_page(PageId::new(0), b"Hello, world!").unwrap();
+
+            // Act
+            let result = DiskManager::new(store);
 
             // Assert
-            assert_eq!(content, "Hello, world!");
-            assert_eq!(disk.next_page_id, PageId::new(0));
+            assert!(matches!(result, Err(Error::InvalidMagic)));
+        }
 
-            // Cleanup
-            remove_file(file_path).unwrap();
+        #[allow(non_snake_case)]
+        #[test]
+        fn フォーマットのバージョンが異なる場合IncompatibleVersionを返すこと() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            let mut superblock = Superblock::new(
+                PageId::new(1),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None,
+            );
+            superblock.version = 0;
+            write_superblock_page(&mut store, &superblock);
+
+            // Act
+            let result = DiskManager::new(store);
+
+            // Assert
+            assert!(matches!(
+                result,
+                Err(Error::IncompatibleVersion { found: 0, .. })
+            ));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn スーパーブロックが壊れている場合ChecksumMismatchを返すこと() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            DiskManager::new(store.clone()).unwrap();
+            let mut page = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            store.read_page(PageId::new(0), &mut page).unwrap();
+            page[DiskManager::DEFAULT_PAGE_SIZE - 1] = 0xff;
+            store.write_page(PageId::new(0), &page).unwrap();
+
+            // Act
+            let result = DiskManager::new(store);
+
+            // Assert
+            assert!(matches!(
+                result,
+                Err(Error::ChecksumMismatch { page_id }) if page_id == DiskManager::SUPERBLOCK_PAGE_ID
+            ));
+        }
+    }
+
+    mod with_options {
+        use super::*;
+
+        #[test]
+        fn 指定したページサイズでデータベースが作成され開き直しても維持されること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let options = Options {
+                page_size: 16384,
+                ..Default::default()
+            };
+            {
+                let mut disk = DiskManager::with_options(store.clone(), options).unwrap();
+
+                // Act
+                disk.allocate_page().unwrap();
+            }
+
+            // Assert
+            let disk = DiskManager::new(store.clone()).unwrap();
+            assert_eq!(disk.page_size(), 16384);
+            assert_eq!(disk.superblock.next_page_id, PageId::new(2));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 未対応のページサイズの場合UnsupportedPageSizeを返すこと() {
+            // Arrange
+            let options = Options {
+                page_size: 5000,
+                ..Default::default()
+            };
+
+            // Act
+            let result = DiskManager::with_options(MemoryStore::default(), options);
+
+            // Assert
+            assert!(matches!(result, Err(Error::UnsupportedPageSize(5000))));
+        }
+
+        #[cfg(feature = "lz4")]
+        #[test]
+        fn 圧縮を有効にした場合ページが圧縮後の大きさで詰めて書き込まれること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let options = Options {
+                compression: Compression::Lz4,
+                ..Default::default()
+            };
+            let pages: Vec<_> = (1..=64)
+                .map(|i| {
+                    let page_id = PageId::new(i);
+                    let mut data = Page::new(DiskManager::DEFAULT_PAGE_SIZE);
+                    data[PageHeader::SIZE..].fill(i as u8);
+                    stamp_checksum(page_id, &mut data);
+                    (page_id, data)
+                })
+                .collect();
+            {
+                let mut disk = DiskManager::with_options(store.clone(), options).unwrap();
+                for _ in &pages {
+                    disk.allocate_page().unwrap();
+                }
+
+                // Act
+                for (page_id, data) in &pages {
+                    disk.write_page_data(*page_id, data).unwrap();
+                }
+
+                // Assert
+                let stats = disk.compression_stats();
+                assert_eq!(stats.pages_compressed, 64);
+                assert!(stats.ratio() > 10.0);
+            }
+            assert!(store.page_count() < 16);
+            let disk = DiskManager::new(store).unwrap();
+            assert_eq!(disk.compression(), Compression::Lz4);
+            for (page_id, data) in &pages {
+                let mut page = Page::new(DiskManager::DEFAULT_PAGE_SIZE);
+                disk.read_page_data(*page_id, &mut page).unwrap();
+                assert_eq!(&page, data);
+            }
+        }
+
+        #[test]
+        fn 暗号化したデータベースを同じ鍵で開き直して読み込めること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let key = EncryptionKey::new([7; 32]);
+            let options = Options {
+                encryption_key: Some(key.clone()),
+                ..Default::default()
+            };
+            let (page_id, data) = {
+                let mut disk = DiskManager::with_options(store.clone(), options.clone()).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                let mut data = Page::new(disk.usable_page_size());
+                data[PageHeader::SIZE..].fill(b'a');
+                stamp_checksum(page_id, &mut data);
+
+                // Act
+                disk.write_page_data(page_id, &data).unwrap();
+                (page_id, data)
+            };
+
+            // Assert
+            let mut raw = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            store.read_page(page_id, &mut raw).unwrap();
+            assert!(!raw.windows(16).any(|window| window == [b'a'; 16]));
+            let disk = DiskManager::with_options(store, options).unwrap();
+            let mut page = Page::new(disk.usable_page_size());
+            disk.read_page_data(page_id, &mut page).unwrap();
+            assert!(disk.is_encrypted());
+            assert_eq!(
+                disk.usable_page_size(),
+                DiskManager::DEFAULT_PAGE_SIZE - Cipher::TRAILER_SIZE
+            );
+            assert_eq!(page, data);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 異なる鍵で開いた場合WrongEncryptionKeyを返すこと() {
+            // Arrange
+            let store = MemoryStore::default();
+            let options = Options {
+                encryption_key: Some(EncryptionKey::new([7; 32])),
+                ..Default::default()
+            };
+            DiskManager::with_options(store.clone(), options).unwrap();
+            let options = Options {
+                encryption_key: Some(EncryptionKey::new([8; 32])),
+                ..Default::default()
+            };
+
+            // Act
+            let result = DiskManager::with_options(store.clone(), options);
+
+            // Assert
+            assert!(matches!(result, Err(Error::WrongEncryptionKey)));
+            assert!(matches!(
+                DiskManager::new(store),
+                Err(Error::EncryptionKeyRequired)
+            ));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 暗号化されていないデータベースを鍵付きで開いた場合NotEncryptedを返すこと() {
+            // Arrange
+            let store = MemoryStore::default();
+            DiskManager::new(store.clone()).unwrap();
+            let options = Options {
+                encryption_key: Some(EncryptionKey::new([7; 32])),
+                ..Default::default()
+            };
+
+            // Act
+            let result = DiskManager::with_options(store, options);
+
+            // Assert
+            assert!(matches!(result, Err(Error::NotEncrypted)));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 圧縮と暗号化を同時に指定した場合CompressionWithEncryptionを返すこと() {
+            // Arrange
+            let options = Options {
+                compression: Compression::Lz4,
+                encryption_key: Some(EncryptionKey::new([7; 32])),
+                ..Default::default()
+            };
+
+            // Act
+            let result = DiskManager::with_options(MemoryStore::default(), options);
+
+            // Assert
+            assert!(matches!(result, Err(Error::CompressionWithEncryption)));
+        }
+
+        #[cfg(not(feature = "lz4"))]
+        #[allow(non_snake_case)]
+        #[test]
+        fn 無効な圧縮方式を指定した場合UnsupportedCompressionを返すこと() {
+            // Arrange
+            let options = Options {
+                compression: Compression::Lz4,
+                ..Default::default()
+            };
+
+            // Act
+            let result = DiskManager::with_options(MemoryStore::default(), options);
+
+            // Assert
+            assert!(matches!(result, Err(Error::UnsupportedCompression(1))));
         }
     }
 
@@ -103,99 +914,255 @@ mod disk_manager_test {
         fn すでに存在するファイルを正しく開けること() {
             // Arrange
             let file_path = "disk_manager_test::open::0.txt";
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(file_path)
-                .unwrap();
-            file.write_all(b"Hello, world!").unwrap();
-            file.flush().unwrap();
+            {
+                let mut disk = DiskManager::open(file_path).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                disk.write_page_data(page_id, &[b'a'; DiskManager::DEFAULT_PAGE_SIZE])
+                    .unwrap();
+            }
 
             // Act
-            let mut disk = DiskManager::open(file_path).unwrap();
-            let mut content = String::new();
-            disk.heap_file.read_to_string(&mut content).unwrap();
+            let disk = DiskManager::open(file_path).unwrap();
+            let mut data = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            disk.read_page_data(PageId::new(1), &mut data).unwrap();
 
             // Assert
-            assert_eq!(content, "Hello, world!");
-            assert_eq!(disk.next_page_id, PageId::new(0));
+            assert_eq!(data, [b'a'; DiskManager::DEFAULT_PAGE_SIZE]);
+            assert_eq!(disk.superblock.next_page_id, PageId::new(2));
 
             // Cleanup
             remove_file(file_path).unwrap();
         }
     }
 
-    mod allocate_page {
+    #[cfg(target_os = "linux")]
+    mod open_direct {
         use super::*;
         use std::fs::remove_file;
 
-        #[allow(non_snake_case)]
         #[test]
-        fn 現在のページIDを返し内部の値はインクリメントされていること() {
+        fn 作成したデータベースを開き直せること() {
             // Arrange
-            let file_path = "disk_manager_test::allocate_page::0.txt";
-            let mut file = File::create(file_path).unwrap();
-            file.flush().unwrap();
+            let file_path = "disk_manager_test::open_direct::0.txt";
+            let page_id = {
+                let mut disk = DiskManager::open_direct(file_path).unwrap();
+                disk.allocate_page().unwrap()
+            };
 
             // Act
-            let mut disk = DiskManager::new(file).unwrap();
-            let page_id = disk.allocate_page();
+            let disk = DiskManager::open_direct(file_path).unwrap();
 
             // Assert
-            assert_eq!(page_id, PageId::new(0));
-            assert_eq!(disk.next_page_id, PageId::new(0).next());
+            assert_eq!(disk.superblock.next_page_id, page_id.next());
 
             // Cleanup
             remove_file(file_path).unwrap();
         }
     }
 
-    mod write_page_data {
+    mod set_catalog_page_id {
         use super::*;
-        use std::fs::remove_file;
 
         #[test]
-        fn データをファイルに書き込めること() {
+        fn カタログのルートページが永続化されること() {
+            // Arrange
+            let store = MemoryStore::default();
+            {
+                let mut disk = DiskManager::new(store.clone()).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+
+                // Act
+                disk.set_catalog_page_id(Some(page_id)).unwrap();
+            }
+
+            // Assert
+            let disk = DiskManager::new(store).unwrap();
+            assert_eq!(disk.catalog_page_id(), Some(PageId::new(1)));
+        }
+    }
+
+    mod allocate_page {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 現在のページIDを返し内部の値はインクリメントされていること() {
             // Arrange
-            let file_path = "disk_manager_test::write_page_data::0.txt";
-            let mut disk = DiskManager::open(file_path).unwrap();
+            let store = MemoryStore::default();
 
             // Act
-            disk.write_page_data(disk.next_page_id, b"Hello, world!")
-                .unwrap();
-            disk.heap_file.seek(SeekFrom::Start(0)).unwrap();
-            let mut content = String::new();
-            disk.heap_file.read_to_string(&mut content).unwrap();
+            let mut disk = DiskManager::new(store.clone()).unwrap();
+            let page_id = disk.allocate_page().unwrap();
 
             // Assert
-            assert_eq!(content, "Hello, world!");
+            assert_eq!(page_id, PageId::new(1));
+            assert_eq!(disk.superblock.next_page_id, PageId::new(1).next());
+            assert_eq!(store.page_count(), 2);
+        }
 
-            // Cleanup
-            remove_file(file_path).unwrap();
+        #[test]
+        fn スーパーブロックはsyncするまで書き込まないこと() {
+            // Arrange
+            let store = MemoryStore::default();
+            let mut disk = DiskManager::new(store.clone()).unwrap();
+            let read_next_page_id = || {
+                let mut page = [0u8; DiskManager::DEFAULT_PAGE_SIZE];
+                store
+                    .read_page(DiskManager::SUPERBLOCK_PAGE_ID, &mut page)
+                    .unwrap();
+                Superblock::read_from_prefix(&page[PageHeader::SIZE..])
+                    .unwrap()
+                    .next_page_id
+            };
+
+            // Act
+            for _ in 0..3 {
+                disk.allocate_page().unwrap();
+            }
+            let before_sync = read_next_page_id();
+            disk.sync().unwrap();
+
+            // Assert
+            assert_eq!(before_sync, PageId::new(1));
+            assert_eq!(read_next_page_id(), PageId::new(4));
+        }
+
+        #[test]
+        fn 同期されていないフリーリストの先頭が取り出し済みの場合新しいページを割り当てること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let (page_id, other_page_id) = {
+                let mut disk = DiskManager::new(store.clone()).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                let other_page_id = disk.allocate_page().unwrap();
+                disk.deallocate_page(other_page_id).unwrap();
+                disk.deallocate_page(page_id).unwrap();
+                disk.sync().unwrap();
+                assert_eq!(disk.allocate_page().unwrap(), page_id);
+                // Crashes before the superblock is written again.
+                std::mem::forget(disk);
+                (page_id, other_page_id)
+            };
+            let mut disk = DiskManager::new(store).unwrap();
+
+            // Act
+            let reused_page_id = disk.allocate_page().unwrap();
+
+            // Assert
+            assert_ne!(reused_page_id, page_id);
+            assert_ne!(reused_page_id, other_page_id);
+            assert_eq!(disk.superblock.free_page_id.valid(), None);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 解放済みのページがある場合そのページIDを再利用すること() {
+            // Arrange
+            let mut disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let first_page_id = disk.allocate_page().unwrap();
+            let second_page_id = disk.allocate_page().unwrap();
+            disk.deallocate_page(first_page_id).unwrap();
+            disk.deallocate_page(second_page_id).unwrap();
+
+            // Act
+            let page_ids = [
+                disk.allocate_page().unwrap(),
+                disk.allocate_page().unwrap(),
+                disk.allocate_page().unwrap(),
+            ];
+
+            // Assert
+            assert_eq!(page_ids, [second_page_id, first_page_id, PageId::new(3)]);
+            assert_eq!(disk.superblock.free_page_id.valid(), None);
         }
     }
 
-    mod read_page_data {
+    #[cfg(feature = "lz4")]
+    mod write_page_data {
         use super::*;
-        use std::fs::remove_file;
 
         #[test]
-        fn ファイルに書き込まれたデータを読み込めること() {
+        fn 圧縮したページを書き換えた後にクラッシュした場合同期した時点の内容を読めること() {
             // Arrange
-            let file_path = "disk_manager_test::read_page_data::0.txt";
-            let mut disk = DiskManager::open(file_path).unwrap();
-            disk.heap_file.write_all(b"Hello, world!").unwrap();
+            let store = MemoryStore::default();
+            let options = Options {
+                compression: Compression::Lz4,
+                ..Default::default()
+            };
+            let fill = |page_id: PageId, byte: u8| {
+                let mut data = Page::new(DiskManager::DEFAULT_PAGE_SIZE);
+                data[PageHeader::SIZE..].fill(byte);
+                stamp_checksum(page_id, &mut data);
+                data
+            };
+            let (page_id, synced) = {
+                let mut disk = DiskManager::with_options(store.clone(), options).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                let other_page_id = disk.allocate_page().unwrap();
+                let synced = fill(page_id, b'a');
+                disk.write_page_data(page_id, &synced).unwrap();
+                disk.sync().unwrap();
 
-            // Act
-            let mut data = vec![0u8; 13];
-            disk.read_page_data(PageId::new(0), &mut data).unwrap();
+                // Act
+                disk.write_page_data(page_id, &fill(page_id, b'b')).unwrap();
+                for byte in b'c'..=b'z' {
+                    disk.write_page_data(other_page_id, &fill(other_page_id, byte))
+                        .unwrap();
+                }
+                // Crashes before the page map is written again.
+                std::mem::forget(disk);
+                (page_id, synced)
+            };
+
+            // Assert
+            let disk = DiskManager::new(store).unwrap();
+            let mut page = Page::new(DiskManager::DEFAULT_PAGE_SIZE);
+            disk.read_page_data(page_id, &mut page).unwrap();
+            assert_eq!(page, synced);
+        }
+    }
+
+    mod deallocate_page {
+        use super::*;
+
+        #[test]
+        fn 解放したページがフリーリストの先頭として永続化されること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let page_id = {
+                let mut disk = DiskManager::new(store.clone()).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                disk.allocate_page().unwrap();
+
+                // Act
+                disk.deallocate_page(page_id).unwrap();
+                page_id
+            };
 
             // Assert
-            assert_eq!(data, b"Hello, world!");
+            let mut disk = DiskManager::new(store).unwrap();
+            assert_eq!(disk.superblock.free_page_id, page_id);
+            assert_eq!(disk.allocate_page().unwrap(), page_id);
+        }
 
-            // Cleanup
-            remove_file(file_path).unwrap();
+        #[test]
+        fn 解放済みのページを再び解放しようとした場合エラーを返すこと() {
+            // Arrange
+            let mut disk = DiskManager::new(MemoryStore::default()).unwrap();
+            let page_id = disk.allocate_page().unwrap();
+            let other_page_id = disk.allocate_page().unwrap();
+            disk.deallocate_page(page_id).unwrap();
+            disk.deallocate_page(other_page_id).unwrap();
+
+            // Act
+            let result = disk.deallocate_page(page_id);
+
+            // Assert
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(disk.allocate_page().unwrap(), other_page_id);
+            assert_eq!(disk.allocate_page().unwrap(), page_id);
+            assert_eq!(disk.superblock.free_page_id.valid(), None);
+            disk.deallocate_page(page_id).unwrap();
         }
     }
 }
@@ -0,0 +1,225 @@
+use super::{verify_checksum, Compression, DiskManager, Error, PageHeader, PageId, Superblock};
+use memmap2::Mmap;
+use std::{fs::File, io, ops::Deref, path::Path, rc::Rc};
+use zerocopy::FromBytes;
+
+/// Read-only view of a database file mapped into memory. Pages are handed out
+/// straight from the mapping instead of being copied into buffers, which suits
+/// analytical scans over a file that is not being written.
+pub struct MmapFile {
+    map: Rc<Mmap>,
+    page_size: usize,
+    catalog_page_id: PageId,
+    next_xid: u64,
+}
+
+impl MmapFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // SAFETY: the file must not be modified while it is mapped, which is
+        // the contract of opening a database read-only.
+        let map = unsafe { Mmap::map(&file)? };
+        let superblock = map
+            .get(PageHeader::SIZE..)
+            .and_then(Superblock::read_from_prefix)
+            .ok_or(Error::InvalidMagic)?;
+        superblock.validate()?;
+        if superblock.compression()? != Compression::None || superblock.is_encrypted != 0 {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compressed or encrypted databases cannot be memory-mapped",
+            )));
+        }
+        let mmap_file = Self {
+            map: Rc::new(map),
+            page_size: superblock.page_size as usize,
+            catalog_page_id: superblock.catalog_page_id,
+            next_xid: superblock.next_xid,
+        };
+        mmap_file.read_page(DiskManager::SUPERBLOCK_PAGE_ID)?;
+        Ok(mmap_file)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn catalog_page_id(&self) -> Option<PageId> {
+        self.catalog_page_id.valid()
+    }
+
+    /// Transaction id above any found in the rows of the file.
+    pub fn next_xid(&self) -> u64 {
+        self.next_xid
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<MappedPage, Error> {
+        let offset = (page_id.value() as usize)
+            .checked_mul(self.page_size)
+            .filter(|offset| offset + self.page_size <= self.map.len())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let page = MappedPage {
+            map: self.map.clone(),
+            offset,
+            len: self.page_size,
+        };
+        if !verify_checksum(page_id, &page) {
+            return Err(Error::ChecksumMismatch { page_id });
+        }
+        Ok(page)
+    }
+}
+
+/// A page of an `MmapFile`, borrowed from the mapping without copying.
+#[derive(Clone)]
+pub struct MappedPage {
+    map: Rc<Mmap>,
+    offset: usize,
+    len: usize,
+}
+
+impl MappedPage {
+    pub fn body(&self) -> &[u8] {
+        &self[PageHeader::SIZE..]
+    }
+}
+
+impl Deref for MappedPage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map[self.offset..][..self.len]
+    }
+}
+
+#[cfg(test)]
+mod mmap_file_test {
+    use super::*;
+    use std::fs::{remove_file, write};
+
+    mod open {
+        use super::*;
+
+        #[test]
+        fn 作成したデータベースのスーパーブロックを読み込めること() {
+            // Arrange
+            let file_path = "mmap_file_test::open::0.txt";
+            let page_id = {
+                let mut disk = DiskManager::open(file_path).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                disk.set_catalog_page_id(Some(page_id)).unwrap();
+                page_id
+            };
+
+            // Act
+            let mmap_file = MmapFile::open(file_path).unwrap();
+
+            // Assert
+            assert_eq!(mmap_file.page_size(), DiskManager::DEFAULT_PAGE_SIZE);
+            assert_eq!(mmap_file.catalog_page_id(), Some(page_id));
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn データベースファイルでない場合InvalidMagicを返すこと() {
+            // Arrange
+            let file_path = "mmap_file_test::open::1.txt";
+            write(file_path, b"hello").unwrap();
+
+            // Act
+            let result = MmapFile::open(file_path);
+
+            // Assert
+            assert!(matches!(result, Err(Error::InvalidMagic)));
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod read_page {
+        use super::*;
+        use crate::disk::stamp_checksum;
+
+        #[test]
+        fn ページの内容をコピーせずに返すこと() {
+            // Arrange
+            let file_path = "mmap_file_test::read_page::0.txt";
+            let page_id = {
+                let mut disk = DiskManager::open(file_path).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                let mut data = [b'a'; DiskManager::DEFAULT_PAGE_SIZE];
+                stamp_checksum(page_id, &mut data);
+                disk.write_page_data(page_id, &data).unwrap();
+                page_id
+            };
+            let mmap_file = MmapFile::open(file_path).unwrap();
+
+            // Act
+            let page = mmap_file.read_page(page_id).unwrap();
+
+            // Assert
+            assert_eq!(page.len(), DiskManager::DEFAULT_PAGE_SIZE);
+            assert!(page.body().iter().all(|&byte| byte == b'a'));
+            assert_eq!(
+                page.as_ptr(),
+                mmap_file.map[DiskManager::DEFAULT_PAGE_SIZE..].as_ptr()
+            );
+
+            // Cleanup
+            drop(page);
+            drop(mmap_file);
+            remove_file(file_path).unwrap();
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn ページが壊れている場合ChecksumMismatchを返すこと() {
+            // Arrange
+            let file_path = "mmap_file_test::read_page::1.txt";
+            let page_id = {
+                let mut disk = DiskManager::open(file_path).unwrap();
+                let page_id = disk.allocate_page().unwrap();
+                disk.write_page_data(page_id, &[b'a'; DiskManager::DEFAULT_PAGE_SIZE])
+                    .unwrap();
+                page_id
+            };
+            let mmap_file = MmapFile::open(file_path).unwrap();
+
+            // Act
+            let result = mmap_file.read_page(page_id);
+
+            // Assert
+            assert!(
+                matches!(result, Err(Error::ChecksumMismatch { page_id: id }) if id == page_id)
+            );
+
+            // Cleanup
+            drop(mmap_file);
+            remove_file(file_path).unwrap();
+        }
+
+        #[test]
+        fn ファイルの範囲外のページを読み込もうとした場合エラーを返すこと() {
+            // Arrange
+            let file_path = "mmap_file_test::read_page::2.txt";
+            DiskManager::open(file_path).unwrap();
+            let mmap_file = MmapFile::open(file_path).unwrap();
+
+            // Act
+            let result = mmap_file.read_page(PageId::new(1));
+
+            // Assert
+            assert!(
+                matches!(result, Err(Error::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof)
+            );
+
+            // Cleanup
+            drop(mmap_file);
+            remove_file(file_path).unwrap();
+        }
+    }
+}
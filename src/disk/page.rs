@@ -1,12 +1,35 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    alloc::{self, Layout},
+    fmt::{self, Debug},
+    mem::size_of,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
 use zerocopy::{AsBytes, FromBytes, FromZeroes};
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, FromBytes, FromZeroes, AsBytes)]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    FromBytes,
+    FromZeroes,
+    AsBytes,
+    Serialize,
+    Deserialize,
+)]
 #[repr(C)]
 pub struct PageId(u64);
 impl PageId {
     pub const INVALID_PAGE_ID: PageId = PageId(u64::MAX);
 
-    pub fn new(value: u64) -> Self {
+    pub const fn new(value: u64) -> Self {
         Self(value)
     }
 
@@ -46,6 +69,122 @@ impl From<&[u8]> for PageId {
     }
 }
 
+/// Zero-initialized page image on the heap, aligned so that it can be handed
+/// to `O_DIRECT` I/O as is. The default page is empty and owns no memory.
+pub struct Page {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+unsafe impl Send for Page {}
+unsafe impl Sync for Page {}
+
+impl Page {
+    pub const ALIGN: usize = 4096;
+
+    pub fn new(len: usize) -> Self {
+        if len == 0 {
+            return Self::default();
+        }
+        let layout = Self::layout(len);
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, len }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len, Self::ALIGN).unwrap()
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            len: 0,
+        }
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+        }
+    }
+}
+
+impl Deref for Page {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for Page {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Clone for Page {
+    fn clone(&self) -> Self {
+        let mut page = Self::new(self.len);
+        page.copy_from_slice(self);
+        page
+    }
+}
+
+impl PartialEq for Page {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Debug for Page {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Page").field("len", &self.len).finish()
+    }
+}
+
+impl From<&[u8]> for Page {
+    fn from(bytes: &[u8]) -> Self {
+        let mut page = Self::new(bytes.len());
+        page.copy_from_slice(bytes);
+        page
+    }
+}
+
+#[derive(Debug, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct PageHeader {
+    pub checksum: u32,
+    /// Length of the compressed body when the page is stored compressed, or 0.
+    pub compressed_len: u32,
+    /// LSN of the last log record applied to the page, or 0.
+    pub lsn: u64,
+}
+
+impl PageHeader {
+    pub const SIZE: usize = size_of::<Self>();
+}
+
+pub fn compute_checksum(page_id: PageId, page: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(page_id.as_bytes());
+    crc32c::crc32c_append(crc, &page[size_of::<u32>()..])
+}
+
+pub fn stamp_checksum(page_id: PageId, page: &mut [u8]) {
+    let checksum = compute_checksum(page_id, page);
+    PageHeader::mut_from_prefix(page).unwrap().checksum = checksum;
+}
+
+pub fn verify_checksum(page_id: PageId, page: &[u8]) -> bool {
+    let header = PageHeader::ref_from_prefix(page).unwrap();
+    header.checksum == compute_checksum(page_id, page)
+}
+
 #[cfg(test)]
 mod page_id_test {
     use super::*;
@@ -96,3 +235,75 @@ mod page_id_test {
         }
     }
 }
+
+#[cfg(test)]
+mod page_test {
+    use super::*;
+
+    mod new {
+        use super::*;
+
+        #[test]
+        fn ゼロで初期化されアラインされたページを返すこと() {
+            // Act
+            let page = Page::new(8192);
+
+            // Assert
+            assert_eq!(page.len(), 8192);
+            assert_eq!(page.as_ptr() as usize % Page::ALIGN, 0);
+            assert!(page.iter().all(|&byte| byte == 0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod checksum_test {
+    use super::*;
+
+    mod verify_checksum {
+        use super::*;
+
+        #[test]
+        fn 書き込み時のチェックサムと一致する場合trueを返すこと() {
+            // Arrange
+            let page_id = PageId(1);
+            let mut page = [b'a'; 64];
+            stamp_checksum(page_id, &mut page);
+
+            // Act
+            let actual = verify_checksum(page_id, &page);
+
+            // Assert
+            assert!(actual);
+        }
+
+        #[test]
+        fn ページの内容が壊れている場合falseを返すこと() {
+            // Arrange
+            let page_id = PageId(1);
+            let mut page = [b'a'; 64];
+            stamp_checksum(page_id, &mut page);
+            page[63] = b'b';
+
+            // Act
+            let actual = verify_checksum(page_id, &page);
+
+            // Assert
+            assert!(!actual);
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 別のページIDで書き込まれたページの場合falseを返すこと() {
+            // Arrange
+            let mut page = [b'a'; 64];
+            stamp_checksum(PageId(1), &mut page);
+
+            // Act
+            let actual = verify_checksum(PageId(2), &page);
+
+            // Assert
+            assert!(!actual);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::{stamp_checksum, verify_checksum, Error, Page, PageHeader, PageId};
+use std::{collections::BTreeSet, mem::size_of};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+/// Byte range of the store. Extents start and end on sector boundaries, so
+/// that they can be read and written with `O_DIRECT`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl Extent {
+    pub const SECTOR_SIZE: usize = 512;
+
+    pub fn new(offset: u64, len: u64) -> Self {
+        Self { offset, len }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+/// Extent holding each page of a compressed database, which takes up only as
+/// many sectors as its compressed form needs. The map is kept in memory and
+/// stored in chunks of one page each, listed by a directory that the
+/// superblock points at. Chunks and directory are always written to fresh
+/// extents, so that the map on disk stays intact until the superblock points
+/// at the new one.
+#[derive(Debug)]
+pub struct PageMap {
+    page_size: usize,
+    extents: Vec<Extent>,
+    chunks: Vec<Extent>,
+    dirty_chunks: BTreeSet<usize>,
+    is_directory_dirty: bool,
+}
+
+impl PageMap {
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size,
+            extents: Vec::new(),
+            chunks: Vec::new(),
+            dirty_chunks: BTreeSet::new(),
+            is_directory_dirty: false,
+        }
+    }
+
+    /// Number of pages in the database, including the superblock.
+    pub fn len(&self) -> u64 {
+        self.extents.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extents.is_empty()
+    }
+
+    pub fn resize(&mut self, page_count: u64) {
+        self.extents.resize(page_count as usize, Extent::default());
+    }
+
+    /// The extent of the page, which is empty if the page was never written,
+    /// or `None` if the database has no such page.
+    pub fn get(&self, page_id: PageId) -> Option<Extent> {
+        self.extents.get(page_id.value() as usize).copied()
+    }
+
+    /// Points the page at `extent` and returns the extent it replaces.
+    pub fn set(&mut self, page_id: PageId, extent: Extent) -> Extent {
+        let index = page_id.value() as usize;
+        if index >= self.extents.len() {
+            self.resize(page_id.next().value());
+        }
+        self.dirty_chunks.insert(index / self.entries_per_chunk());
+        std::mem::replace(&mut self.extents[index], extent)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_chunks.is_empty() || self.is_directory_dirty
+    }
+
+    pub fn dirty_chunks(&self) -> Vec<usize> {
+        self.dirty_chunks.iter().copied().collect()
+    }
+
+    pub fn chunk_count(&self) -> u64 {
+        self.chunks.len() as u64
+    }
+
+    /// Extents taken by the pages and the chunks of the map.
+    pub fn used_extents(&self) -> impl Iterator<Item = Extent> + '_ {
+        self.extents
+            .iter()
+            .chain(&self.chunks)
+            .copied()
+            .filter(|extent| !extent.is_empty())
+    }
+
+    pub fn encode_chunk(&self, index: usize) -> Page {
+        let mut page = Page::new(self.page_size);
+        let entries = self
+            .extents
+            .iter()
+            .skip(index * self.entries_per_chunk())
+            .take(self.entries_per_chunk());
+        for (entry, extent) in page[PageHeader::SIZE..]
+            .chunks_exact_mut(size_of::<Extent>())
+            .zip(entries)
+        {
+            entry.copy_from_slice(extent.as_bytes());
+        }
+        stamp_checksum(Self::chunk_page_id(index), &mut page);
+        page
+    }
+
+    /// Records that chunk `index` has been written to `extent` and returns
+    /// the extent of its previous version.
+    pub fn set_chunk(&mut self, index: usize, extent: Extent) -> Extent {
+        if index >= self.chunks.len() {
+            self.chunks.resize(index + 1, Extent::default());
+        }
+        self.dirty_chunks.remove(&index);
+        self.is_directory_dirty = true;
+        std::mem::replace(&mut self.chunks[index], extent)
+    }
+
+    /// Restores chunk `index`, read from `extent`.
+    pub fn load_chunk(&mut self, index: usize, extent: Extent, chunk: &[u8]) -> Result<(), Error> {
+        if chunk.len() != self.page_size || !verify_checksum(Self::chunk_page_id(index), chunk) {
+            return Err(Error::CorruptedPageMap);
+        }
+        let first = index * self.entries_per_chunk();
+        let page_count = first + self.entries_per_chunk();
+        if self.extents.len() < page_count {
+            self.resize(page_count as u64);
+        }
+        for (i, entry) in chunk[PageHeader::SIZE..]
+            .chunks_exact(size_of::<Extent>())
+            .take(self.entries_per_chunk())
+            .enumerate()
+        {
+            self.extents[first + i] = Extent::read_from(entry).unwrap();
+        }
+        if index >= self.chunks.len() {
+            self.chunks.resize(index + 1, Extent::default());
+        }
+        self.chunks[index] = extent;
+        Ok(())
+    }
+
+    /// The directory listing the extent of every chunk, padded to whole
+    /// sectors, and its checksum.
+    pub fn encode_directory(&self) -> (Page, u32) {
+        let bytes = self.chunks.as_bytes();
+        let mut page = Page::new(bytes.len().max(1).next_multiple_of(Extent::SECTOR_SIZE));
+        page[..bytes.len()].copy_from_slice(bytes);
+        (page, crc32c::crc32c(bytes))
+    }
+
+    /// Reads back the chunk extents listed by a directory.
+    pub fn decode_directory(
+        directory: &[u8],
+        chunk_count: u64,
+        checksum: u32,
+    ) -> Result<Vec<Extent>, Error> {
+        let bytes = (chunk_count as usize)
+            .checked_mul(size_of::<Extent>())
+            .and_then(|len| directory.get(..len))
+            .filter(|bytes| crc32c::crc32c(bytes) == checksum)
+            .ok_or(Error::CorruptedPageMap)?;
+        Ok(bytes
+            .chunks_exact(size_of::<Extent>())
+            .map(|entry| Extent::read_from(entry).unwrap())
+            .collect())
+    }
+
+    /// Marks the map as written once the superblock can point at the
+    /// directory.
+    pub fn mark_directory_written(&mut self) {
+        self.is_directory_dirty = false;
+    }
+
+    fn entries_per_chunk(&self) -> usize {
+        (self.page_size - PageHeader::SIZE) / size_of::<Extent>()
+    }
+
+    /// Chunks are checksummed with their index in place of a page ID.
+    fn chunk_page_id(index: usize) -> PageId {
+        PageId::new(index as u64)
+    }
+}
+
+#[cfg(test)]
+mod page_map_test {
+    use super::*;
+    use crate::disk::DiskManager;
+
+    mod set {
+        use super::*;
+
+        #[test]
+        fn 置き換えた領域を返しそのチャンクを書き込み待ちにすること() {
+            // Arrange
+            let mut page_map = PageMap::new(DiskManager::DEFAULT_PAGE_SIZE);
+            page_map.resize(300);
+            page_map.set(PageId::new(260), Extent::new(4096, 512));
+
+            // Act
+            let replaced = page_map.set(PageId::new(260), Extent::new(8192, 1024));
+
+            // Assert
+            assert_eq!(replaced, Extent::new(4096, 512));
+            assert_eq!(
+                page_map.get(PageId::new(260)),
+                Some(Extent::new(8192, 1024))
+            );
+            assert_eq!(page_map.dirty_chunks(), vec![1]);
+            assert_eq!(page_map.get(PageId::new(300)), None);
+        }
+    }
+
+    mod load_chunk {
+        use super::*;
+
+        #[test]
+        fn 書き出したチャンクとディレクトリから同じ対応を復元すること() {
+            // Arrange
+            let mut page_map = PageMap::new(DiskManager::DEFAULT_PAGE_SIZE);
+            for i in 0..300 {
+                page_map.set(PageId::new(i), Extent::new(i * 512, 512));
+            }
+            for index in page_map.dirty_chunks() {
+                page_map.set_chunk(index, Extent::new((1 << 20) + index as u64 * 4096, 4096));
+            }
+            let (directory, checksum) = page_map.encode_directory();
+
+            // Act
+            let chunks =
+                PageMap::decode_directory(&directory, page_map.chunk_count(), checksum).unwrap();
+            let mut loaded = PageMap::new(DiskManager::DEFAULT_PAGE_SIZE);
+            for (index, extent) in chunks.into_iter().enumerate() {
+                loaded
+                    .load_chunk(index, extent, &page_map.encode_chunk(index))
+                    .unwrap();
+            }
+            loaded.resize(page_map.len());
+
+            // Assert
+            assert_eq!(loaded.extents, page_map.extents);
+            assert_eq!(loaded.chunks, page_map.chunks);
+            assert!(!loaded.is_dirty());
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn チャンクが壊れている場合CorruptedPageMapを返すこと() {
+            // Arrange
+            let mut page_map = PageMap::new(DiskManager::DEFAULT_PAGE_SIZE);
+            page_map.set(PageId::new(1), Extent::new(4096, 512));
+            let mut chunk = page_map.encode_chunk(0);
+            chunk[PageHeader::SIZE] ^= 1;
+
+            // Act
+            let result = PageMap::new(DiskManager::DEFAULT_PAGE_SIZE).load_chunk(
+                0,
+                Extent::new(8192, 4096),
+                &chunk,
+            );
+
+            // Assert
+            assert!(matches!(result, Err(Error::CorruptedPageMap)));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use super::PageId;
+use std::io;
+
+mod fault_injecting;
+mod file;
+mod memory;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub use fault_injecting::*;
+pub use file::*;
+pub use memory::*;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use uring::*;
+
+/// Fixed-size page storage addressed by [`PageId`]. A store starts out with
+/// [`DiskManager::DEFAULT_PAGE_SIZE`] pages until the `DiskManager` tells it
+/// the page size recorded in the superblock. Compressed databases do not keep
+/// pages in fixed slots and address the store by byte offset instead.
+///
+/// [`DiskManager::DEFAULT_PAGE_SIZE`]: super::DiskManager::DEFAULT_PAGE_SIZE
+pub trait PageStore: Send + Sync {
+    fn set_page_size(&mut self, page_size: usize);
+    fn page_count(&self) -> u64;
+    fn read_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()>;
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()>;
+    fn allocate(&mut self) -> io::Result<PageId>;
+    fn sync(&mut self) -> io::Result<()>;
+
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> io::Result<()>;
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+    fn read_pages(&self, pages: &mut [(PageId, &mut [u8])]) -> io::Result<()> {
+        for (page_id, data) in pages.iter_mut() {
+            self.read_page(*page_id, data)?;
+        }
+        Ok(())
+    }
+
+    fn write_pages(&mut self, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        for &(page_id, data) in pages {
+            self.write_page(page_id, data)?;
+        }
+        Ok(())
+    }
+}
+
+fn calc_offset(page_id: PageId, page_size: usize) -> u64 {
+    page_id.value() * page_size as u64
+}
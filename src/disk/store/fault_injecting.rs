@@ -0,0 +1,201 @@
+use super::PageStore;
+use crate::disk::PageId;
+use parking_lot::Mutex;
+use std::{io, sync::Arc};
+
+#[derive(Default)]
+struct FaultState {
+    writes_until_crash: Option<usize>,
+    torn_write_len: Option<usize>,
+    fail_reads: bool,
+    fail_syncs: bool,
+}
+
+impl FaultState {
+    fn is_crashed(&self) -> bool {
+        self.writes_until_crash == Some(0)
+    }
+}
+
+/// Wraps another store and fails or tears its I/O on demand. Once a write has
+/// failed, the store behaves as if the machine crashed and rejects every write
+/// until the faults are cleared through its [`FaultInjector`].
+pub struct FaultInjectingStore<S> {
+    inner: S,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl<S: PageStore> FaultInjectingStore<S> {
+    pub fn new(inner: S) -> (Self, FaultInjector) {
+        let state = Arc::new(Mutex::new(FaultState::default()));
+        let injector = FaultInjector {
+            state: state.clone(),
+        };
+        (Self { inner, state }, injector)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn check_read(&self) -> io::Result<()> {
+        if self.state.lock().fail_reads {
+            return Err(injected_fault());
+        }
+        Ok(())
+    }
+
+    /// Writes `data` through `write` unless a crash or a torn write is due.
+    fn write_with(
+        &mut self,
+        data: &[u8],
+        write: impl FnOnce(&mut S, &[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut state = self.state.lock();
+        if state.is_crashed() {
+            return Err(injected_fault());
+        }
+        if let Some(len) = state.torn_write_len.take() {
+            state.writes_until_crash = Some(0);
+            write(&mut self.inner, &data[..len.min(data.len())])?;
+            return Err(injected_fault());
+        }
+        if let Some(count) = state.writes_until_crash.as_mut() {
+            *count -= 1;
+        }
+        write(&mut self.inner, data)
+    }
+}
+
+fn injected_fault() -> io::Error {
+    io::Error::other("injected fault")
+}
+
+impl<S: PageStore> PageStore for FaultInjectingStore<S> {
+    fn set_page_size(&mut self, page_size: usize) {
+        self.inner.set_page_size(page_size)
+    }
+
+    fn page_count(&self) -> u64 {
+        self.inner.page_count()
+    }
+
+    fn read_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        self.check_read()?;
+        self.inner.read_page(page_id, data)
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.write_with(data, |inner, data| inner.write_page(page_id, data))
+    }
+
+    fn allocate(&mut self) -> io::Result<PageId> {
+        if self.state.lock().is_crashed() {
+            return Err(injected_fault());
+        }
+        self.inner.allocate()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let state = self.state.lock();
+        if state.fail_syncs || state.is_crashed() {
+            return Err(injected_fault());
+        }
+        self.inner.sync()
+    }
+
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        self.check_read()?;
+        self.inner.read_at(offset, data)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.write_with(data, |inner, data| inner.write_at(offset, data))
+    }
+}
+
+#[derive(Clone)]
+pub struct FaultInjector {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl FaultInjector {
+    pub fn crash_after_writes(&self, count: usize) {
+        self.state.lock().writes_until_crash = Some(count);
+    }
+
+    pub fn tear_next_write(&self, len: usize) {
+        self.state.lock().torn_write_len = Some(len);
+    }
+
+    pub fn fail_reads(&self, fail: bool) {
+        self.state.lock().fail_reads = fail;
+    }
+
+    pub fn fail_syncs(&self, fail: bool) {
+        self.state.lock().fail_syncs = fail;
+    }
+
+    pub fn clear(&self) {
+        *self.state.lock() = FaultState::default();
+    }
+}
+
+#[cfg(test)]
+mod fault_injecting_store_test {
+    use super::*;
+    use crate::disk::MemoryStore;
+
+    mod write_page {
+        use super::*;
+
+        #[test]
+        fn 指定した回数の書き込みの後は失敗し続けること() {
+            // Arrange
+            let (mut store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            injector.crash_after_writes(1);
+
+            // Act
+            let first = store.write_page(PageId::new(0), b"first");
+            let second = store.write_page(PageId::new(1), b"second");
+            let third = store.write_page(PageId::new(2), b"third");
+
+            // Assert
+            assert!(first.is_ok());
+            assert!(second.is_err());
+            assert!(third.is_err());
+            assert_eq!(store.page_count(), 1);
+        }
+
+        #[test]
+        fn 書き込みが途中で千切れた場合先頭だけが書き込まれること() {
+            // Arrange
+            let (mut store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            store.write_page(PageId::new(0), b"aaaaaaaa").unwrap();
+            injector.tear_next_write(4);
+
+            // Act
+            let result = store.write_page(PageId::new(0), b"bbbbbbbb");
+
+            // Assert
+            assert!(result.is_err());
+            let mut data = [0u8; 8];
+            store.read_page(PageId::new(0), &mut data).unwrap();
+            assert_eq!(&data, b"bbbbaaaa");
+        }
+
+        #[test]
+        fn clearした後は書き込めること() {
+            // Arrange
+            let (mut store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            injector.crash_after_writes(0);
+
+            // Act
+            injector.clear();
+            let result = store.write_page(PageId::new(0), b"data");
+
+            // Assert
+            assert!(result.is_ok());
+        }
+    }
+}
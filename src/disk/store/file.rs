@@ -0,0 +1,270 @@
+use super::{calc_offset, PageStore};
+use crate::disk::{DiskManager, PageId};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+};
+
+pub struct FileStore {
+    pub(super) heap_file: File,
+    pub(super) heap_file_size: u64,
+    pub(super) page_size: usize,
+}
+
+impl FileStore {
+    pub fn new(heap_file: File) -> io::Result<Self> {
+        let heap_file_size = heap_file.metadata()?.len();
+        Ok(Self {
+            heap_file,
+            heap_file_size,
+            page_size: DiskManager::DEFAULT_PAGE_SIZE,
+        })
+    }
+
+    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file = open_options().open(heap_file_path)?;
+        Self::new(heap_file)
+    }
+
+    /// Opens the heap file with `O_DIRECT` so that pages bypass the OS page
+    /// cache. Every buffer passed to this store must then be a [`Page`].
+    ///
+    /// [`Page`]: crate::disk::Page
+    #[cfg(target_os = "linux")]
+    pub fn open_direct(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file = open_options()
+            .custom_flags(libc::O_DIRECT)
+            .open(heap_file_path)?;
+        Self::new(heap_file)
+    }
+}
+
+pub(super) fn open_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    options
+}
+
+impl PageStore for FileStore {
+    fn set_page_size(&mut self, page_size: usize) {
+        self.page_size = page_size;
+    }
+
+    fn page_count(&self) -> u64 {
+        self.heap_file_size.div_ceil(self.page_size as u64)
+    }
+
+    fn read_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        self.read_at(calc_offset(page_id, self.page_size), data)
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.write_at(calc_offset(page_id, self.page_size), data)
+    }
+
+    fn allocate(&mut self) -> io::Result<PageId> {
+        let page_id = PageId::new(self.page_count());
+        let heap_file_size = calc_offset(page_id.next(), self.page_size);
+        self.heap_file.set_len(heap_file_size)?;
+        self.heap_file_size = heap_file_size;
+        Ok(page_id)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.heap_file.sync_all()
+    }
+
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        self.heap_file.read_exact_at(data, offset)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.heap_file.write_all_at(data, offset)?;
+        self.heap_file_size = self.heap_file_size.max(offset + data.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod file_store_test {
+    use super::*;
+    use std::{
+        fs::remove_file,
+        io::{Read, Seek, SeekFrom, Write},
+    };
+
+    mod new {
+        use super::*;
+
+        #[test]
+        fn ファイルサイズからページ数を求めること() {
+            // Arrange
+            let file_path = "file_store_test::new::0.txt";
+            let mut file = File::create(file_path).unwrap();
+            file.write_all(&[0u8; DiskManager::DEFAULT_PAGE_SIZE + 1])
+                .unwrap();
+
+            // Act
+            let store = FileStore::new(file).unwrap();
+
+            // Assert
+            assert_eq!(store.page_count(), 2);
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod open_direct {
+        use super::*;
+        use crate::disk::Page;
+
+        #[test]
+        fn アラインされたページを読み書きできること() {
+            // Arrange
+            let file_path = "file_store_test::open_direct::0.txt";
+            let mut store = FileStore::open_direct(file_path).unwrap();
+            let page_id = store.allocate().unwrap();
+            let data = Page::from(&[b'a'; DiskManager::DEFAULT_PAGE_SIZE][..]);
+
+            // Act
+            store.write_page(page_id, &data).unwrap();
+            let mut page = Page::new(DiskManager::DEFAULT_PAGE_SIZE);
+            store.read_page(page_id, &mut page).unwrap();
+
+            // Assert
+            assert_eq!(page, data);
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod set_page_size {
+        use super::*;
+
+        #[test]
+        fn 指定したページサイズでページを読み書きすること() {
+            // Arrange
+            let file_path = "file_store_test::set_page_size::0.txt";
+            let mut store = FileStore::open(file_path).unwrap();
+
+            // Act
+            store.set_page_size(8192);
+            store.write_page(PageId::new(1), b"Hello, world!").unwrap();
+
+            // Assert
+            assert_eq!(store.page_count(), 2);
+            assert_eq!(store.heap_file.metadata().unwrap().len(), 8192 + 13);
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod allocate {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn ファイルを1ページ分伸ばしそのページIDを返すこと() {
+            // Arrange
+            let file_path = "file_store_test::allocate::0.txt";
+            let mut store = FileStore::open(file_path).unwrap();
+
+            // Act
+            let page_id = store.allocate().unwrap();
+
+            // Assert
+            assert_eq!(page_id, PageId::new(0));
+            assert_eq!(store.page_count(), 1);
+            assert_eq!(
+                store.heap_file.metadata().unwrap().len(),
+                DiskManager::DEFAULT_PAGE_SIZE as u64
+            );
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod write_page {
+        use super::*;
+
+        #[test]
+        fn データをファイルに書き込めること() {
+            // Arrange
+            let file_path = "file_store_test::write_page::0.txt";
+            let mut store = FileStore::open(file_path).unwrap();
+
+            // Act
+            store.write_page(PageId::new(1), b"Hello, world!").unwrap();
+            store
+                .heap_file
+                .seek(SeekFrom::Start(DiskManager::DEFAULT_PAGE_SIZE as u64))
+                .unwrap();
+            let mut content = String::new();
+            store.heap_file.read_to_string(&mut content).unwrap();
+
+            // Assert
+            assert_eq!(content, "Hello, world!");
+            assert_eq!(store.page_count(), 2);
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod read_page {
+        use super::*;
+
+        #[test]
+        fn ファイルに書き込まれたデータを読み込めること() {
+            // Arrange
+            let file_path = "file_store_test::read_page::0.txt";
+            let mut store = FileStore::open(file_path).unwrap();
+            store
+                .heap_file
+                .seek(SeekFrom::Start(DiskManager::DEFAULT_PAGE_SIZE as u64))
+                .unwrap();
+            store.heap_file.write_all(b"Hello, world!").unwrap();
+
+            // Act
+            let mut data = vec![0u8; 13];
+            store.read_page(PageId::new(1), &mut data).unwrap();
+
+            // Assert
+            assert_eq!(data, b"Hello, world!");
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+
+    mod write_at {
+        use super::*;
+
+        #[test]
+        fn ページの境界をまたいで書き込めること() {
+            // Arrange
+            let file_path = "file_store_test::write_at::0.txt";
+            let mut store = FileStore::open(file_path).unwrap();
+            let offset = DiskManager::DEFAULT_PAGE_SIZE as u64 - 5;
+
+            // Act
+            store.write_at(offset, b"Hello, world!").unwrap();
+
+            // Assert
+            let mut data = vec![0u8; 13];
+            store.read_at(offset, &mut data).unwrap();
+            assert_eq!(data, b"Hello, world!");
+            assert_eq!(store.page_count(), 2);
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::{calc_offset, PageStore};
+use crate::disk::{DiskManager, PageId};
+use parking_lot::Mutex;
+use std::{io, sync::Arc};
+
+/// Keeps pages in memory. Clones share the same pages, so a test can reopen
+/// a database from a clone after dropping the `DiskManager` that wrote it.
+#[derive(Clone)]
+pub struct MemoryStore {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    page_size: usize,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            bytes: Default::default(),
+            page_size: DiskManager::DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl PageStore for MemoryStore {
+    fn set_page_size(&mut self, page_size: usize) {
+        self.page_size = page_size;
+    }
+
+    fn page_count(&self) -> u64 {
+        (self.bytes.lock().len() as u64).div_ceil(self.page_size as u64)
+    }
+
+    fn read_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        self.read_at(calc_offset(page_id, self.page_size), data)
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.write_at(calc_offset(page_id, self.page_size), data)
+    }
+
+    fn allocate(&mut self) -> io::Result<PageId> {
+        let page_id = PageId::new(self.page_count());
+        self.bytes
+            .lock()
+            .resize(calc_offset(page_id.next(), self.page_size) as usize, 0);
+        Ok(page_id)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        let bytes = self.bytes.lock();
+        let offset = offset as usize;
+        let src = bytes
+            .get(offset..offset + data.len())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        data.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut bytes = self.bytes.lock();
+        let offset = offset as usize;
+        if bytes.len() < offset + data.len() {
+            bytes.resize(offset + data.len(), 0);
+        }
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod memory_store_test {
+    use super::*;
+
+    mod read_page {
+        use super::*;
+
+        #[test]
+        fn 書き込んだデータを読み込めること() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            store.write_page(PageId::new(1), b"Hello, world!").unwrap();
+
+            // Act
+            let mut data = vec![0u8; 13];
+            store.read_page(PageId::new(1), &mut data).unwrap();
+
+            // Assert
+            assert_eq!(data, b"Hello, world!");
+            assert_eq!(store.page_count(), 2);
+        }
+
+        #[test]
+        fn 存在しないページを読み込もうとした場合エラーを返すこと() {
+            // Arrange
+            let store = MemoryStore::default();
+
+            // Act
+            let mut data = vec![0u8; DiskManager::DEFAULT_PAGE_SIZE];
+            let result = store.read_page(PageId::new(0), &mut data);
+
+            // Assert
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    mod clone {
+        use super::*;
+
+        #[test]
+        fn クローンとページを共有すること() {
+            // Arrange
+            let mut store = MemoryStore::default();
+            let clone = store.clone();
+
+            // Act
+            let page_id = store.allocate().unwrap();
+            store.write_page(page_id, b"Hello, world!").unwrap();
+
+            // Assert
+            let mut data = vec![0u8; 13];
+            clone.read_page(page_id, &mut data).unwrap();
+            assert_eq!(data, b"Hello, world!");
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use super::{calc_offset, FileStore, PageStore};
+use crate::disk::PageId;
+use io_uring::{opcode, squeue, types, IoUring};
+use parking_lot::Mutex;
+use std::{
+    fs::File,
+    io,
+    os::unix::{fs::FileExt, io::AsRawFd},
+    path::Path,
+};
+
+/// File-backed store that submits batched page reads and writes through a
+/// Linux io_uring instance. Single-page I/O still goes through `pread`/`pwrite`.
+pub struct UringStore {
+    file: FileStore,
+    ring: Mutex<IoUring>,
+}
+
+impl UringStore {
+    pub const QUEUE_DEPTH: u32 = 64;
+
+    pub fn new(heap_file: File) -> io::Result<Self> {
+        Ok(Self {
+            file: FileStore::new(heap_file)?,
+            ring: Mutex::new(IoUring::new(Self::QUEUE_DEPTH)?),
+        })
+    }
+
+    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: FileStore::open(heap_file_path)?,
+            ring: Mutex::new(IoUring::new(Self::QUEUE_DEPTH)?),
+        })
+    }
+
+    pub fn open_direct(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: FileStore::open_direct(heap_file_path)?,
+            ring: Mutex::new(IoUring::new(Self::QUEUE_DEPTH)?),
+        })
+    }
+
+    fn fd(&self) -> types::Fd {
+        types::Fd(self.file.heap_file.as_raw_fd())
+    }
+
+    /// Submits `entries` in chunks of the queue depth and returns the result
+    /// of each one, indexed by its `user_data`. The caller must keep every
+    /// buffer referenced by `entries` alive until this returns, which it
+    /// only does, even with an error, once the kernel is done with all of
+    /// them.
+    fn submit(&self, entries: &[squeue::Entry]) -> io::Result<Vec<i32>> {
+        let mut ring = self.ring.lock();
+        let mut results = vec![0; entries.len()];
+        for chunk in entries.chunks(Self::QUEUE_DEPTH as usize) {
+            // SAFETY: the entries point into buffers the caller keeps alive
+            // until this returns, and `complete` does not return while the
+            // kernel may still use any of them.
+            unsafe { ring.submission().push_multiple(chunk) }
+                .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+            if let Err(err) = Self::complete(&mut ring, chunk.len(), &mut results) {
+                // The entries the kernel never took are still queued, and a
+                // later call would submit them once their buffers are gone.
+                *ring = IoUring::new(Self::QUEUE_DEPTH)?;
+                return Err(err);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Submits the `count` entries just pushed and waits for them. If
+    /// submitting fails, it still waits for those the kernel has taken
+    /// before returning the error, leaving the others queued.
+    fn complete(ring: &mut IoUring, count: usize, results: &mut [i32]) -> io::Result<()> {
+        let mut completed = 0;
+        let mut error = None;
+        while completed < count {
+            let in_flight = count - completed - ring.submission().len();
+            let result = match error {
+                None => ring.submit_and_wait(count - completed),
+                Some(_) if in_flight > 0 => {
+                    // SAFETY: submits nothing and only waits for completions.
+                    unsafe {
+                        ring.submitter().enter::<libc::sigset_t>(
+                            0,
+                            in_flight as u32,
+                            IORING_ENTER_GETEVENTS,
+                            None,
+                        )
+                    }
+                }
+                Some(_) => break,
+            };
+            match result {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+            for cqe in ring.completion() {
+                results[cqe.user_data() as usize] = cqe.result();
+                completed += 1;
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+/// Flag of `io_uring_enter` to wait for completions.
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+fn check_result(result: i32) -> io::Result<usize> {
+    if result < 0 {
+        Err(io::Error::from_raw_os_error(-result))
+    } else {
+        Ok(result as usize)
+    }
+}
+
+impl PageStore for UringStore {
+    fn set_page_size(&mut self, page_size: usize) {
+        self.file.set_page_size(page_size)
+    }
+
+    fn page_count(&self) -> u64 {
+        self.file.page_count()
+    }
+
+    fn read_page(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        self.file.read_page(page_id, data)
+    }
+
+    fn write_page(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.file.write_page(page_id, data)
+    }
+
+    fn allocate(&mut self) -> io::Result<PageId> {
+        self.file.allocate()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync()
+    }
+
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        self.file.read_at(offset, data)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.file.write_at(offset, data)
+    }
+
+    fn read_pages(&self, pages: &mut [(PageId, &mut [u8])]) -> io::Result<()> {
+        let entries: Vec<_> = pages
+            .iter_mut()
+            .enumerate()
+            .map(|(i, (page_id, data))| {
+                opcode::Read::new(self.fd(), data.as_mut_ptr(), data.len() as u32)
+                    .offset(calc_offset(*page_id, self.file.page_size))
+                    .build()
+                    .user_data(i as u64)
+            })
+            .collect();
+        let results = self.submit(&entries)?;
+        for ((page_id, data), result) in pages.iter_mut().zip(results) {
+            let read = check_result(result)?;
+            if read < data.len() {
+                let offset = calc_offset(*page_id, self.file.page_size) + read as u64;
+                self.file
+                    .heap_file
+                    .read_exact_at(&mut data[read..], offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_pages(&mut self, pages: &[(PageId, &[u8])]) -> io::Result<()> {
+        let entries: Vec<_> = pages
+            .iter()
+            .enumerate()
+            .map(|(i, (page_id, data))| {
+                opcode::Write::new(self.fd(), data.as_ptr(), data.len() as u32)
+                    .offset(calc_offset(*page_id, self.file.page_size))
+                    .build()
+                    .user_data(i as u64)
+            })
+            .collect();
+        let results = self.submit(&entries)?;
+        for (&(page_id, data), result) in pages.iter().zip(results) {
+            let offset = calc_offset(page_id, self.file.page_size);
+            let written = check_result(result)?;
+            if written < data.len() {
+                let rest_offset = offset + written as u64;
+                self.file
+                    .heap_file
+                    .write_all_at(&data[written..], rest_offset)?;
+            }
+            self.file.heap_file_size = self.file.heap_file_size.max(offset + data.len() as u64);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod uring_store_test {
+    use super::*;
+    use crate::disk::DiskManager;
+    use std::fs::remove_file;
+
+    mod write_pages {
+        use super::*;
+
+        #[test]
+        fn まとめて書き込んだページをまとめて読み込めること() {
+            // Arrange
+            let file_path = "uring_store_test::write_pages::0.txt";
+            let mut store = UringStore::open(file_path).unwrap();
+            let pages: Vec<_> = (0..100u8)
+                .map(|i| {
+                    (
+                        PageId::new(i as u64),
+                        vec![i; DiskManager::DEFAULT_PAGE_SIZE],
+                    )
+                })
+                .collect();
+
+            // Act
+            let batch: Vec<_> = pages
+                .iter()
+                .map(|(page_id, data)| (*page_id, data.as_slice()))
+                .collect();
+            store.write_pages(&batch).unwrap();
+
+            // Assert
+            let mut buffers = vec![vec![0u8; DiskManager::DEFAULT_PAGE_SIZE]; pages.len()];
+            let mut batch: Vec<_> = pages
+                .iter()
+                .zip(buffers.iter_mut())
+                .map(|((page_id, _), data)| (*page_id, data.as_mut_slice()))
+                .collect();
+            store.read_pages(&mut batch).unwrap();
+            assert_eq!(store.page_count(), 100);
+            for ((_, expected), actual) in pages.iter().zip(buffers.iter()) {
+                assert_eq!(actual, expected);
+            }
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+}
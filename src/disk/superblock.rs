@@ -0,0 +1,175 @@
+use super::{Cipher, Compression, DiskManager, EncryptionKey, Error, Extent, KeyCheck, PageId};
+use std::mem::size_of;
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+pub const MAGIC: [u8; 8] = *b"LIGHTSQL";
+pub const FORMAT_VERSION: u32 = 9;
+
+#[derive(Debug, PartialEq, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct Superblock {
+    pub magic: [u8; 8],
+    pub version: u32,
+    pub page_size: u32,
+    pub next_page_id: PageId,
+    pub free_page_id: PageId,
+    pub catalog_page_id: PageId,
+    pub compression: u32,
+    pub is_encrypted: u32,
+    pub key_check: KeyCheck,
+    pub page_map_checksum: u32,
+    /// Directory of the page map of a compressed database, listing
+    /// `page_map_chunk_count` chunks.
+    pub page_map: Extent,
+    pub page_map_chunk_count: u64,
+    /// Transaction ids from this one on have never been handed out.
+    pub next_xid: u64,
+}
+
+impl Superblock {
+    pub fn new(next_page_id: PageId, page_size: usize, compression: Compression) -> Self {
+        Self {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            page_size: page_size as u32,
+            next_page_id,
+            free_page_id: PageId::INVALID_PAGE_ID,
+            catalog_page_id: PageId::INVALID_PAGE_ID,
+            compression: compression.into(),
+            is_encrypted: 0,
+            key_check: [0; size_of::<KeyCheck>()],
+            page_map_checksum: 0,
+            page_map: Extent::default(),
+            page_map_chunk_count: 0,
+            next_xid: 1,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.magic != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        if self.version != FORMAT_VERSION {
+            return Err(Error::IncompatibleVersion {
+                found: self.version,
+                expected: FORMAT_VERSION,
+            });
+        }
+        DiskManager::check_page_size(self.page_size as usize)?;
+        self.compression()?.check()
+    }
+
+    pub fn compression(&self) -> Result<Compression, Error> {
+        Compression::try_from(self.compression)
+    }
+
+    /// Sets up the cipher of an encrypted database, checking that `key` is
+    /// the one it was created with.
+    pub fn cipher(&self, key: Option<&EncryptionKey>) -> Result<Option<Cipher>, Error> {
+        match (self.is_encrypted != 0, key) {
+            (false, None) => Ok(None),
+            (false, Some(_)) => Err(Error::NotEncrypted),
+            (true, None) => Err(Error::EncryptionKeyRequired),
+            (true, Some(key)) => {
+                let cipher = Cipher::new(key);
+                if cipher.verify_key_check(&self.key_check) {
+                    Ok(Some(cipher))
+                } else {
+                    Err(Error::WrongEncryptionKey)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod superblock_test {
+    use super::*;
+
+    mod validate {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 正しいスーパーブロックの場合Okを返すこと() {
+            assert!(Superblock::new(
+                PageId::new(1),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None
+            )
+            .validate()
+            .is_ok())
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn マジックナンバーが異なる場合InvalidMagicを返すこと() {
+            // Arrange
+            let mut superblock = Superblock::new(
+                PageId::new(1),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None,
+            );
+            superblock.magic = *b"NOTLSQL!";
+
+            // Act
+            let result = superblock.validate();
+
+            // Assert
+            assert!(matches!(result, Err(Error::InvalidMagic)));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 未対応のページサイズの場合UnsupportedPageSizeを返すこと() {
+            // Arrange
+            let superblock = Superblock::new(PageId::new(1), 1000, Compression::None);
+
+            // Act
+            let result = superblock.validate();
+
+            // Assert
+            assert!(matches!(result, Err(Error::UnsupportedPageSize(1000))));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn バージョンが異なる場合IncompatibleVersionを返すこと() {
+            // Arrange
+            let mut superblock = Superblock::new(
+                PageId::new(1),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None,
+            );
+            superblock.version = FORMAT_VERSION + 1;
+
+            // Act
+            let result = superblock.validate();
+
+            // Assert
+            assert!(matches!(
+                result,
+                Err(Error::IncompatibleVersion { found, expected })
+                    if found == FORMAT_VERSION + 1 && expected == FORMAT_VERSION
+            ));
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 未知の圧縮方式の場合UnsupportedCompressionを返すこと() {
+            // Arrange
+            let mut superblock = Superblock::new(
+                PageId::new(1),
+                DiskManager::DEFAULT_PAGE_SIZE,
+                Compression::None,
+            );
+            superblock.compression = 99;
+
+            // Act
+            let result = superblock.validate();
+
+            // Assert
+            assert!(matches!(result, Err(Error::UnsupportedCompression(99))));
+        }
+    }
+}
@@ -6,4 +6,7 @@ pub mod memcmpable;
 pub mod query;
 pub mod slotted;
 pub mod table;
+pub mod transaction;
 pub mod tuple;
+pub mod wal;
+pub mod worker;
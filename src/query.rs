@@ -1,7 +1,8 @@
 use crate::{
     btree::{self, BTree, SearchMode},
-    buffer::BufferPoolManager,
+    buffer::{BufferPoolManager, BufferRing},
     disk::PageId,
+    transaction::Snapshot,
     tuple,
 };
 use anyhow::Result;
@@ -28,13 +29,19 @@ impl<'a> TupleSearchMode<'a> {
 }
 
 pub trait Executor {
-    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>>;
+    fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Tuple>>;
 }
 
 pub type BoxExecutor<'a> = Box<dyn Executor + 'a>;
 
+/// Plan whose executor returns the rows `snapshot` sees, however long it
+/// runs and whatever is inserted meanwhile.
 pub trait PlanNode {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor>;
+    fn start<'a>(
+        &'a self,
+        bufmgr: &BufferPoolManager,
+        snapshot: &'a Snapshot,
+    ) -> Result<BoxExecutor<'a>>;
 }
 
 pub struct SeqScan<'a> {
@@ -44,11 +51,18 @@ pub struct SeqScan<'a> {
 }
 
 impl<'a> PlanNode for SeqScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start<'b>(
+        &'b self,
+        bufmgr: &BufferPoolManager,
+        snapshot: &'b Snapshot,
+    ) -> Result<BoxExecutor<'b>> {
         let btree = BTree::new(self.table_meta_page_id);
-        let table_iter = btree.search(bufmgr, self.search_mode.encode())?;
+        let ring = BufferRing::default();
+        let table_iter = btree.search(&bufmgr.with_ring(&ring), self.search_mode.encode())?;
         Ok(Box::new(ExecSeqScan {
             table_iter,
+            ring,
+            snapshot,
             while_cond: self.while_cond,
         }))
     }
@@ -56,12 +70,17 @@ impl<'a> PlanNode for SeqScan<'a> {
 
 pub struct ExecSeqScan<'a> {
     table_iter: btree::Iter,
+    /// Frames the scan reads the table through, so that it does not evict
+    /// the pages other plans are using.
+    ring: BufferRing,
+    snapshot: &'a Snapshot,
     while_cond: &'a dyn Fn(TupleSlice) -> bool,
 }
 
 impl<'a> Executor for ExecSeqScan<'a> {
-    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (pkey_bytes, tuple_bytes) = match self.table_iter.next(bufmgr)? {
+    fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Tuple>> {
+        let source = bufmgr.with_ring(&self.ring);
+        let (pkey_bytes, tuple_bytes) = match self.table_iter.next(&source, self.snapshot)? {
             Some(pair) => pair,
             None => return Ok(None),
         };
@@ -82,8 +101,12 @@ pub struct Filter<'a> {
 }
 
 impl<'a> PlanNode for Filter<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
-        let inner_iter = self.inner_plan.start(bufmgr)?;
+    fn start<'b>(
+        &'b self,
+        bufmgr: &BufferPoolManager,
+        snapshot: &'b Snapshot,
+    ) -> Result<BoxExecutor<'b>> {
+        let inner_iter = self.inner_plan.start(bufmgr, snapshot)?;
         Ok(Box::new(ExecFilter {
             inner_iter,
             cond: self.cond,
@@ -97,7 +120,7 @@ pub struct ExecFilter<'a> {
 }
 
 impl<'a> Executor for ExecFilter<'a> {
-    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+    fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Tuple>> {
         loop {
             match self.inner_iter.next(bufmgr)? {
                 Some(tuple) => {
@@ -119,13 +142,18 @@ pub struct IndexScan<'a> {
 }
 
 impl<'a> PlanNode for IndexScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start<'b>(
+        &'b self,
+        bufmgr: &BufferPoolManager,
+        snapshot: &'b Snapshot,
+    ) -> Result<BoxExecutor<'b>> {
         let table_btree = BTree::new(self.table_meta_page_id);
         let index_btree = BTree::new(self.index_meta_page_id);
         let index_iter = index_btree.search(bufmgr, self.search_mode.encode())?;
         Ok(Box::new(ExecIndexScan {
             table_btree,
             index_iter,
+            snapshot,
             while_cond: self.while_cond,
         }))
     }
@@ -134,28 +162,36 @@ impl<'a> PlanNode for IndexScan<'a> {
 pub struct ExecIndexScan<'a> {
     table_btree: BTree,
     index_iter: btree::Iter,
+    snapshot: &'a Snapshot,
     while_cond: &'a dyn Fn(TupleSlice) -> bool,
 }
 
 impl<'a> Executor for ExecIndexScan<'a> {
-    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr)? {
-            Some(pair) => pair,
-            None => return Ok(None),
-        };
-        let mut skey = vec![];
-        tuple::decode(&skey_bytes, &mut skey);
-        if !(self.while_cond)(&skey) {
-            return Ok(None);
+    fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr, self.snapshot)? {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            let mut skey = vec![];
+            tuple::decode(&skey_bytes, &mut skey);
+            if !(self.while_cond)(&skey) {
+                return Ok(None);
+            }
+            let mut table_iter = self
+                .table_btree
+                .search(bufmgr, SearchMode::Key(pkey_bytes.clone()))?;
+            // The row may be gone from the snapshot even though its index
+            // entry is not, so entries pointing to no row are skipped.
+            let tuple_bytes = match table_iter.next(bufmgr, self.snapshot)? {
+                Some((key, tuple_bytes)) if key == pkey_bytes => tuple_bytes,
+                _ => continue,
+            };
+            let mut tuple = vec![];
+            tuple::decode(&pkey_bytes, &mut tuple);
+            tuple::decode(&tuple_bytes, &mut tuple);
+            return Ok(Some(tuple));
         }
-        let mut table_iter = self
-            .table_btree
-            .search(bufmgr, SearchMode::Key(pkey_bytes))?;
-        let (pkey_bytes, tuple_bytes) = table_iter.next(bufmgr)?.unwrap();
-        let mut tuple = vec![];
-        tuple::decode(&pkey_bytes, &mut tuple);
-        tuple::decode(&tuple_bytes, &mut tuple);
-        Ok(Some(tuple))
     }
 }
 
@@ -166,11 +202,16 @@ pub struct IndexOnlyScan<'a> {
 }
 
 impl<'a> PlanNode for IndexOnlyScan<'a> {
-    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start<'b>(
+        &'b self,
+        bufmgr: &BufferPoolManager,
+        snapshot: &'b Snapshot,
+    ) -> Result<BoxExecutor<'b>> {
         let btree = BTree::new(self.index_meta_page_id);
         let index_iter = btree.search(bufmgr, self.search_mode.encode())?;
         Ok(Box::new(ExecIndexOnlyScan {
             index_iter,
+            snapshot,
             while_cond: self.while_cond,
         }))
     }
@@ -178,12 +219,13 @@ impl<'a> PlanNode for IndexOnlyScan<'a> {
 
 pub struct ExecIndexOnlyScan<'a> {
     index_iter: btree::Iter,
+    snapshot: &'a Snapshot,
     while_cond: &'a dyn Fn(TupleSlice) -> bool,
 }
 
 impl<'a> Executor for ExecIndexOnlyScan<'a> {
-    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr)? {
+    fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Tuple>> {
+        let (skey_bytes, pkey_bytes) = match self.index_iter.next(bufmgr, self.snapshot)? {
             Some(pair) => pair,
             None => return Ok(None),
         };
@@ -1,6 +1,10 @@
 use crate::buffer::BufferPoolManager;
 use crate::tuple;
-use crate::{btree::BTree, disk::PageId};
+use crate::{
+    btree::{BTree, WritablePageSource},
+    disk::PageId,
+    transaction::Transaction,
+};
 use anyhow::Result;
 
 #[derive(Debug)]
@@ -10,19 +14,26 @@ pub struct SimpleTable {
 }
 
 impl SimpleTable {
-    pub fn create(&mut self, bufmgr: &mut BufferPoolManager) -> Result<()> {
-        let btree = BTree::create(bufmgr)?;
+    pub fn create(&mut self, bufmgr: &BufferPoolManager, txn: &mut Transaction) -> Result<()> {
+        let btree = BTree::create(bufmgr, txn)?;
         self.meta_page_id = btree.meta_page_id;
         Ok(())
     }
 
-    pub fn insert(&self, bufmgr: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
+    /// Bulk loads can go through `BufferPoolManager::with_ring` so that they
+    /// do not evict the rest of the pool.
+    pub fn insert<S: WritablePageSource>(
+        &self,
+        bufmgr: &S,
+        txn: &mut Transaction,
+        record: &[&[u8]],
+    ) -> Result<()> {
         let btree = BTree::new(self.meta_page_id);
         let mut key = vec![];
         tuple::encode(record[..self.key_elems_count].iter(), &mut key);
         let mut value = vec![];
         tuple::encode(record[self.key_elems_count..].iter(), &mut value);
-        btree.insert(bufmgr, &key, &value)?;
+        btree.insert(bufmgr, txn, &key, &value)?;
         Ok(())
     }
 }
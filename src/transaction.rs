@@ -0,0 +1,10 @@
+mod error;
+mod lock;
+#[allow(clippy::module_inception)]
+mod transaction;
+mod version;
+
+pub use error::*;
+pub use lock::*;
+pub use transaction::*;
+pub use version::*;
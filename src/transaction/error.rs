@@ -0,0 +1,12 @@
+use crate::{buffer, disk::PageId};
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("timed out waiting for the lock on the B-tree at {0:?}.")]
+    LockTimeout(PageId),
+}
@@ -0,0 +1,52 @@
+use super::Error;
+use crate::disk::PageId;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Exclusive locks on B-trees, each named by its meta page and held by one
+/// transaction at a time until it ends.
+#[derive(Debug, Default)]
+pub struct LockTable {
+    owners: Mutex<HashMap<PageId, u64>>,
+    released: Condvar,
+}
+
+impl LockTable {
+    /// How long `lock` waits for another transaction to end before giving
+    /// up, which is how deadlocks between transactions are broken.
+    pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Locks the tree at `meta_page_id` for `owner`, and returns whether it
+    /// did not hold the lock already.
+    pub fn lock(&self, meta_page_id: PageId, owner: u64) -> Result<bool, Error> {
+        let deadline = Instant::now() + Self::TIMEOUT;
+        let mut owners = self.owners.lock();
+        loop {
+            match owners.get(&meta_page_id) {
+                None => {
+                    owners.insert(meta_page_id, owner);
+                    return Ok(true);
+                }
+                Some(&holder) if holder == owner => return Ok(false),
+                Some(_) => {
+                    if self.released.wait_until(&mut owners, deadline).timed_out()
+                        && owners.contains_key(&meta_page_id)
+                    {
+                        return Err(Error::LockTimeout(meta_page_id));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn unlock(&self, meta_page_ids: &[PageId]) {
+        let mut owners = self.owners.lock();
+        for meta_page_id in meta_page_ids {
+            owners.remove(meta_page_id);
+        }
+        self.released.notify_all();
+    }
+}
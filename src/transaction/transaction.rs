@@ -0,0 +1,566 @@
+use super::Error;
+use crate::{
+    btree::log::{self, Change},
+    buffer::{BufferPoolManager, WritePageGuard},
+    disk::PageId,
+    wal::{LogRecord, Lsn},
+};
+
+/// Changes to B-trees that either all stay, once `commit` has made them
+/// durable, or are all undone, by `rollback` or by recovery after a crash.
+/// A transaction holds the lock on every tree it writes to until it ends, so
+/// no other transaction changes the pages it may have to undo. The rows it
+/// inserts and deletes are stamped with its id, so snapshots taken before it
+/// commits see none of its changes. One dropped before it ends is rolled
+/// back.
+pub struct Transaction<'a> {
+    bufmgr: &'a BufferPoolManager,
+    /// Id it holds locks and stamps rows with.
+    xid: u64,
+    /// LSN of its `Begin` record, or 0 without a log.
+    id: Lsn,
+    last_lsn: Lsn,
+    /// Changes made so far, latest last, to undo them on rollback.
+    updates: Vec<(Lsn, PageId, Change)>,
+    locked_trees: Vec<PageId>,
+    is_ended: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn begin(bufmgr: &'a BufferPoolManager) -> Result<Self, Error> {
+        let xid = bufmgr
+            .versions()
+            .begin(|next_xid| bufmgr.reserve_xids(next_xid))?;
+        let id = bufmgr
+            .log()
+            .map_or(Lsn::ZERO, |log| log.append(&LogRecord::Begin));
+        Ok(Self {
+            bufmgr,
+            xid,
+            id,
+            last_lsn: id,
+            updates: vec![],
+            locked_trees: vec![],
+            is_ended: false,
+        })
+    }
+
+    pub(crate) fn xid(&self) -> u64 {
+        self.xid
+    }
+
+    /// `VersionTable::horizon` of the manager it writes through.
+    pub(crate) fn horizon(&self) -> u64 {
+        self.bufmgr.versions().horizon()
+    }
+
+    /// Locks the tree at `meta_page_id` until the transaction ends, waiting
+    /// for the transaction holding it, if any, for up to
+    /// `LockTable::TIMEOUT`.
+    pub fn lock(&mut self, meta_page_id: PageId) -> Result<(), Error> {
+        if self.bufmgr.locks().lock(meta_page_id, self.xid)? {
+            self.locked_trees.push(meta_page_id);
+        }
+        Ok(())
+    }
+
+    /// Records a change just made to the page of `guard`. The page has to
+    /// stay latched until then, so that it is never written without its
+    /// change being in the log first.
+    pub(crate) fn log(&mut self, guard: &mut WritePageGuard, change: Change) {
+        let lsn = match self.bufmgr.log() {
+            Some(log) => {
+                let lsn = log.append(&LogRecord::Update {
+                    op: self.id,
+                    prev_lsn: self.last_lsn,
+                    page_id: guard.page_id(),
+                    change: change.to_bytes(),
+                });
+                guard.set_lsn(lsn);
+                self.last_lsn = lsn;
+                lsn
+            }
+            None => Lsn::ZERO,
+        };
+        self.updates.push((lsn, guard.page_id(), change));
+    }
+
+    /// Point `roll_back_to` can undo the changes made after.
+    pub(crate) fn savepoint(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Undoes the changes made since `savepoint`, latest first, leaving the
+    /// transaction running. None of their pages may be latched by the
+    /// caller.
+    pub(crate) fn roll_back_to(&mut self, savepoint: usize) -> Result<(), Error> {
+        while self.updates.len() > savepoint {
+            let (_, page_id, change) = self.updates.pop().unwrap();
+            let mut guard = self.bufmgr.fetch_page_write(page_id)?;
+            match self.bufmgr.log() {
+                Some(log) => {
+                    let undo_next_lsn = self.updates.last().map_or(self.id, |&(lsn, ..)| lsn);
+                    self.last_lsn = log::undo(log, self.id, &mut guard, &change, undo_next_lsn);
+                }
+                None => change.undo(&mut guard),
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the transaction, keeping its changes. They survive a crash once
+    /// this returns. The locks are released before the log is synced, so
+    /// that the next transaction on the same trees can commit in the same
+    /// sync. If the sync fails, the changes may or may not survive a crash,
+    /// and no snapshot sees them until recovery has settled which.
+    pub fn commit(mut self) -> Result<(), Error> {
+        let Some(log) = self.bufmgr.log() else {
+            self.end();
+            return Ok(());
+        };
+        let lsn = log.append(&LogRecord::End { op: self.id });
+        // The changes can no longer be undone, so whoever takes the locks
+        // next may build on them: its records follow this end in the log,
+        // and none of them can be durable unless this end is too.
+        self.unlock();
+        log.commit(lsn)?;
+        self.bufmgr.versions().end(self.xid);
+        Ok(())
+    }
+
+    /// Ends the transaction, undoing its changes. It ends even if some of
+    /// them could not be undone, which recovery then does.
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.roll_back()
+    }
+
+    fn roll_back(&mut self) -> Result<(), Error> {
+        let result = self.roll_back_to(0);
+        if result.is_ok() {
+            if let Some(log) = self.bufmgr.log() {
+                log.append(&LogRecord::End { op: self.id });
+            }
+            self.end();
+        } else {
+            // Without its end in the log, recovery undoes what is left, and
+            // until then no snapshot sees it.
+            self.unlock();
+        }
+        result
+    }
+
+    fn end(&mut self) {
+        self.bufmgr.versions().end(self.xid);
+        self.unlock();
+    }
+
+    fn unlock(&mut self) {
+        self.bufmgr.locks().unlock(&self.locked_trees);
+        self.is_ended = true;
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.is_ended {
+            // Whatever could not be undone here is undone by recovery.
+            let _ = self.roll_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod transaction_test {
+    use super::*;
+    use crate::{
+        btree::{BTree, SearchMode},
+        buffer::ClockSweepBufferPool,
+        disk::{DiskManager, FaultInjectingStore, MemoryStore},
+        transaction::Snapshot,
+        wal::{self, LogManager, LogRecord, LogStore, MemoryLogStore},
+    };
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Barrier,
+        },
+        thread,
+        time::Duration,
+    };
+
+    /// Log kept in memory whose syncs fail while `fail_syncs` is set.
+    #[derive(Clone, Default)]
+    struct FlakyLogStore {
+        inner: MemoryLogStore,
+        fail_syncs: Arc<AtomicBool>,
+    }
+
+    impl LogStore for FlakyLogStore {
+        fn size(&self) -> u64 {
+            self.inner.size()
+        }
+
+        fn read(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+            self.inner.read(offset, data)
+        }
+
+        fn append(&mut self, data: &[u8]) -> io::Result<()> {
+            self.inner.append(data)
+        }
+
+        fn truncate(&mut self, size: u64) -> io::Result<()> {
+            self.inner.truncate(size)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            if self.fail_syncs.load(Ordering::Relaxed) {
+                return Err(io::Error::other("injected fault"));
+            }
+            self.inner.sync()
+        }
+
+        fn replace(&mut self, data: &[u8]) -> io::Result<()> {
+            self.inner.replace(data)
+        }
+    }
+
+    fn with_log(store: &MemoryStore, log_store: &MemoryLogStore) -> BufferPoolManager {
+        let disk = DiskManager::new(store.clone()).unwrap();
+        let log = LogManager::new(log_store.clone()).unwrap();
+        BufferPoolManager::new(disk, ClockSweepBufferPool::from(16)).with_log(log)
+    }
+
+    fn create(bufmgr: &BufferPoolManager) -> BTree {
+        let mut txn = Transaction::begin(bufmgr).unwrap();
+        let btree = BTree::create(bufmgr, &mut txn).unwrap();
+        txn.commit().unwrap();
+        btree
+    }
+
+    fn insert(btree: &BTree, bufmgr: &BufferPoolManager, txn: &mut Transaction, key: u32) {
+        btree
+            .insert(bufmgr, txn, &key.to_be_bytes(), &[b'a'; 100])
+            .unwrap();
+    }
+
+    fn keys(btree: &BTree, bufmgr: &BufferPoolManager) -> Vec<u32> {
+        let snapshot = Snapshot::take(bufmgr);
+        let mut keys = vec![];
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        while let Some((key, _)) = iter.next(bufmgr, &snapshot).unwrap() {
+            keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    mod commit {
+        use super::*;
+
+        #[test]
+        fn 確定した挿入はページを書き込む前に落ちても残ること() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = with_log(&store, &log_store);
+                let btree = create(&bufmgr);
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..500 {
+                    insert(&btree, &bufmgr, &mut txn, i);
+                }
+
+                // Act
+                txn.commit().unwrap();
+                btree
+            };
+
+            // Assert
+            let bufmgr = with_log(&store, &log_store);
+            wal::recover(&bufmgr).unwrap();
+            assert_eq!(keys(&btree, &bufmgr), (0u32..500).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn ログを同期できなかった場合もロックを解放すること() {
+            // Arrange
+            let log_store = FlakyLogStore::default();
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let log = LogManager::new(log_store.clone()).unwrap();
+                BufferPoolManager::new(disk, ClockSweepBufferPool::from(16)).with_log(log)
+            };
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0);
+            log_store.fail_syncs.store(true, Ordering::Relaxed);
+
+            // Act
+            let result = txn.commit();
+
+            // Assert
+            assert!(result.is_err());
+            log_store.fail_syncs.store(false, Ordering::Relaxed);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 1);
+            txn.commit().unwrap();
+            // The row whose commit failed stays hidden until recovery.
+            assert_eq!(keys(&btree, &bufmgr), vec![1]);
+        }
+
+        #[test]
+        fn 同じ木に書き込む並行した確定を少ない同期にまとめること() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let bufmgr = with_log(&store, &log_store);
+            let btree = create(&bufmgr);
+            let log = bufmgr.log().unwrap();
+            log.set_commit_delay(Duration::from_millis(5));
+            let before = log.stats();
+            let barrier = Barrier::new(8);
+
+            // Act
+            thread::scope(|scope| {
+                for i in 0u32..8 {
+                    let (btree, bufmgr, barrier) = (&btree, &bufmgr, &barrier);
+                    scope.spawn(move || {
+                        for j in 0u32..10 {
+                            let mut txn = Transaction::begin(bufmgr).unwrap();
+                            barrier.wait();
+                            insert(btree, bufmgr, &mut txn, i * 10 + j);
+                            txn.commit().unwrap();
+                        }
+                    });
+                }
+            });
+
+            // Assert
+            let stats = log.stats();
+            assert_eq!(stats.commits - before.commits, 80);
+            assert!(stats.syncs - before.syncs < 80);
+            assert_eq!(keys(&btree, &bufmgr), (0u32..80).collect::<Vec<_>>());
+        }
+    }
+
+    mod rollback {
+        use super::*;
+
+        #[test]
+        fn 分割を含むすべての挿入を取り消すこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let bufmgr = with_log(&store, &log_store);
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0);
+            txn.commit().unwrap();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 1u32..500 {
+                insert(&btree, &bufmgr, &mut txn, i);
+            }
+
+            // Act
+            txn.rollback().unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), vec![0]);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 1u32..500 {
+                insert(&btree, &bufmgr, &mut txn, i);
+            }
+            txn.commit().unwrap();
+            assert_eq!(keys(&btree, &bufmgr), (0u32..500).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 削除を取り消すと落ちた後も行が残ること() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = with_log(&store, &log_store);
+                let btree = create(&bufmgr);
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..10 {
+                    insert(&btree, &bufmgr, &mut txn, i);
+                }
+                txn.commit().unwrap();
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..10 {
+                    btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+                }
+
+                // Act
+                txn.rollback().unwrap();
+                bufmgr.log().unwrap().flush_all().unwrap();
+                btree
+            };
+
+            // Assert
+            let bufmgr = with_log(&store, &log_store);
+            wal::recover(&bufmgr).unwrap();
+            assert_eq!(keys(&btree, &bufmgr), (0u32..10).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 挿入し直しを取り消すと落ちた後も削除された行のままであること() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = with_log(&store, &log_store);
+                let btree = create(&bufmgr);
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..10 {
+                    insert(&btree, &bufmgr, &mut txn, i);
+                }
+                txn.commit().unwrap();
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..5 {
+                    btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+                }
+                txn.commit().unwrap();
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 0u32..5 {
+                    insert(&btree, &bufmgr, &mut txn, i);
+                }
+
+                // Act
+                txn.rollback().unwrap();
+                assert_eq!(keys(&btree, &bufmgr), (5u32..10).collect::<Vec<_>>());
+                bufmgr.log().unwrap().flush_all().unwrap();
+                btree
+            };
+
+            // Assert
+            let bufmgr = with_log(&store, &log_store);
+            wal::recover(&bufmgr).unwrap();
+            assert_eq!(keys(&btree, &bufmgr), (5u32..10).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 取り消しはページの写しでなく取り消した変更をログに書くこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let bufmgr = with_log(&store, &log_store);
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..10 {
+                insert(&btree, &bufmgr, &mut txn, i);
+            }
+
+            // Act
+            txn.rollback().unwrap();
+
+            // Assert
+            let log = bufmgr.log().unwrap();
+            log.flush_all().unwrap();
+            let changes: Vec<_> = log
+                .records()
+                .unwrap()
+                .into_iter()
+                .filter_map(|(_, record)| match record {
+                    LogRecord::Compensation { change, .. } => Some(change),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(changes.len(), 10);
+            let total: usize = changes.iter().map(Vec::len).sum();
+            assert!(total < DiskManager::DEFAULT_PAGE_SIZE);
+        }
+
+        #[test]
+        fn ログがなくても取り消せること() {
+            // Arrange
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                BufferPoolManager::new(disk, ClockSweepBufferPool::from(16))
+            };
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..500 {
+                insert(&btree, &bufmgr, &mut txn, i);
+            }
+
+            // Act
+            txn.rollback().unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), vec![]);
+        }
+
+        #[test]
+        fn 取り消しに失敗してもロックを解放すること() {
+            // Arrange
+            let (store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            let bufmgr = {
+                let disk = DiskManager::new(store).unwrap();
+                let log = LogManager::new(MemoryLogStore::default()).unwrap();
+                BufferPoolManager::new(disk, ClockSweepBufferPool::from(8)).with_log(log)
+            };
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..500 {
+                insert(&btree, &bufmgr, &mut txn, i);
+            }
+            injector.fail_reads(true);
+
+            // Act
+            let result = txn.rollback();
+
+            // Assert
+            assert!(result.is_err());
+            injector.clear();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            assert!(txn.lock(btree.meta_page_id).is_ok());
+        }
+    }
+
+    mod drop {
+        use super::*;
+
+        #[test]
+        fn 終わらずに破棄すると取り消されること() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let bufmgr = with_log(&store, &log_store);
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0);
+
+            // Act
+            drop(txn);
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), vec![]);
+        }
+    }
+
+    mod lock {
+        use super::*;
+
+        #[test]
+        fn 同じ木に書き込むトランザクションは先のものが終わるまで待つこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let bufmgr = with_log(&store, &log_store);
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0);
+            let is_committed = AtomicBool::new(false);
+
+            // Act
+            thread::scope(|scope| {
+                scope.spawn(|| {
+                    let mut txn = Transaction::begin(&bufmgr).unwrap();
+                    insert(&btree, &bufmgr, &mut txn, 1);
+                    assert!(is_committed.load(Ordering::Acquire));
+                    txn.commit().unwrap();
+                });
+                thread::sleep(Duration::from_millis(50));
+                is_committed.store(true, Ordering::Release);
+                txn.commit().unwrap();
+            });
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), vec![0, 1]);
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use crate::{buffer::BufferPoolManager, disk::MmapFile};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+/// Ids of the transactions that created and deleted a row, stored in front
+/// of it. Rows are never changed in place otherwise, so together with a
+/// snapshot these tell whether it sees the row.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    FromZeroes,
+    FromBytes,
+    AsBytes,
+    Serialize,
+    Deserialize,
+)]
+#[repr(C)]
+pub struct Stamp {
+    pub xmin: u64,
+    /// 0 while the row has not been deleted.
+    pub xmax: u64,
+}
+
+impl Stamp {
+    pub const SIZE: usize = std::mem::size_of::<Self>();
+
+    pub fn new(xmin: u64) -> Self {
+        Self { xmin, xmax: 0 }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.xmax != 0
+    }
+
+    /// Whether no snapshot, whether taken already or later, sees the row,
+    /// given the `VersionTable::horizon` of the moment.
+    pub fn is_dead(&self, horizon: u64) -> bool {
+        self.is_deleted() && self.xmax < horizon
+    }
+}
+
+/// Transactions writing through a buffer pool manager. A transaction that
+/// is not running has either committed or undone all its changes, so this
+/// is all a snapshot needs to tell whether it sees a row: nothing is kept
+/// per row.
+#[derive(Debug)]
+pub struct VersionTable {
+    state: RwLock<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    next_xid: u64,
+    /// Ids below this one may be in rows on disk, as far as the database
+    /// knows after a crash.
+    reserved_xid: u64,
+    /// Transactions that have not ended, and those that ended with changes
+    /// that were neither made durable nor undone. No snapshot sees the rows
+    /// of the latter until recovery settles them when the database is
+    /// opened again.
+    running: HashSet<u64>,
+    /// Oldest transaction each live snapshot does not see as committed,
+    /// with how many snapshots share it.
+    snapshots: BTreeMap<u64, usize>,
+}
+
+impl VersionTable {
+    /// How many transaction ids are reserved on disk at a time.
+    pub const RESERVED_XIDS: u64 = 1 << 16;
+
+    /// Table handing out ids from `next_xid` on, which must be above any id
+    /// found in the rows of the database.
+    pub fn new(next_xid: u64) -> Self {
+        Self {
+            state: RwLock::new(State {
+                next_xid,
+                reserved_xid: next_xid,
+                running: HashSet::new(),
+                snapshots: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Id for a new transaction, which it holds locks and stamps rows
+    /// with. Ids only grow, so every snapshot taken before a transaction
+    /// begins has a smaller `xmax` than its id. Once the reserved ids run
+    /// out, `reserve` is asked to record durably that ids up to the one it
+    /// is given may be in use.
+    pub fn begin<E>(&self, reserve: impl FnOnce(u64) -> Result<(), E>) -> Result<u64, E> {
+        let mut state = self.state.write();
+        if state.next_xid == state.reserved_xid {
+            let reserved_xid = state.next_xid + Self::RESERVED_XIDS;
+            reserve(reserved_xid)?;
+            state.reserved_xid = reserved_xid;
+        }
+        let xid = state.next_xid;
+        state.next_xid += 1;
+        state.running.insert(xid);
+        Ok(xid)
+    }
+
+    /// Ends the transaction `xid`, which has either committed or undone
+    /// every change it made.
+    pub fn end(&self, xid: u64) {
+        self.state.write().running.remove(&xid);
+    }
+
+    /// Transactions below this id have ended, and every live snapshot sees
+    /// them as committed, as will every snapshot taken from now on. The
+    /// rows they deleted can go.
+    pub fn horizon(&self) -> u64 {
+        let state = self.state.read();
+        let oldest_running = state.running.iter().min().copied();
+        let oldest_snapshot = state.snapshots.keys().next().copied();
+        [oldest_running, oldest_snapshot]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(state.next_xid)
+    }
+
+    /// State for a new snapshot, which holds back the horizon at the
+    /// oldest transaction it does not see as committed until it is
+    /// released.
+    fn take(&self) -> (u64, HashSet<u64>, u64) {
+        let mut state = self.state.write();
+        let xmax = state.next_xid;
+        let horizon = state
+            .running
+            .iter()
+            .min()
+            .map_or(xmax, |&xid| xid.min(xmax));
+        *state.snapshots.entry(horizon).or_default() += 1;
+        (xmax, state.running.clone(), horizon)
+    }
+
+    fn release(&self, horizon: u64) {
+        let mut state = self.state.write();
+        let count = state.snapshots.get_mut(&horizon).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            state.snapshots.remove(&horizon);
+        }
+    }
+}
+
+/// The rows of the transactions that had committed when it was taken, as
+/// they were then. Reading through one never waits for the transactions
+/// writing to the trees read.
+pub struct Snapshot {
+    /// Transactions with this id or higher began after it was taken.
+    xmax: u64,
+    /// Transactions that were running when it was taken.
+    running: HashSet<u64>,
+    /// Table it holds the horizon of back, and where.
+    versions: Option<(Arc<VersionTable>, u64)>,
+}
+
+impl Snapshot {
+    pub fn take(bufmgr: &BufferPoolManager) -> Self {
+        let versions = bufmgr.versions();
+        let (xmax, running, horizon) = versions.take();
+        Self {
+            xmax,
+            running,
+            versions: Some((versions.clone(), horizon)),
+        }
+    }
+
+    /// Snapshot of a file nothing writes to any more, which sees every row
+    /// committed in it. Rows of transactions that were running when the
+    /// database crashed are seen too, unless it has been recovered since.
+    pub fn of_mmap_file(mmap_file: &MmapFile) -> Self {
+        Self {
+            xmax: mmap_file.next_xid(),
+            running: HashSet::new(),
+            versions: None,
+        }
+    }
+
+    /// Whether the row stamped with `stamp` is one to see.
+    pub fn sees(&self, stamp: &Stamp) -> bool {
+        self.has_committed(stamp.xmin) && !(stamp.is_deleted() && self.has_committed(stamp.xmax))
+    }
+
+    fn has_committed(&self, xid: u64) -> bool {
+        xid < self.xmax && !self.running.contains(&xid)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Some((versions, horizon)) = &self.versions {
+            versions.release(*horizon);
+        }
+    }
+}
+
+#[cfg(test)]
+mod version_table_test {
+    use super::*;
+
+    mod begin {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 予約したIDを使い切ると次の分を予約すること() {
+            // Arrange
+            let versions = VersionTable::new(1);
+            let mut reserved = vec![];
+
+            // Act
+            for _ in 0..=VersionTable::RESERVED_XIDS {
+                versions
+                    .begin(|next_xid| {
+                        reserved.push(next_xid);
+                        Ok::<_, ()>(())
+                    })
+                    .unwrap();
+            }
+
+            // Assert
+            assert_eq!(
+                reserved,
+                vec![
+                    1 + VersionTable::RESERVED_XIDS,
+                    1 + 2 * VersionTable::RESERVED_XIDS
+                ]
+            );
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 予約に失敗した場合IDを配らないこと() {
+            // Arrange
+            let versions = VersionTable::new(1);
+
+            // Act
+            let result = versions.begin(|_| Err(()));
+
+            // Assert
+            assert_eq!(result, Err(()));
+            assert_eq!(versions.begin(|_| Ok::<_, ()>(())), Ok(1));
+        }
+    }
+
+    mod horizon {
+        use super::*;
+
+        #[test]
+        fn 解放していないスナップショットが見ていない確定までしか進まないこと() {
+            // Arrange
+            let versions = VersionTable::new(1);
+            let begin = || versions.begin(|_| Ok::<_, ()>(())).unwrap();
+            let (first, second) = (begin(), begin());
+            let (xmax, _, horizon) = versions.take();
+
+            // Act
+            versions.end(first);
+            versions.end(second);
+            let held = versions.horizon();
+            versions.release(horizon);
+            let released = versions.horizon();
+
+            // Assert
+            assert_eq!(held, first);
+            assert_eq!(released, xmax);
+        }
+    }
+}
+
+#[cfg(test)]
+mod snapshot_test {
+    use super::*;
+    use crate::{
+        btree::{BTree, SearchMode},
+        buffer::ClockSweepBufferPool,
+        disk::{DiskManager, MemoryStore},
+        transaction::Transaction,
+    };
+    use std::ops::Range;
+
+    fn bufmgr() -> BufferPoolManager {
+        let disk = DiskManager::new(MemoryStore::default()).unwrap();
+        BufferPoolManager::new(disk, ClockSweepBufferPool::from(64))
+    }
+
+    fn create(bufmgr: &BufferPoolManager) -> BTree {
+        let mut txn = Transaction::begin(bufmgr).unwrap();
+        let btree = BTree::create(bufmgr, &mut txn).unwrap();
+        txn.commit().unwrap();
+        btree
+    }
+
+    fn insert(btree: &BTree, bufmgr: &BufferPoolManager, txn: &mut Transaction, keys: Range<u32>) {
+        for i in keys {
+            btree
+                .insert(bufmgr, txn, &i.to_be_bytes(), &[b'a'; 100])
+                .unwrap();
+        }
+    }
+
+    fn keys(btree: &BTree, bufmgr: &BufferPoolManager, snapshot: &Snapshot) -> Vec<u32> {
+        let mut keys = vec![];
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        while let Some((key, _)) = iter.next(bufmgr, snapshot).unwrap() {
+            keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    mod sees {
+        use super::*;
+
+        #[test]
+        fn 撮った後に始まったトランザクションの行は確定しても見えないこと() {
+            // Arrange
+            let bufmgr = bufmgr();
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0..10);
+            txn.commit().unwrap();
+            let snapshot = Snapshot::take(&bufmgr);
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 10..500);
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (0u32..10).collect::<Vec<_>>()
+            );
+            let snapshot = Snapshot::take(&bufmgr);
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (0u32..500).collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn 撮った時に実行中だったトランザクションの行は確定しても見えないこと() {
+            // Arrange
+            let bufmgr = bufmgr();
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0..10);
+            let snapshot = Snapshot::take(&bufmgr);
+
+            // Act
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr, &snapshot), vec![]);
+        }
+
+        #[test]
+        fn 走査中に挿入が続いても撮った時の行だけを返すこと() {
+            // Arrange
+            let bufmgr = bufmgr();
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in (0u32..1000).step_by(2) {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            let snapshot = Snapshot::take(&bufmgr);
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            let mut keys = vec![];
+            for _ in 0..100 {
+                let (key, _) = iter.next(&bufmgr, &snapshot).unwrap().unwrap();
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in (1u32..1000).step_by(2) {
+                btree
+                    .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                    .unwrap();
+            }
+            txn.commit().unwrap();
+            while let Some((key, _)) = iter.next(&bufmgr, &snapshot).unwrap() {
+                keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+            }
+
+            // Assert
+            assert_eq!(keys, (0u32..1000).step_by(2).collect::<Vec<_>>());
+        }
+    }
+
+    mod sees_deleted {
+        use super::*;
+
+        #[test]
+        fn 撮った後に削除された行は見え続けること() {
+            // Arrange
+            let bufmgr = bufmgr();
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0..10);
+            txn.commit().unwrap();
+            let snapshot = Snapshot::take(&bufmgr);
+
+            // Act
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            for i in 0u32..5 {
+                btree.delete(&bufmgr, &mut txn, &i.to_be_bytes()).unwrap();
+            }
+            let running = Snapshot::take(&bufmgr);
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (0u32..10).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                keys(&btree, &bufmgr, &running),
+                (0u32..10).collect::<Vec<_>>()
+            );
+            let snapshot = Snapshot::take(&bufmgr);
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (5u32..10).collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn 開き直しても前に確定した行が見えること() {
+            // Arrange
+            let store = MemoryStore::default();
+            let btree = {
+                let disk = DiskManager::new(store.clone()).unwrap();
+                let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(64));
+                let btree = create(&bufmgr);
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                insert(&btree, &bufmgr, &mut txn, 0..10);
+                txn.commit().unwrap();
+                bufmgr.flush_all().unwrap();
+                btree
+            };
+
+            // Act
+            let disk = DiskManager::new(store).unwrap();
+            let bufmgr = BufferPoolManager::new(disk, ClockSweepBufferPool::from(64));
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 10..20);
+            let snapshot = Snapshot::take(&bufmgr);
+            txn.commit().unwrap();
+
+            // Assert
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (0u32..10).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    mod take {
+        use super::*;
+
+        #[test]
+        fn 終わったトランザクションを覚えておかないこと() {
+            // Arrange
+            let bufmgr = bufmgr();
+            let btree = create(&bufmgr);
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 0..10);
+            txn.commit().unwrap();
+            let mut txn = Transaction::begin(&bufmgr).unwrap();
+            insert(&btree, &bufmgr, &mut txn, 10..20);
+            txn.rollback().unwrap();
+
+            // Act
+            let snapshot = Snapshot::take(&bufmgr);
+
+            // Assert
+            assert!(snapshot.running.is_empty());
+            assert_eq!(
+                keys(&btree, &bufmgr, &snapshot),
+                (0u32..10).collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -0,0 +1,15 @@
+mod checkpoint;
+mod error;
+mod manager;
+mod record;
+mod recovery;
+mod stats;
+mod store;
+
+pub use checkpoint::*;
+pub use error::*;
+pub use manager::*;
+pub use record::*;
+pub use recovery::*;
+pub use stats::LogStats;
+pub use store::*;
@@ -0,0 +1,165 @@
+use super::Error;
+use crate::{buffer::BufferPoolManager, worker::PeriodicWorker};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Takes a checkpoint of `bufmgr`. The dirty pages are written back while
+/// other threads go on changing them, then the pages still dirty and the
+/// operations still running are logged, and the records recovery no longer
+/// needs are dropped from the log. Does nothing if the manager has no log.
+pub fn checkpoint(bufmgr: &BufferPoolManager) -> Result<(), Error> {
+    let Some(log) = bufmgr.log() else {
+        return Ok(());
+    };
+    bufmgr.flush_all()?;
+    log.checkpoint(|| {
+        let dirty_pages = bufmgr.dirty_pages();
+        // Pages evicted since they were last synced are not listed, so they
+        // have to be on disk before the log that redoes them is dropped.
+        bufmgr.sync()?;
+        Ok::<_, Error>(dirty_pages)
+    })?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckpointerOptions {
+    /// How long the checkpointer sleeps between two checkpoints.
+    pub interval: Duration,
+}
+
+impl Default for CheckpointerOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Thread that periodically takes a checkpoint, which keeps both the log
+/// and the time recovery takes bounded. It runs until it is stopped or
+/// dropped.
+pub struct Checkpointer {
+    worker: PeriodicWorker,
+    checkpoints: Arc<AtomicU64>,
+}
+
+impl Checkpointer {
+    pub fn start(bufmgr: Arc<BufferPoolManager>, options: CheckpointerOptions) -> Self {
+        let checkpoints = Arc::new(AtomicU64::new(0));
+        let worker = PeriodicWorker::start(options.interval, {
+            let checkpoints = checkpoints.clone();
+            move || {
+                // A failed checkpoint leaves the log as it was, to be cut
+                // short by the next one.
+                if checkpoint(&bufmgr).is_ok() {
+                    checkpoints.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        Self {
+            worker,
+            checkpoints,
+        }
+    }
+
+    /// Number of checkpoints taken since the checkpointer was started.
+    pub fn checkpoints(&self) -> u64 {
+        self.checkpoints.load(Ordering::Relaxed)
+    }
+
+    /// Stops the checkpointer and waits for the checkpoint in progress to
+    /// finish.
+    pub fn stop(self) {
+        self.worker.stop();
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_test {
+    use super::*;
+    use crate::{
+        buffer::ClockSweepBufferPool,
+        disk::{DiskManager, FaultInjectingStore, MemoryStore},
+        wal::{LogManager, LogRecord, MemoryLogStore},
+    };
+
+    mod checkpoint {
+        use super::*;
+
+        #[test]
+        fn ダーティなページがなくてもデータファイルを同期できなければログを切り詰めないこと() {
+            // Arrange
+            let (store, injector) = FaultInjectingStore::new(MemoryStore::default());
+            let bufmgr = {
+                let disk = DiskManager::new(store).unwrap();
+                let log = LogManager::new(MemoryLogStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                BufferPoolManager::new(disk, pool).with_log(log)
+            };
+            bufmgr.create_page().unwrap().fill(b'a');
+            bufmgr.flush_all().unwrap();
+            let log = bufmgr.log().unwrap();
+            log.append(&LogRecord::CheckpointBegin);
+            let records = log.records().unwrap();
+            injector.fail_syncs(true);
+
+            // Act
+            let result = checkpoint(&bufmgr);
+
+            // Assert
+            assert!(result.is_err());
+            assert_eq!(log.records().unwrap()[..records.len()], records[..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod checkpointer_test {
+    use super::*;
+    use crate::{
+        buffer::ClockSweepBufferPool,
+        disk::{DiskManager, MemoryStore},
+        wal::{LogManager, LogRecord, MemoryLogStore},
+    };
+    use std::{thread, time::Instant};
+
+    mod start {
+        use super::*;
+
+        #[test]
+        fn 定期的にチェックポイントを取ること() {
+            // Arrange
+            let bufmgr = {
+                let disk = DiskManager::new(MemoryStore::default()).unwrap();
+                let log = LogManager::new(MemoryLogStore::default()).unwrap();
+                let pool = ClockSweepBufferPool::from(4);
+                Arc::new(BufferPoolManager::new(disk, pool).with_log(log))
+            };
+            for _ in 0..4 {
+                bufmgr.create_page().unwrap().fill(b'a');
+            }
+            let options = CheckpointerOptions {
+                interval: Duration::from_millis(1),
+            };
+
+            // Act
+            let checkpointer = Checkpointer::start(bufmgr.clone(), options);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while checkpointer.checkpoints() < 1 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            checkpointer.stop();
+
+            // Assert
+            assert_eq!(bufmgr.flush_all().unwrap(), 0);
+            let records = bufmgr.log().unwrap().records().unwrap();
+            assert_eq!(records[0].1, LogRecord::CheckpointBegin);
+        }
+    }
+}
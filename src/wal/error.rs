@@ -0,0 +1,15 @@
+use super::Lsn;
+use crate::{buffer, disk};
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    Disk(#[from] disk::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("log record {0:?} is corrupted.")]
+    CorruptedRecord(Lsn),
+}
@@ -0,0 +1,525 @@
+use super::{stats::Counters, FileLogStore, LogRecord, LogStats, LogStore, Lsn};
+use crate::disk::PageId;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    io,
+    mem::{self, size_of},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+#[derive(FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+struct LogHeader {
+    magic: [u8; 8],
+    /// LSN of the first byte after the header.
+    base_lsn: u64,
+}
+
+impl LogHeader {
+    const SIZE: u64 = size_of::<Self>() as u64;
+}
+
+/// Prefix of every record in the store. A record whose checksum does not
+/// match was torn by a crash and ends the log.
+#[derive(FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+struct FrameHeader {
+    len: u32,
+    checksum: u32,
+}
+
+impl FrameHeader {
+    const SIZE: usize = size_of::<Self>();
+}
+
+/// Write-ahead log. Appended records are buffered in memory, and only reach
+/// the store once `flush` is asked for an LSN past them.
+pub struct LogManager {
+    store: Mutex<Box<dyn LogStore>>,
+    tail: Mutex<Tail>,
+    flushed_lsn: AtomicU64,
+    /// LSN of the first byte after the header of the store, which grows as
+    /// checkpoints drop old records.
+    base_lsn: AtomicU64,
+    /// Held for the whole of a checkpoint, so that one never drops records
+    /// another still needs.
+    checkpoint: Mutex<()>,
+    /// Nanoseconds a commit waits for others to join its sync.
+    commit_delay: AtomicU64,
+    counters: Counters,
+}
+
+/// Records appended but not flushed yet.
+struct Tail {
+    bytes: Vec<u8>,
+    next_lsn: Lsn,
+    /// Last record of each operation appended since the log was opened and
+    /// not ended yet. Recovery ends every operation it finds in the log.
+    operations: HashMap<Lsn, Lsn>,
+}
+
+impl LogManager {
+    pub const MAGIC: [u8; 8] = *b"LSQL WAL";
+
+    /// Opens the log kept in `store`, starting a new one if it is empty and
+    /// dropping whatever a crash left of a record at its end.
+    pub fn new<T: 'static + LogStore>(store: T) -> io::Result<Self> {
+        let mut store: Box<dyn LogStore> = Box::new(store);
+        if store.size() == 0 {
+            let header = LogHeader {
+                magic: Self::MAGIC,
+                base_lsn: 0,
+            };
+            store.append(header.as_bytes())?;
+            store.sync()?;
+        }
+        let mut header = LogHeader::new_zeroed();
+        if store.size() < LogHeader::SIZE {
+            return Err(not_a_log());
+        }
+        store.read(0, header.as_bytes_mut())?;
+        if header.magic != Self::MAGIC {
+            return Err(not_a_log());
+        }
+        let (_, end) = read_records(&*store, header.base_lsn)?;
+        if end < store.size() {
+            store.truncate(end)?;
+            store.sync()?;
+        }
+        let flushed_lsn = header.base_lsn + end - LogHeader::SIZE;
+        Ok(Self {
+            store: Mutex::new(store),
+            tail: Mutex::new(Tail {
+                bytes: vec![],
+                next_lsn: Lsn::new(flushed_lsn),
+                operations: HashMap::new(),
+            }),
+            flushed_lsn: AtomicU64::new(flushed_lsn),
+            base_lsn: AtomicU64::new(header.base_lsn),
+            checkpoint: Mutex::new(()),
+            commit_delay: AtomicU64::new(0),
+            counters: Counters::default(),
+        })
+    }
+
+    pub fn open(log_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(FileLogStore::open(log_file_path)?)
+    }
+
+    /// Buffers `record` and returns its LSN.
+    pub fn append(&self, record: &LogRecord) -> Lsn {
+        Self::push(&mut self.tail.lock(), record)
+    }
+
+    fn push(tail: &mut Tail, record: &LogRecord) -> Lsn {
+        let payload = record.to_bytes();
+        let header = FrameHeader {
+            len: payload.len() as u32,
+            checksum: crc32c::crc32c(&payload),
+        };
+        tail.bytes.extend_from_slice(header.as_bytes());
+        tail.bytes.extend_from_slice(&payload);
+        tail.next_lsn =
+            Lsn::new(tail.next_lsn.value() + (FrameHeader::SIZE + payload.len()) as u64);
+        let lsn = tail.next_lsn;
+        match record {
+            LogRecord::Begin => {
+                tail.operations.insert(lsn, lsn);
+            }
+            LogRecord::Update { op, .. } | LogRecord::Compensation { op, .. } => {
+                tail.operations.insert(*op, lsn);
+            }
+            LogRecord::End { op } => {
+                tail.operations.remove(op);
+            }
+            LogRecord::CheckpointBegin | LogRecord::CheckpointEnd { .. } => {}
+        }
+        lsn
+    }
+
+    pub fn commit_delay(&self) -> Duration {
+        Duration::from_nanos(self.commit_delay.load(Ordering::Relaxed))
+    }
+
+    /// Makes `commit` wait for `commit_delay` before syncing while other
+    /// operations are running, so that those ending in the meantime share
+    /// the sync. No delay, the default, syncs right away.
+    pub fn set_commit_delay(&self, commit_delay: Duration) {
+        self.commit_delay
+            .store(commit_delay.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> LogStats {
+        self.counters.stats()
+    }
+
+    /// Waits until the record at `lsn`, the end of an operation, is in the
+    /// store. Commits that wait at the same time are made durable by one
+    /// sync: whichever takes the store first flushes the records of the
+    /// others too.
+    pub fn commit(&self, lsn: Lsn) -> io::Result<()> {
+        self.counters.commits.fetch_add(1, Ordering::Relaxed);
+        let commit_delay = self.commit_delay();
+        if !commit_delay.is_zero()
+            && self.flushed_lsn() < lsn
+            && !self.tail.lock().operations.is_empty()
+        {
+            thread::sleep(commit_delay);
+        }
+        self.flush(lsn)
+    }
+
+    /// LSN up to which every record is in the store.
+    pub fn flushed_lsn(&self) -> Lsn {
+        Lsn::new(self.flushed_lsn.load(Ordering::Acquire))
+    }
+
+    /// Writes and syncs the buffered records, if the record at `lsn` is
+    /// among them.
+    pub fn flush(&self, lsn: Lsn) -> io::Result<()> {
+        if self.flushed_lsn() >= lsn {
+            return Ok(());
+        }
+        let mut store = self.store.lock();
+        if self.flushed_lsn() >= lsn {
+            return Ok(());
+        }
+        let (bytes, next_lsn) = {
+            let mut tail = self.tail.lock();
+            (mem::take(&mut tail.bytes), tail.next_lsn)
+        };
+        let size = store.size();
+        if let Err(err) = store.append(&bytes).and_then(|_| store.sync()) {
+            // The records go back in front of those appended meanwhile, to
+            // be written by the next flush.
+            let _ = store.truncate(size);
+            self.tail.lock().bytes.splice(0..0, bytes);
+            return Err(err);
+        }
+        self.flushed_lsn.store(next_lsn.value(), Ordering::Release);
+        self.counters.syncs.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_written
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Writes and syncs every record appended so far.
+    pub fn flush_all(&self) -> io::Result<()> {
+        let next_lsn = self.tail.lock().next_lsn;
+        self.flush(next_lsn)
+    }
+
+    /// Records in the store, in the order they were appended.
+    pub fn records(&self) -> io::Result<Vec<(Lsn, LogRecord)>> {
+        let store = self.store.lock();
+        Ok(read_records(&**store, self.base_lsn.load(Ordering::Acquire))?.0)
+    }
+
+    /// Records a checkpoint and drops the records recovery no longer needs
+    /// once it is in the store. `dirty_pages` is called after the checkpoint
+    /// has begun, and lists the pages not written yet with the first record
+    /// each may be missing on disk. Every other page must be durable by the
+    /// time it returns, as the records that would redo it are dropped.
+    /// Returns the LSN of the checkpoint.
+    pub fn checkpoint<E: From<io::Error>>(
+        &self,
+        dirty_pages: impl FnOnce() -> Result<Vec<(PageId, Lsn)>, E>,
+    ) -> Result<Lsn, E> {
+        let _checkpoint = self.checkpoint.lock();
+        let begin_lsn = self.append(&LogRecord::CheckpointBegin);
+        let dirty_pages = dirty_pages()?;
+        let (end_lsn, keep_lsn) = {
+            let mut tail = self.tail.lock();
+            let operations: Vec<_> = tail
+                .operations
+                .iter()
+                .map(|(&op, &lsn)| (op, lsn))
+                .collect();
+            // Redo needs the records after the checkpoint began and those
+            // missing from the dirty pages, undo every record of the
+            // operations.
+            let keep_lsn = dirty_pages
+                .iter()
+                .map(|&(_, lsn)| lsn)
+                .chain(operations.iter().map(|&(op, _)| op))
+                .fold(begin_lsn, Lsn::min);
+            let record = LogRecord::CheckpointEnd {
+                begin_lsn,
+                dirty_pages,
+                operations,
+            };
+            (Self::push(&mut tail, &record), keep_lsn)
+        };
+        self.flush(end_lsn)?;
+        self.truncate(keep_lsn)?;
+        Ok(end_lsn)
+    }
+
+    /// Drops the records in the store before the one at `lsn`, rewriting it
+    /// with a header that keeps the LSNs of the others.
+    fn truncate(&self, lsn: Lsn) -> io::Result<()> {
+        let mut store = self.store.lock();
+        let base_lsn = self.base_lsn.load(Ordering::Acquire);
+        let (records, _) = read_records(&**store, base_lsn)?;
+        let Some(new_base_lsn) = records
+            .iter()
+            .map(|&(record_lsn, _)| record_lsn)
+            .take_while(|&record_lsn| record_lsn < lsn)
+            .last()
+        else {
+            return Ok(());
+        };
+        let header = LogHeader {
+            magic: Self::MAGIC,
+            base_lsn: new_base_lsn.value(),
+        };
+        let offset = LogHeader::SIZE + new_base_lsn.value() - base_lsn;
+        let mut data = header.as_bytes().to_vec();
+        data.resize((LogHeader::SIZE + store.size() - offset) as usize, 0);
+        store.read(offset, &mut data[LogHeader::SIZE as usize..])?;
+        store.replace(&data)?;
+        self.base_lsn.store(new_base_lsn.value(), Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Reads the records up to the first torn one, and returns them with the
+/// offset that record starts at.
+fn read_records(store: &dyn LogStore, base_lsn: u64) -> io::Result<(Vec<(Lsn, LogRecord)>, u64)> {
+    let mut bytes = vec![0; (store.size() - LogHeader::SIZE) as usize];
+    store.read(LogHeader::SIZE, &mut bytes)?;
+    let mut records = vec![];
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + FrameHeader::SIZE) {
+        let header = FrameHeader::read_from(header).unwrap();
+        let start = offset + FrameHeader::SIZE;
+        let Some(payload) = bytes.get(start..start + header.len as usize) else {
+            break;
+        };
+        if crc32c::crc32c(payload) != header.checksum {
+            break;
+        }
+        let Some(record) = LogRecord::from_bytes(payload) else {
+            break;
+        };
+        offset = start + payload.len();
+        records.push((Lsn::new(base_lsn + offset as u64), record));
+    }
+    Ok((records, LogHeader::SIZE + offset as u64))
+}
+
+fn not_a_log() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a lightsql log file")
+}
+
+#[cfg(test)]
+mod log_manager_test {
+    use super::*;
+    use crate::wal::MemoryLogStore;
+
+    fn update(page_id: u64) -> LogRecord {
+        LogRecord::Update {
+            op: Lsn::ZERO,
+            prev_lsn: Lsn::ZERO,
+            page_id: PageId::new(page_id),
+            change: vec![1, 2, 3],
+        }
+    }
+
+    mod commit {
+        use super::*;
+        use std::sync::Barrier;
+
+        #[test]
+        fn 同時に終わった操作の確定を同期一回にまとめること() {
+            // Arrange
+            let log = LogManager::new(MemoryLogStore::default()).unwrap();
+            log.set_commit_delay(Duration::from_millis(50));
+            let barrier = Barrier::new(8);
+
+            // Act
+            thread::scope(|scope| {
+                for _ in 0..8 {
+                    scope.spawn(|| {
+                        let op = log.append(&LogRecord::Begin);
+                        barrier.wait();
+                        let lsn = log.append(&LogRecord::End { op });
+                        log.commit(lsn).unwrap();
+                        assert!(log.flushed_lsn() >= lsn);
+                    });
+                }
+            });
+
+            // Assert
+            let stats = log.stats();
+            assert_eq!(stats.commits, 8);
+            assert!(stats.commits_per_sync() >= 2.0);
+        }
+    }
+
+    mod flush {
+        use super::*;
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 指定したLSNまでのレコードを書き込むこと() {
+            // Arrange
+            let store = MemoryLogStore::default();
+            let log = LogManager::new(store.clone()).unwrap();
+            let lsn = log.append(&update(1));
+
+            // Act
+            log.flush(lsn).unwrap();
+
+            // Assert
+            assert_eq!(log.flushed_lsn(), lsn);
+            let log = LogManager::new(store).unwrap();
+            assert_eq!(log.records().unwrap(), vec![(lsn, update(1))]);
+        }
+
+        #[test]
+        fn 書き込んでいないレコードは開き直すと失われること() {
+            // Arrange
+            let store = MemoryLogStore::default();
+            let log = LogManager::new(store.clone()).unwrap();
+            let lsn = log.append(&update(1));
+            log.flush(lsn).unwrap();
+            log.append(&update(2));
+
+            // Act
+            drop(log);
+            let log = LogManager::new(store).unwrap();
+
+            // Assert
+            assert_eq!(log.records().unwrap(), vec![(lsn, update(1))]);
+        }
+    }
+
+    mod checkpoint {
+        use super::*;
+
+        fn update_of(op: Lsn, page_id: u64) -> LogRecord {
+            LogRecord::Update {
+                op,
+                prev_lsn: op,
+                page_id: PageId::new(page_id),
+                change: vec![1, 2, 3],
+            }
+        }
+
+        #[test]
+        fn 終わった操作のレコードを捨てること() {
+            // Arrange
+            let store = MemoryLogStore::default();
+            let log = LogManager::new(store.clone()).unwrap();
+            let op = log.append(&LogRecord::Begin);
+            log.append(&update_of(op, 1));
+            log.append(&LogRecord::End { op });
+
+            // Act
+            let checkpoint_lsn = log.checkpoint(|| io::Result::Ok(Vec::new())).unwrap();
+
+            // Assert
+            let records = LogManager::new(store).unwrap().records().unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].1, LogRecord::CheckpointBegin);
+            assert_eq!(records[1].0, checkpoint_lsn);
+        }
+
+        #[test]
+        fn 終わっていない操作のレコードを残すこと() {
+            // Arrange
+            let store = MemoryLogStore::default();
+            let log = LogManager::new(store.clone()).unwrap();
+            let ended_op = log.append(&LogRecord::Begin);
+            log.append(&update_of(ended_op, 1));
+            let op = log.append(&LogRecord::Begin);
+            let lsn = log.append(&update_of(op, 2));
+            log.append(&LogRecord::End { op: ended_op });
+
+            // Act
+            log.checkpoint(|| io::Result::Ok(Vec::new())).unwrap();
+
+            // Assert
+            let records = LogManager::new(store).unwrap().records().unwrap();
+            assert_eq!(records[0], (op, LogRecord::Begin));
+            assert_eq!(records[1], (lsn, update_of(op, 2)));
+            assert_eq!(
+                records[4].1,
+                LogRecord::CheckpointEnd {
+                    begin_lsn: records[3].0,
+                    dirty_pages: vec![],
+                    operations: vec![(op, lsn)],
+                }
+            );
+        }
+
+        #[allow(non_snake_case)]
+        #[test]
+        fn 捨てたレコードの後もLSNが続くこと() {
+            // Arrange
+            let store = MemoryLogStore::default();
+            let log = LogManager::new(store.clone()).unwrap();
+            let op = log.append(&LogRecord::Begin);
+            log.append(&LogRecord::End { op });
+            let checkpoint_lsn = log.checkpoint(|| io::Result::Ok(Vec::new())).unwrap();
+            drop(log);
+
+            // Act
+            let log = LogManager::new(store).unwrap();
+            let lsn = log.append(&update(2));
+            log.flush_all().unwrap();
+
+            // Assert
+            assert!(lsn > checkpoint_lsn);
+            assert_eq!(log.records().unwrap().last(), Some(&(lsn, update(2))));
+        }
+    }
+
+    mod new {
+        use super::*;
+
+        #[test]
+        fn 途中で途切れたレコードを切り捨てて続きから追記すること() {
+            // Arrange
+            let mut store = MemoryLogStore::default();
+            let lsn = {
+                let log = LogManager::new(store.clone()).unwrap();
+                let lsn = log.append(&update(1));
+                log.flush_all().unwrap();
+                lsn
+            };
+            store.append(&[8, 0, 0, 0, 1, 2]).unwrap();
+
+            // Act
+            let log = LogManager::new(store).unwrap();
+            let next_lsn = log.append(&update(2));
+            log.flush_all().unwrap();
+
+            // Assert
+            assert_eq!(
+                log.records().unwrap(),
+                vec![(lsn, update(1)), (next_lsn, update(2))]
+            );
+        }
+
+        #[test]
+        fn ログでないファイルの場合エラーを返すこと() {
+            // Arrange
+            let mut store = MemoryLogStore::default();
+            store.append(&[b'x'; 32]).unwrap();
+
+            // Act
+            let result = LogManager::new(store);
+
+            // Assert
+            assert!(result.is_err());
+        }
+    }
+}
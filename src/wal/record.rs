@@ -0,0 +1,81 @@
+use crate::disk::{PageHeader, PageId};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use zerocopy::FromBytes;
+
+/// Position in the log just past the end of a record. Later records have
+/// greater LSNs, and 0 comes before every record.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
+pub struct Lsn(u64);
+
+impl Lsn {
+    pub const ZERO: Lsn = Lsn(0);
+
+    pub const fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// LSN in the header of a page image.
+pub fn page_lsn(page: &[u8]) -> Lsn {
+    Lsn::new(PageHeader::ref_from_prefix(page).unwrap().lsn)
+}
+
+pub fn set_page_lsn(page: &mut [u8], lsn: Lsn) {
+    PageHeader::mut_from_prefix(page).unwrap().lsn = lsn.value();
+}
+
+/// Entry of the log. Changes are grouped into operations, each of which is
+/// identified by the LSN of its `Begin` record and either ends with an `End`
+/// record or is undone by recovery.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LogRecord {
+    Begin,
+    /// Change to the body of a page, in a form only the B-tree interprets.
+    Update {
+        op: Lsn,
+        /// Previous record of the same operation.
+        prev_lsn: Lsn,
+        page_id: PageId,
+        change: Vec<u8>,
+    },
+    /// Undo of an `Update`, recorded as the change it undid so that redo
+    /// undoes it again. Undo continues from `undo_next_lsn`, so that an
+    /// update is never undone twice.
+    Compensation {
+        op: Lsn,
+        page_id: PageId,
+        change: Vec<u8>,
+        undo_next_lsn: Lsn,
+    },
+    End {
+        op: Lsn,
+    },
+    CheckpointBegin,
+    /// State at the end of a checkpoint, from which recovery starts instead
+    /// of the start of the log.
+    CheckpointEnd {
+        begin_lsn: Lsn,
+        /// Pages that were dirty, each with the first record that may be
+        /// missing from it on disk.
+        dirty_pages: Vec<(PageId, Lsn)>,
+        /// Operations that had not ended, each with its last record.
+        operations: Vec<(Lsn, Lsn)>,
+    },
+}
+
+impl LogRecord {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::options().serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bincode::options().deserialize(bytes).ok()
+    }
+}
@@ -0,0 +1,294 @@
+use super::{checkpoint, Error, LogManager, LogRecord, Lsn};
+use crate::{
+    btree::log::{self, Change},
+    buffer::{BufferPool, BufferPoolManager},
+    disk::{DiskManager, PageId},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Opens the database in `heap_file_path` with its log, which is kept next
+/// to it under the same name with a `-wal` suffix, and recovers it.
+pub fn open<T: 'static + BufferPool>(
+    heap_file_path: impl AsRef<Path>,
+    pool: T,
+) -> Result<BufferPoolManager, Error> {
+    let heap_file_path = heap_file_path.as_ref();
+    let disk = DiskManager::open(heap_file_path)?;
+    let log = LogManager::open(log_file_path(heap_file_path))?;
+    let bufmgr = BufferPoolManager::new(disk, pool).with_log(log);
+    recover(&bufmgr)?;
+    Ok(bufmgr)
+}
+
+pub fn log_file_path(heap_file_path: impl AsRef<Path>) -> PathBuf {
+    let mut log_file_path = heap_file_path.as_ref().as_os_str().to_owned();
+    log_file_path.push("-wal");
+    log_file_path.into()
+}
+
+/// Brings the pages of `bufmgr` to the state its log leaves them in,
+/// starting from the last checkpoint. Every logged change missing from a
+/// page is redone, then the changes of the operations that never ended are
+/// undone, and a checkpoint is taken. Pages those operations allocated stay
+/// allocated, as allocation is not logged. Does nothing if the manager has no
+/// log.
+pub fn recover(bufmgr: &BufferPoolManager) -> Result<(), Error> {
+    let Some(log) = bufmgr.log() else {
+        return Ok(());
+    };
+    let records = log.records()?;
+    let (checkpoint_lsn, begin_lsn, dirty_pages, mut last_lsns) = records
+        .iter()
+        .rev()
+        .find_map(|(lsn, record)| match record {
+            LogRecord::CheckpointEnd {
+                begin_lsn,
+                dirty_pages,
+                operations,
+            } => Some((
+                *lsn,
+                *begin_lsn,
+                dirty_pages.iter().copied().collect::<HashMap<_, _>>(),
+                operations.iter().copied().collect::<HashMap<_, _>>(),
+            )),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    // Last record of every operation that has not ended, from those running
+    // at the checkpoint on.
+    for (lsn, record) in records.iter().filter(|(lsn, _)| *lsn > checkpoint_lsn) {
+        match record {
+            LogRecord::Begin => {
+                last_lsns.insert(*lsn, *lsn);
+            }
+            LogRecord::Update { op, .. } | LogRecord::Compensation { op, .. } => {
+                last_lsns.insert(*op, *lsn);
+            }
+            LogRecord::End { op } => {
+                last_lsns.remove(op);
+            }
+            LogRecord::CheckpointBegin | LogRecord::CheckpointEnd { .. } => {}
+        }
+    }
+
+    // Changes from before the checkpoint began can only be missing from the
+    // pages that were dirty then.
+    for (lsn, record) in &records {
+        let may_be_missing = |page_id: &PageId| {
+            *lsn > begin_lsn
+                || dirty_pages
+                    .get(page_id)
+                    .is_some_and(|rec_lsn| rec_lsn <= lsn)
+        };
+        match record {
+            LogRecord::Update {
+                page_id, change, ..
+            } if may_be_missing(page_id) => {
+                let change = decode(*lsn, change)?;
+                redo(bufmgr, *lsn, *page_id, |body| change.redo(body))?;
+            }
+            LogRecord::Compensation {
+                page_id, change, ..
+            } if may_be_missing(page_id) => {
+                let change = decode(*lsn, change)?;
+                redo(bufmgr, *lsn, *page_id, |body| change.undo(body))?;
+            }
+            _ => {}
+        }
+    }
+
+    let records: HashMap<_, _> = records.into_iter().collect();
+    // The next record to look at for each operation, latest first.
+    let mut undo_next: BTreeMap<_, _> = last_lsns.into_iter().map(|(op, lsn)| (lsn, op)).collect();
+    while let Some((lsn, op)) = undo_next.pop_last() {
+        let next_lsn = match &records[&lsn] {
+            LogRecord::Begin => {
+                log.append(&LogRecord::End { op });
+                continue;
+            }
+            LogRecord::Update {
+                prev_lsn,
+                page_id,
+                change,
+                ..
+            } => {
+                let change = decode(lsn, change)?;
+                let mut guard = bufmgr.fetch_page_write(*page_id)?;
+                log::undo(log, op, &mut guard, &change, *prev_lsn);
+                *prev_lsn
+            }
+            LogRecord::Compensation { undo_next_lsn, .. } => *undo_next_lsn,
+            LogRecord::End { .. }
+            | LogRecord::CheckpointBegin
+            | LogRecord::CheckpointEnd { .. } => {
+                unreachable!("only records of running operations are undone")
+            }
+        };
+        undo_next.insert(next_lsn, op);
+    }
+    checkpoint(bufmgr)
+}
+
+/// Applies the change at `lsn` to the page unless the page already has it.
+fn redo(
+    bufmgr: &BufferPoolManager,
+    lsn: Lsn,
+    page_id: PageId,
+    apply: impl FnOnce(&mut [u8]),
+) -> Result<(), Error> {
+    bufmgr.allocate_up_to(page_id)?;
+    let mut guard = bufmgr.fetch_page_write(page_id)?;
+    if guard.lsn() < lsn {
+        apply(&mut guard);
+        guard.set_lsn(lsn);
+    }
+    Ok(())
+}
+
+fn decode(lsn: Lsn, change: &[u8]) -> Result<Change, Error> {
+    Change::from_bytes(change).ok_or(Error::CorruptedRecord(lsn))
+}
+
+#[cfg(test)]
+mod recovery_test {
+    use super::*;
+    use crate::{
+        btree::{BTree, SearchMode},
+        buffer::ClockSweepBufferPool,
+        disk::MemoryStore,
+        transaction::{Snapshot, Transaction},
+        wal::MemoryLogStore,
+    };
+    use std::{mem, ops::Range};
+
+    fn reopen(store: &MemoryStore, log_store: &MemoryLogStore) -> BufferPoolManager {
+        let disk = DiskManager::new(store.clone()).unwrap();
+        let log = LogManager::new(log_store.clone()).unwrap();
+        BufferPoolManager::new(disk, ClockSweepBufferPool::from(16)).with_log(log)
+    }
+
+    fn create(bufmgr: &BufferPoolManager) -> BTree {
+        let mut txn = Transaction::begin(bufmgr).unwrap();
+        let btree = BTree::create(bufmgr, &mut txn).unwrap();
+        txn.commit().unwrap();
+        btree
+    }
+
+    fn insert(btree: &BTree, bufmgr: &BufferPoolManager, keys: Range<u32>) {
+        let mut txn = Transaction::begin(bufmgr).unwrap();
+        for i in keys {
+            btree
+                .insert(bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                .unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    fn keys(btree: &BTree, bufmgr: &BufferPoolManager) -> Vec<u32> {
+        let snapshot = Snapshot::take(bufmgr);
+        let mut keys = vec![];
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        while let Some((key, _)) = iter.next(bufmgr, &snapshot).unwrap() {
+            keys.push(u32::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    mod recover {
+        use super::*;
+
+        #[test]
+        fn ログだけ書き込まれた挿入をやり直すこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = reopen(&store, &log_store);
+                let btree = create(&bufmgr);
+                insert(&btree, &bufmgr, 0..500);
+                btree
+            };
+            let bufmgr = reopen(&store, &log_store);
+
+            // Act
+            recover(&bufmgr).unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), (0u32..500).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn チェックポイントの後の挿入をやり直すこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = reopen(&store, &log_store);
+                let btree = create(&bufmgr);
+                insert(&btree, &bufmgr, 0..250);
+                checkpoint(&bufmgr).unwrap();
+                insert(&btree, &bufmgr, 250..500);
+                btree
+            };
+            let bufmgr = reopen(&store, &log_store);
+
+            // Act
+            recover(&bufmgr).unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), (0u32..500).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn チェックポイントでダーティだったページの変更をやり直すこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = reopen(&store, &log_store);
+                let btree = create(&bufmgr);
+                insert(&btree, &bufmgr, 0..10);
+                let log = bufmgr.log().unwrap();
+                log.checkpoint(|| std::io::Result::Ok(bufmgr.dirty_pages()))
+                    .unwrap();
+                btree
+            };
+            let bufmgr = reopen(&store, &log_store);
+
+            // Act
+            recover(&bufmgr).unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), (0u32..10).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn 確定していないトランザクションの挿入を取り消すこと() {
+            // Arrange
+            let (store, log_store) = (MemoryStore::default(), MemoryLogStore::default());
+            let btree = {
+                let bufmgr = reopen(&store, &log_store);
+                let btree = create(&bufmgr);
+                insert(&btree, &bufmgr, 0..10);
+                let mut txn = Transaction::begin(&bufmgr).unwrap();
+                for i in 10u32..200 {
+                    btree
+                        .insert(&bufmgr, &mut txn, &i.to_be_bytes(), &[b'a'; 100])
+                        .unwrap();
+                }
+                bufmgr.flush_all().unwrap();
+                // Crashes before the transaction ends.
+                mem::forget(txn);
+                btree
+            };
+            let bufmgr = reopen(&store, &log_store);
+
+            // Act
+            recover(&bufmgr).unwrap();
+
+            // Assert
+            assert_eq!(keys(&btree, &bufmgr), (0u32..10).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Running totals of a `LogManager` since it was opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LogStats {
+    /// Commits that waited for their records to be durable.
+    pub commits: u64,
+    /// Syncs of the store, each making every record appended before it
+    /// durable.
+    pub syncs: u64,
+    /// Bytes of records written to the store.
+    pub bytes_written: u64,
+}
+
+impl LogStats {
+    /// Commits made durable per sync of the store, or 0.0 before any sync.
+    pub fn commits_per_sync(&self) -> f64 {
+        if self.syncs == 0 {
+            0.0
+        } else {
+            self.commits as f64 / self.syncs as f64
+        }
+    }
+}
+
+/// The counters behind `LogStats`, bumped by the threads using the log.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub commits: AtomicU64,
+    pub syncs: AtomicU64,
+    pub bytes_written: AtomicU64,
+}
+
+impl Counters {
+    pub fn stats(&self) -> LogStats {
+        LogStats {
+            commits: self.commits.load(Ordering::Relaxed),
+            syncs: self.syncs.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod log_stats_test {
+    use super::*;
+
+    mod commits_per_sync {
+        use super::*;
+
+        #[test]
+        fn 同期一回あたりの確定の数を返すこと() {
+            // Arrange
+            let stats = LogStats {
+                commits: 6,
+                syncs: 4,
+                ..Default::default()
+            };
+
+            // Act
+            let actual = stats.commits_per_sync();
+
+            // Assert
+            assert_eq!(actual, 1.5);
+        }
+
+        #[test]
+        fn 一度も同期していない場合0を返すこと() {
+            assert_eq!(LogStats::default().commits_per_sync(), 0.0);
+        }
+    }
+}
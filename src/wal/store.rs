@@ -0,0 +1,20 @@
+use std::io;
+
+mod file;
+mod memory;
+pub use file::*;
+pub use memory::*;
+
+/// Append-only storage of the log, read back in full by recovery.
+pub trait LogStore: Send + Sync {
+    /// Bytes stored so far.
+    fn size(&self) -> u64;
+    fn read(&self, offset: u64, data: &mut [u8]) -> io::Result<()>;
+    fn append(&mut self, data: &[u8]) -> io::Result<()>;
+    /// Drops every byte from `size` on.
+    fn truncate(&mut self, size: u64) -> io::Result<()>;
+    fn sync(&mut self) -> io::Result<()>;
+    /// Replaces every byte with `data`, durably, so that a crash leaves
+    /// either the old bytes or the new ones.
+    fn replace(&mut self, data: &[u8]) -> io::Result<()>;
+}
@@ -0,0 +1,114 @@
+use super::LogStore;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+pub struct FileLogStore {
+    log_file: File,
+    log_file_size: u64,
+    /// Where `replace` renames the file it writes to.
+    log_file_path: PathBuf,
+}
+
+impl FileLogStore {
+    pub fn open(log_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let log_file_path = log_file_path.as_ref().to_path_buf();
+        let log_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&log_file_path)?;
+        let log_file_size = log_file.metadata()?.len();
+        Ok(Self {
+            log_file,
+            log_file_size,
+            log_file_path,
+        })
+    }
+}
+
+impl LogStore for FileLogStore {
+    fn size(&self) -> u64 {
+        self.log_file_size
+    }
+
+    fn read(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        self.log_file.read_exact_at(data, offset)
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        self.log_file.write_all_at(data, self.log_file_size)?;
+        self.log_file_size += data.len() as u64;
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.log_file.set_len(size)?;
+        self.log_file_size = size;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.log_file.sync_data()
+    }
+
+    /// Writes `data` to a new file and renames it over the log.
+    fn replace(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut new_file_path = self.log_file_path.clone().into_os_string();
+        new_file_path.push(".new");
+        let mut new_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&new_file_path)?;
+        new_file.write_all(data)?;
+        new_file.sync_all()?;
+        fs::rename(&new_file_path, &self.log_file_path)?;
+        let dir = match self.log_file_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+        self.log_file = new_file;
+        self.log_file_size = data.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod file_log_store_test {
+    use super::*;
+    use std::fs::remove_file;
+
+    mod replace {
+        use super::*;
+
+        #[test]
+        fn 置き換えた内容に続けて追記できること() {
+            // Arrange
+            let file_path = "file_log_store_test::replace::0.txt";
+            let mut store = FileLogStore::open(file_path).unwrap();
+            store.append(b"old").unwrap();
+
+            // Act
+            store.replace(b"new").unwrap();
+            store.append(b"!").unwrap();
+            store.sync().unwrap();
+
+            // Assert
+            let store = FileLogStore::open(file_path).unwrap();
+            let mut data = [0u8; 4];
+            store.read(0, &mut data).unwrap();
+            assert_eq!(store.size(), 4);
+            assert_eq!(&data, b"new!");
+
+            // Cleanup
+            remove_file(file_path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::LogStore;
+use parking_lot::Mutex;
+use std::{io, sync::Arc};
+
+/// Keeps the log in memory. Clones share the same bytes, so a test can
+/// recover from a clone after dropping the `LogManager` that wrote it.
+#[derive(Clone, Default)]
+pub struct MemoryLogStore {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl LogStore for MemoryLogStore {
+    fn size(&self) -> u64 {
+        self.bytes.lock().len() as u64
+    }
+
+    fn read(&self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        let bytes = self.bytes.lock();
+        let offset = offset as usize;
+        let src = bytes
+            .get(offset..offset + data.len())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        data.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        self.bytes.lock().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.bytes.lock().truncate(size as usize);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn replace(&mut self, data: &[u8]) -> io::Result<()> {
+        *self.bytes.lock() = data.to_vec();
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Thread that runs a task every `interval` until it is stopped or dropped.
+/// Stopping wakes the thread up at once, and waits for a run in progress to
+/// finish.
+pub struct PeriodicWorker {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    is_stopped: Mutex<bool>,
+    wakeup: Condvar,
+}
+
+impl PeriodicWorker {
+    pub fn start(interval: Duration, mut task: impl FnMut() + Send + 'static) -> Self {
+        let shared = Arc::new(Shared {
+            is_stopped: Mutex::new(false),
+            wakeup: Condvar::new(),
+        });
+        let handle = thread::spawn({
+            let shared = shared.clone();
+            move || {
+                let mut is_stopped = shared.is_stopped.lock();
+                while !*is_stopped {
+                    shared.wakeup.wait_for(&mut is_stopped, interval);
+                    if *is_stopped {
+                        break;
+                    }
+                    MutexGuard::unlocked(&mut is_stopped, &mut task);
+                }
+            }
+        });
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        *self.shared.is_stopped.lock() = true;
+        self.shared.wakeup.notify_one();
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+impl Drop for PeriodicWorker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod periodic_worker_test {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::Instant,
+    };
+
+    mod stop {
+        use super::*;
+
+        #[test]
+        fn 止めた後は処理を実行しないこと() {
+            // Arrange
+            let runs = Arc::new(AtomicU64::new(0));
+            let worker = PeriodicWorker::start(Duration::from_millis(1), {
+                let runs = runs.clone();
+                move || {
+                    runs.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while runs.load(Ordering::Relaxed) < 3 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            // Act
+            worker.stop();
+
+            // Assert
+            let stopped_runs = runs.load(Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(10));
+            assert!(stopped_runs >= 3);
+            assert_eq!(runs.load(Ordering::Relaxed), stopped_runs);
+        }
+
+        #[test]
+        fn 間隔の途中でもすぐに止まること() {
+            // Arrange
+            let worker = PeriodicWorker::start(Duration::from_secs(3600), || {});
+            let started_at = Instant::now();
+
+            // Act
+            worker.stop();
+
+            // Assert
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+        }
+    }
+}